@@ -0,0 +1,262 @@
+// Runs the toolbox pipeline over a whole folder in the background
+use dioxus::prelude::*;
+use rfd::FileDialog;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::core::batch::{
+    folder_images, run_batch, BatchJob, FileOutcome, FileStatus, DEFAULT_NAMING, REPORT_FILE,
+};
+use crate::core::encode::ImageFormat;
+use crate::core::pipeline::Pipeline;
+
+// Folder the outputs go to when none is chosen, inside the input folder
+const DEFAULT_OUT_SUBDIR: &str = "processed";
+const MAX_WORKERS: usize = 64;
+
+fn default_workers() -> usize {
+    std::thread::available_parallelism().map_or(4, |n| n.get())
+}
+
+/// Batch job settings, progress bar and per-file error report.
+///
+/// `input_dir` is the folder suggested as input, usually the one the toolbox
+/// image was picked from.
+#[component]
+pub fn BatchPanel(pipeline: Pipeline, input_dir: Option<PathBuf>) -> Element {
+    let mut chosen_input = use_signal(|| None::<PathBuf>);
+    let mut chosen_output = use_signal(|| None::<PathBuf>);
+    let mut naming = use_signal(|| DEFAULT_NAMING.to_string());
+    let mut format = use_signal(|| ImageFormat::Png);
+    let mut workers = use_signal(default_workers);
+
+    let mut cancel_flag = use_signal(|| None::<Arc<AtomicBool>>);
+    let mut progress = use_signal(|| (0usize, 0usize));
+    let mut failures = use_signal(Vec::<FileOutcome>::new);
+    let mut summary = use_signal(|| None::<String>);
+
+    let input = chosen_input().or(input_dir);
+    let output = chosen_output().or_else(|| input.as_ref().map(|dir| dir.join(DEFAULT_OUT_SUBDIR)));
+    let running = cancel_flag.read().is_some();
+    let step_count = pipeline.active_filters().count();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let pick_input = move |_| {
+        spawn(async move {
+            if let Some(dir) = FileDialog::new().pick_folder() {
+                chosen_input.set(Some(dir));
+            }
+        });
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let pick_output = move |_| {
+        spawn(async move {
+            if let Some(dir) = FileDialog::new().pick_folder() {
+                chosen_output.set(Some(dir));
+            }
+        });
+    };
+
+    let start = {
+        let input = input.clone();
+        let output = output.clone();
+        move |_| {
+            let (Some(input), Some(out_dir)) = (input.clone(), output.clone()) else {
+                return;
+            };
+            let inputs = match folder_images(&input) {
+                Ok(inputs) if !inputs.is_empty() => inputs,
+                Ok(_) => {
+                    summary.set(Some(format!("No images in {}", input.display())));
+                    return;
+                }
+                Err(e) => {
+                    summary.set(Some(format!("{}: {}", input.display(), e)));
+                    return;
+                }
+            };
+            let job = BatchJob {
+                pipeline: pipeline.clone(),
+                out_dir,
+                naming: naming(),
+                format: format(),
+            };
+            if let Err(e) = job.validate_for(&input) {
+                summary.set(Some(e));
+                return;
+            }
+
+            let cancel = Arc::new(AtomicBool::new(false));
+            cancel_flag.set(Some(cancel.clone()));
+            progress.set((0, inputs.len()));
+            failures.set(vec![]);
+            summary.set(None);
+            let out_dir = job.out_dir.clone();
+            spawn(async move {
+                let result = run_batch(job, inputs, workers(), cancel, |outcome| {
+                    progress.with_mut(|p| p.0 += 1);
+                    if let FileStatus::Failed(e) = &outcome.status {
+                        eprintln!("❌ {}: {}", outcome.input.display(), e);
+                        failures.write().push(outcome.clone());
+                    }
+                })
+                .await;
+                cancel_flag.set(None);
+                match result {
+                    Ok(outcomes) => {
+                        let count = |f: fn(&FileStatus) -> bool| {
+                            outcomes.iter().filter(|o| f(&o.status)).count()
+                        };
+                        let written = count(|s| matches!(s, FileStatus::Written(_)));
+                        let failed = count(|s| matches!(s, FileStatus::Failed(_)));
+                        let cancelled = count(|s| matches!(s, FileStatus::Cancelled));
+                        let text = format!(
+                            "{} written, {} failed, {} cancelled; report in {}",
+                            written,
+                            failed,
+                            cancelled,
+                            out_dir.join(REPORT_FILE).display()
+                        );
+                        println!("🗂️  Batch done: {}", text);
+                        summary.set(Some(text));
+                    }
+                    Err(e) => {
+                        eprintln!("Batch failed: {}", e);
+                        summary.set(Some(format!("Batch failed: {}", e)));
+                    }
+                }
+            });
+        }
+    };
+
+    let cancel = move |_| {
+        if let Some(flag) = cancel_flag.read().as_ref() {
+            flag.store(true, Ordering::Relaxed);
+        }
+    };
+
+    let (done, total) = progress();
+    let percent = if total == 0 {
+        0.0
+    } else {
+        done as f64 * 100.0 / total as f64
+    };
+    let input_label = input
+        .as_ref()
+        .map_or("none".to_string(), |p| p.display().to_string());
+    let output_label = output
+        .as_ref()
+        .map_or("none".to_string(), |p| p.display().to_string());
+
+    rsx! {
+        div { class: "flex flex-col gap-2 mt-4 p-3 border rounded",
+            span { class: "font-semibold", "Batch: apply the pipeline ({step_count} active steps) to a folder" }
+            div { class: "flex items-center gap-2 text-sm",
+                button {
+                    disabled: running,
+                    onclick: pick_input,
+                    class: "px-2 py-1 bg-gray-600 text-white rounded",
+                    "Input folder…"
+                }
+                span { class: "font-mono", "{input_label}" }
+            }
+            div { class: "flex items-center gap-2 text-sm",
+                button {
+                    disabled: running,
+                    onclick: pick_output,
+                    class: "px-2 py-1 bg-gray-600 text-white rounded",
+                    "Output folder…"
+                }
+                span { class: "font-mono", "{output_label}" }
+            }
+            div { class: "flex items-center gap-4 text-sm",
+                label { class: "flex items-center gap-1",
+                    "Name"
+                    input {
+                        r#type: "text",
+                        value: "{naming}",
+                        title: "{{stem}} is the input file name, {{n}} its number in the batch",
+                        class: "border rounded px-2 py-1 w-48 font-mono",
+                        oninput: move |evt| naming.set(evt.value()),
+                    }
+                }
+                label { class: "flex items-center gap-1",
+                    "Format"
+                    select {
+                        class: "border rounded px-2 py-1",
+                        onchange: move |evt| {
+                            if let Some(f) = ImageFormat::from_name(&evt.value()) {
+                                format.set(f);
+                            }
+                        },
+                        for f in ImageFormat::ALL {
+                            option { value: f.name(), selected: f == format(), "{f.name()}" }
+                        }
+                    }
+                }
+                label { class: "flex items-center gap-1",
+                    "Workers"
+                    input {
+                        r#type: "number",
+                        min: "1",
+                        max: "{MAX_WORKERS}",
+                        value: "{workers}",
+                        class: "border rounded px-2 py-1 w-16",
+                        oninput: move |evt| {
+                            if let Ok(val) = evt.value().parse::<usize>() {
+                                workers.set(val.clamp(1, MAX_WORKERS));
+                            }
+                        }
+                    }
+                }
+            }
+            div { class: "flex items-center gap-2",
+                button {
+                    disabled: running || input.is_none(),
+                    onclick: start,
+                    class: "px-4 py-2 bg-indigo-600 text-white rounded",
+                    "Run batch"
+                }
+                button {
+                    disabled: !running,
+                    onclick: cancel,
+                    class: "px-4 py-2 bg-red-600 text-white rounded",
+                    "Cancel"
+                }
+                if total > 0 {
+                    div { class: "flex-1 h-3 bg-gray-200 rounded overflow-hidden",
+                        div { class: "h-full bg-indigo-500", style: "width: {percent:.1}%;" }
+                    }
+                    span { class: "text-sm font-mono", "{done}/{total}" }
+                }
+            }
+            if let Some(text) = summary() {
+                div { class: "text-sm", "{text}" }
+            }
+            if !failures.read().is_empty() {
+                table { class: "text-sm border-collapse",
+                    thead {
+                        tr {
+                            th { class: "border px-2 text-left", "File" }
+                            th { class: "border px-2 text-left", "Error" }
+                        }
+                    }
+                    tbody {
+                        for outcome in failures.read().iter() {
+                            tr {
+                                td { class: "border px-2 font-mono", "{outcome.input.display()}" }
+                                td { class: "border px-2 text-red-600",
+                                    if let FileStatus::Failed(e) = &outcome.status {
+                                        "{e}"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// Command-line access to the toolbox operations and ROI pixel export, so
+// scripts and CI can reproduce exactly what the GUI produces
+//
+//   imgtool apply <op>[,<op>...] <input>... [-o <dir>]
+//   imgtool apply <recipe.toml|recipe.json> <input>... [-o <dir>]
+//   imgtool roi-pixels <input>... [-o <dir>] [--space <space>]
+//
+// Inputs are image files, folders (their png/jpg/jpeg/webp files) or glob
+// patterns such as 'data/**/*.png'.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use image_upload_demo::core::batch::{claim_outputs, folder_images, is_image};
+use image_upload_demo::core::color_model::{to_color_space, ChannelOrder, ColorSpace};
+use image_upload_demo::core::filters::{decode_image, encode_png, Filter};
+use image_upload_demo::core::pipeline::Pipeline;
+use image_upload_demo::core::pixel_export::export_roi_pixels;
+use image_upload_demo::core::recipe::load_recipe;
+use image_upload_demo::core::roi_sidecar::{load_rois, save_rois};
+use opencv::prelude::*;
+
+const DEFAULT_OUT_DIR: &str = "imgtool_out";
+
+const USAGE: &str = "\
+Usage:
+  imgtool apply <op>[,<op>...] <input>... [-o <dir>]
+  imgtool apply <recipe.toml|recipe.json> <input>... [-o <dir>]
+  imgtool roi-pixels <input>... [-o <dir>] [--space <space>]
+
+Inputs are image files, folders or glob patterns (quote them).
+
+Operations, applied left to right, with the GUI's defaults:
+  blur[=<ksize>]            box blur, default 15
+  resize[=<w>x<h>]          default 200x200
+  grayscale
+  invert
+  canny[=<low>:<high>[:<aperture>]]
+                            default 100:200:3, aperture is 3, 5 or 7
+  crop[=<x>:<y>:<w>:<h>]    default 50:50:100:100, clipped to the image
+  rotate                    90 degrees clockwise
+  rotate180
+  rotate270                 90 degrees counterclockwise
+  flip[=horizontal|vertical]
+                            default horizontal (mirror left to right)
+  angle[=<degrees>[:<border>]]
+                            free rotation counterclockwise on a canvas grown
+                            to fit, default 0; border is black (default),
+                            replicate, reflect or wrap
+  warp=<x1>:<y1>:<x2>:<y2>:<x3>:<y3>:<x4>:<y4>
+                            straightens the quadrilateral with these corners
+                            (top-left, top-right, bottom-right, bottom-left)
+  threshold[=<method>[:inv]]
+                            binary mask of the grayscale image, default
+                            fixed:128; method is fixed[:<level>], otsu,
+                            triangle, mean[:<block>:<c>] or
+                            gaussian[:<block>:<c>], :inv swaps black and white
+  morph[=<op>[:<shape>[:<size>[:<iterations>]]]]
+                            default open:rect:3:1; op is erode, dilate, open,
+                            close, gradient, tophat or blackhat, shape is
+                            rect, ellipse or cross
+  gaussian[=<ksize>[:<sigma>]]
+                            Gaussian blur, default 5, sigma 0 derives it
+                            from the kernel size
+  median[=<ksize>]          median blur, default 5
+  bilateral[=<d>:<sigma color>:<sigma space>]
+                            edge-preserving blur, default 9:75:75
+  nlmeans[=<h>:<h color>[:<template>:<search>]]
+                            non-local means denoising, default 10:10:7:21
+  sharpen[=<sigma>:<amount>]
+                            unsharp mask, default 1.5:1
+  equalize                  histogram equalization
+  clahe[=<clip>:<tiles>]    adaptive equalization, default 2:8
+  gamma[=<gamma>]           above 1 brightens the midtones, default 1
+  brightness[=<b>:<c>]      brightness offset and contrast factor, default 0:1
+  levels[=<black>:<white>[:<gamma>]]
+                            default 0:255:1
+                            Tone operations work on luminance; add :channels
+                            (equalize=channels) to adjust B, G and R separately
+
+A recipe saved from the GUI replays its enabled steps in the same order.
+
+Results are written as <stem>.png to the output folder (default imgtool_out);
+inputs that share a stem, or whose result would replace the input, fail.
+An input's .rois.json sidecar is carried through resize, crop, rotations, flips
+and warps into the output's own sidecar.
+
+roi-pixels reads each image's .rois.json sidecar and writes CSV, NPY, raw
+and JSON metadata per ROI. --space is one of RGB (default), BGR, HSV, Lab,
+YCrCb or GRAY.";
+
+enum Command {
+    Apply(Pipeline),
+    RoiPixels,
+}
+
+struct Args {
+    command: Command,
+    inputs: Vec<String>,
+    out_dir: PathBuf,
+    space: ColorSpace,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let command = match args.next().as_deref() {
+        Some("apply") => {
+            let ops = args
+                .next()
+                .ok_or("apply needs a list of operations or a recipe")?;
+            if Path::new(&ops).is_file() {
+                Command::Apply(load_recipe(Path::new(&ops)).map_err(|e| format!("{}: {}", ops, e))?)
+            } else {
+                let filters = ops
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<Vec<Filter>, _>>()?;
+                Command::Apply(Pipeline::new(filters))
+            }
+        }
+        Some("roi-pixels") => Command::RoiPixels,
+        Some(other) => return Err(format!("unknown command: {}", other)),
+        None => return Err("missing command".to_string()),
+    };
+
+    let mut inputs = vec![];
+    let mut out_dir = PathBuf::from(DEFAULT_OUT_DIR);
+    let mut space = ColorSpace::Rgb;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--out" => out_dir = args.next().ok_or("-o needs a folder")?.into(),
+            "--space" => {
+                let name = args.next().ok_or("--space needs a colour space")?;
+                space = ColorSpace::ALL
+                    .iter()
+                    .copied()
+                    .find(|s| s.name().eq_ignore_ascii_case(&name))
+                    .ok_or_else(|| format!("unknown colour space: {}", name))?;
+            }
+            _ => inputs.push(arg),
+        }
+    }
+    if inputs.is_empty() {
+        return Err("no inputs given".to_string());
+    }
+
+    Ok(Args {
+        command,
+        inputs,
+        out_dir,
+        space,
+    })
+}
+
+/// Expands files, folders and glob patterns into a sorted list of images.
+fn collect_images(inputs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut images = vec![];
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            images.extend(folder_images(path).map_err(|e| format!("{}: {}", input, e))?);
+        } else if path.is_file() {
+            images.push(path.to_path_buf());
+        } else {
+            // Like a shell, wildcards skip hidden entries such as the GUI's
+            // .thumbnails cache
+            let options = glob::MatchOptions {
+                require_literal_leading_dot: true,
+                ..glob::MatchOptions::new()
+            };
+            let matches =
+                glob::glob_with(input, options).map_err(|e| format!("{}: {}", input, e))?;
+            let before = images.len();
+            images.extend(matches.flatten().filter(|p| p.is_file() && is_image(p)));
+            if images.len() == before {
+                return Err(format!("{}: no matching images", input));
+            }
+        }
+    }
+    images.sort();
+    images.dedup();
+    Ok(images)
+}
+
+fn apply_pipeline(path: &Path, pipeline: &Pipeline, out_path: &Path) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let mat = decode_image(&bytes).map_err(|e| e.to_string())?;
+    let rois = load_rois(path, mat.cols(), mat.rows());
+    let (mat, rois) = pipeline
+        .run_with_rois(mat, &rois, |_, _| Ok(()))
+        .map_err(|e| e.to_string())?;
+    let png = encode_png(&mat).map_err(|e| e.to_string())?;
+    fs::write(out_path, png).map_err(|e| e.to_string())?;
+    save_rois(out_path, mat.cols(), mat.rows(), &rois).map_err(|e| e.to_string())
+}
+
+fn extract_roi_pixels(path: &Path, space: ColorSpace, out_dir: &Path) -> Result<usize, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let mat = decode_image(&bytes).map_err(|e| e.to_string())?;
+    let rois = load_rois(path, mat.cols(), mat.rows());
+    if rois.is_empty() {
+        return Ok(0);
+    }
+    let readout = to_color_space(&mat, ChannelOrder::Bgr, space).map_err(|e| e.to_string())?;
+    export_roi_pixels(&readout, &rois, space, path, out_dir).map_err(|e| e.to_string())
+}
+
+fn run(args: Args) -> Result<usize, String> {
+    let images = collect_images(&args.inputs)?;
+    if images.is_empty() {
+        return Err("no images found".to_string());
+    }
+    fs::create_dir_all(&args.out_dir).map_err(|e| format!("{}: {}", args.out_dir.display(), e))?;
+
+    // Outputs are named by stem, so two inputs must not share one and no
+    // output may replace its input; roi-pixels claims its <stem>_roi* files
+    let plan = claim_outputs(&images, |_, path| {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        match &args.command {
+            Command::Apply(_) => args.out_dir.join(format!("{}.png", stem)),
+            Command::RoiPixels => args.out_dir.join(format!("{}_roi*", stem)),
+        }
+    });
+    let mut failures = 0;
+    for (path, planned) in images.iter().zip(plan) {
+        let result = planned.and_then(|out_path| match &args.command {
+            Command::Apply(pipeline) => apply_pipeline(path, pipeline, &out_path)
+                .map(|_| format!("-> {}", out_path.display())),
+            Command::RoiPixels => extract_roi_pixels(path, args.space, &args.out_dir)
+                .map(|count| format!("{} ROIs", count)),
+        });
+        match result {
+            Ok(summary) => println!("✅ {} {}", path.display(), summary),
+            Err(e) => {
+                eprintln!("❌ {}: {}", path.display(), e);
+                failures += 1;
+            }
+        }
+    }
+    println!(
+        "{} of {} images processed",
+        images.len() - failures,
+        images.len()
+    );
+    Ok(failures)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let args = match parse_args(args.into_iter()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("imgtool: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("imgtool: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
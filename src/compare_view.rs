@@ -0,0 +1,135 @@
+// Before/after view of the processed image against the original
+use dioxus::prelude::*;
+use opencv::core::Rect;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareMode {
+    Result,
+    SideBySide,
+    /// Original left of the divider, result right of it
+    Split,
+}
+
+impl CompareMode {
+    const ALL: [CompareMode; 3] = [
+        CompareMode::Result,
+        CompareMode::SideBySide,
+        CompareMode::Split,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            CompareMode::Result => "Result",
+            CompareMode::SideBySide => "Side by side",
+            CompareMode::Split => "Split",
+        }
+    }
+}
+
+// Outlines of `boxes` over an image of `size` pixels, however large it is shown
+fn box_overlay(size: (i32, i32), boxes: &[Rect]) -> Element {
+    let (width, height) = size;
+    rsx! {
+        svg {
+            class: "absolute inset-0 w-full h-full pointer-events-none",
+            view_box: "0 0 {width} {height}",
+            preserve_aspect_ratio: "none",
+            for area in boxes.iter() {
+                rect {
+                    x: "{area.x}",
+                    y: "{area.y}",
+                    width: "{area.width}",
+                    height: "{area.height}",
+                    fill: "none",
+                    stroke: "#ef4444",
+                    stroke_width: "2",
+                    vector_effect: "non-scaling-stroke",
+                }
+            }
+        }
+    }
+}
+
+/// Shows `result`, or `result` next to or split against `original` (both
+/// data URLs). In split mode both are stretched to the same box, so steps that
+/// change the size still line up roughly.
+///
+/// `boxes` (e.g. the image's ROIs, moved along with it) are outlined on the
+/// result, which is `size` pixels large.
+#[component]
+pub fn CompareView(
+    original: String,
+    result: String,
+    #[props(default)] size: (i32, i32),
+    #[props(default)] boxes: Vec<Rect>,
+) -> Element {
+    let mut mode = use_signal(|| CompareMode::Result);
+    // Divider position in percent of the width
+    let mut split = use_signal(|| 50.0);
+
+    rsx! {
+        div { class: "flex flex-col gap-2 mb-4",
+            div { class: "flex items-center gap-1 text-sm",
+                for m in CompareMode::ALL {
+                    button {
+                        onclick: move |_| mode.set(m),
+                        class: if mode() == m { "px-2 py-1 rounded bg-indigo-600 text-white" } else { "px-2 py-1 rounded bg-gray-200" },
+                        "{m.label()}"
+                    }
+                }
+                if mode() == CompareMode::Split {
+                    input {
+                        r#type: "range",
+                        min: "0",
+                        max: "100",
+                        step: "0.5",
+                        value: "{split}",
+                        class: "w-48 ml-2",
+                        oninput: move |evt| {
+                            if let Ok(val) = evt.value().parse::<f64>() {
+                                split.set(val.clamp(0.0, 100.0));
+                            }
+                        }
+                    }
+                }
+            }
+            match mode() {
+                CompareMode::Result => rsx! {
+                    div { class: "relative w-fit",
+                        img { src: "{result}", class: "block max-w-[600px] border rounded shadow" }
+                        {box_overlay(size, &boxes)}
+                    }
+                },
+                CompareMode::SideBySide => rsx! {
+                    div { class: "flex gap-2",
+                        figure {
+                            img { src: "{original}", class: "max-w-[400px] border rounded shadow" }
+                            figcaption { class: "text-xs text-gray-500", "Original" }
+                        }
+                        figure {
+                            div { class: "relative w-fit",
+                                img { src: "{result}", class: "block max-w-[400px] border rounded shadow" }
+                                {box_overlay(size, &boxes)}
+                            }
+                            figcaption { class: "text-xs text-gray-500", "Result" }
+                        }
+                    }
+                },
+                CompareMode::Split => rsx! {
+                    div { class: "relative w-[600px] border rounded shadow overflow-hidden",
+                        img { src: "{original}", class: "block w-full" }
+                        img {
+                            src: "{result}",
+                            class: "absolute inset-0 w-full h-full",
+                            style: "clip-path: inset(0 0 0 {split}%);",
+                        }
+                        div {
+                            class: "absolute top-0 bottom-0 w-px bg-red-600 pointer-events-none",
+                            style: "left: {split}%;",
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,662 @@
+// Dataset export/import (COCO, Pascal VOC, YOLO) on top of the per-image ROI sidecars
+use opencv::{core::Rect, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::filters::decode_image;
+use crate::core::roi_model::{group_labels, ClassPalette, LabeledRoi, DEFAULT_CLASS};
+use crate::core::roi_sidecar::{read_sidecar, save_rois};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnnotationFormat {
+    Coco,
+    PascalVoc,
+    Yolo,
+}
+
+impl AnnotationFormat {
+    pub const ALL: [AnnotationFormat; 3] = [
+        AnnotationFormat::Coco,
+        AnnotationFormat::PascalVoc,
+        AnnotationFormat::Yolo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnnotationFormat::Coco => "COCO",
+            AnnotationFormat::PascalVoc => "Pascal VOC",
+            AnnotationFormat::Yolo => "YOLO",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.name() == name)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CocoDataset {
+    images: Vec<CocoImage>,
+    annotations: Vec<CocoAnnotation>,
+    categories: Vec<CocoCategory>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CocoImage {
+    id: u64,
+    file_name: String,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CocoAnnotation {
+    id: u64,
+    image_id: u64,
+    category_id: u64,
+    /// `[x, y, width, height]` in pixels
+    bbox: [f64; 4],
+    #[serde(default)]
+    area: f64,
+    #[serde(default)]
+    iscrowd: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CocoCategory {
+    id: u64,
+    name: String,
+}
+
+/// Image size and ROIs for one file, taken from its sidecar when present.
+struct ImageAnnotations {
+    path: PathBuf,
+    width: i32,
+    height: i32,
+    rois: Vec<LabeledRoi>,
+}
+
+/// Size of the image at `path` as the GUI sees it: the size its sidecar was
+/// saved for, or else what OpenCV decodes, which applies EXIF orientation.
+fn image_size(path: &Path) -> io::Result<(i32, i32)> {
+    if let Some(sidecar) = read_sidecar(path) {
+        return Ok((sidecar.image_width, sidecar.image_height));
+    }
+    let image = decode_image(&fs::read(path)?).map_err(|e| io::Error::other(e.to_string()))?;
+    if image.cols() == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a readable image",
+        ));
+    }
+    Ok((image.cols(), image.rows()))
+}
+
+fn collect_annotations(image_paths: &[PathBuf]) -> Vec<ImageAnnotations> {
+    image_paths
+        .iter()
+        .filter_map(|path| {
+            if let Some(sidecar) = read_sidecar(path) {
+                return Some(ImageAnnotations {
+                    path: path.clone(),
+                    width: sidecar.image_width,
+                    height: sidecar.image_height,
+                    rois: sidecar.rois.iter().map(LabeledRoi::from).collect(),
+                });
+            }
+            // Images without ROIs are still exported as negatives
+            match image_size(path) {
+                Ok((width, height)) => Some(ImageAnnotations {
+                    path: path.clone(),
+                    width,
+                    height,
+                    rois: vec![],
+                }),
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Clips a rectangle to the image; returns `None` when nothing is left.
+fn clip_to_image(x: i32, y: i32, w: i32, h: i32, img_w: i32, img_h: i32) -> Option<Rect> {
+    let x0 = x.clamp(0, img_w);
+    let y0 = y.clamp(0, img_h);
+    let x1 = (x + w).clamp(0, img_w);
+    let y1 = (y + h).clamp(0, img_h);
+    if x1 > x0 && y1 > y0 {
+        Some(Rect::new(x0, y0, x1 - x0, y1 - y0))
+    } else {
+        None
+    }
+}
+
+/// Writes the ROIs of every image in `image_paths` to `out_dir` and returns the
+/// number of images exported.
+pub fn export_dataset(
+    format: AnnotationFormat,
+    image_paths: &[PathBuf],
+    out_dir: &Path,
+) -> io::Result<usize> {
+    fs::create_dir_all(out_dir)?;
+    let images = collect_annotations(image_paths);
+
+    // Class ids follow the folder's palette so they stay stable between exports
+    let palette = image_paths
+        .first()
+        .and_then(|p| p.parent())
+        .map(ClassPalette::load)
+        .unwrap_or_default();
+    let all_rois: Vec<LabeledRoi> = images.iter().flat_map(|i| i.rois.clone()).collect();
+    let classes = group_labels(&palette, &all_rois);
+
+    match format {
+        AnnotationFormat::Coco => export_coco(&images, &classes, out_dir)?,
+        AnnotationFormat::PascalVoc => export_voc(&images, out_dir)?,
+        AnnotationFormat::Yolo => export_yolo(&images, &classes, out_dir)?,
+    }
+    Ok(images.len())
+}
+
+fn class_index(classes: &[String], label: &str) -> usize {
+    classes.iter().position(|c| c == label).unwrap_or(0)
+}
+
+fn export_coco(images: &[ImageAnnotations], classes: &[String], out_dir: &Path) -> io::Result<()> {
+    let mut dataset = CocoDataset {
+        images: vec![],
+        annotations: vec![],
+        categories: classes
+            .iter()
+            .enumerate()
+            .map(|(i, name)| CocoCategory {
+                id: i as u64 + 1,
+                name: name.clone(),
+            })
+            .collect(),
+    };
+
+    for (i, img) in images.iter().enumerate() {
+        let image_id = i as u64 + 1;
+        dataset.images.push(CocoImage {
+            id: image_id,
+            file_name: file_name(&img.path),
+            width: img.width,
+            height: img.height,
+        });
+        for LabeledRoi {
+            rect: roi, label, ..
+        } in &img.rois
+        {
+            dataset.annotations.push(CocoAnnotation {
+                id: dataset.annotations.len() as u64 + 1,
+                image_id,
+                category_id: class_index(classes, label) as u64 + 1,
+                bbox: [
+                    roi.x as f64,
+                    roi.y as f64,
+                    roi.width as f64,
+                    roi.height as f64,
+                ],
+                area: (roi.width * roi.height) as f64,
+                iscrowd: 0,
+            });
+        }
+    }
+
+    let json = serde_json::to_string_pretty(&dataset)?;
+    fs::write(out_dir.join("annotations.json"), json)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn export_voc(images: &[ImageAnnotations], out_dir: &Path) -> io::Result<()> {
+    for img in images {
+        let folder = img
+            .path
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut xml = String::from("<annotation>\n");
+        xml.push_str(&format!("  <folder>{}</folder>\n", xml_escape(&folder)));
+        xml.push_str(&format!(
+            "  <filename>{}</filename>\n",
+            xml_escape(&file_name(&img.path))
+        ));
+        xml.push_str(&format!(
+            "  <size>\n    <width>{}</width>\n    <height>{}</height>\n    <depth>3</depth>\n  </size>\n",
+            img.width, img.height
+        ));
+        xml.push_str("  <segmented>0</segmented>\n");
+        for LabeledRoi {
+            rect: roi, label, ..
+        } in &img.rois
+        {
+            // VOC boxes are 1-based and inclusive
+            xml.push_str(&format!(
+                "  <object>\n    <name>{}</name>\n    <pose>Unspecified</pose>\n    <truncated>0</truncated>\n    <difficult>0</difficult>\n    <bndbox>\n      <xmin>{}</xmin>\n      <ymin>{}</ymin>\n      <xmax>{}</xmax>\n      <ymax>{}</ymax>\n    </bndbox>\n  </object>\n",
+                xml_escape(label),
+                roi.x + 1,
+                roi.y + 1,
+                roi.x + roi.width,
+                roi.y + roi.height
+            ));
+        }
+        xml.push_str("</annotation>\n");
+
+        fs::write(out_dir.join(format!("{}.xml", file_stem(&img.path))), xml)?;
+    }
+    Ok(())
+}
+
+fn export_yolo(images: &[ImageAnnotations], classes: &[String], out_dir: &Path) -> io::Result<()> {
+    let mut names = classes.join("\n");
+    names.push('\n');
+    fs::write(out_dir.join("classes.txt"), names)?;
+
+    for img in images {
+        let (w, h) = (img.width as f64, img.height as f64);
+        let mut lines = String::new();
+        for LabeledRoi {
+            rect: roi, label, ..
+        } in &img.rois
+        {
+            let cx = (roi.x as f64 + roi.width as f64 / 2.0) / w;
+            let cy = (roi.y as f64 + roi.height as f64 / 2.0) / h;
+            lines.push_str(&format!(
+                "{} {:.6} {:.6} {:.6} {:.6}\n",
+                class_index(classes, label),
+                cx,
+                cy,
+                roi.width as f64 / w,
+                roi.height as f64 / h
+            ));
+        }
+        fs::write(out_dir.join(format!("{}.txt", file_stem(&img.path))), lines)?;
+    }
+    Ok(())
+}
+
+/// Reads annotations from `source` and overwrites the sidecars of the matching
+/// images in `image_paths`. `source` is the COCO JSON file, or the directory
+/// holding the VOC `.xml` / YOLO `.txt` files. Returns the number of images
+/// that received ROIs.
+///
+/// Images the dataset gives no boxes keep the ROIs they have, and images
+/// annotated at another size than they decode to are skipped, since their
+/// boxes would not line up.
+pub fn import_dataset(
+    format: AnnotationFormat,
+    source: &Path,
+    image_paths: &[PathBuf],
+) -> io::Result<usize> {
+    match format {
+        AnnotationFormat::Coco => import_coco(source, image_paths),
+        AnnotationFormat::PascalVoc => import_voc(source, image_paths),
+        AnnotationFormat::Yolo => import_yolo(source, image_paths),
+    }
+}
+
+fn import_coco(source: &Path, image_paths: &[PathBuf]) -> io::Result<usize> {
+    let text = fs::read_to_string(source)?;
+    let dataset: CocoDataset = serde_json::from_str(&text)?;
+
+    let mut imported = 0;
+    for image in &dataset.images {
+        // COCO file names may carry a sub-directory, match on the bare name
+        let wanted = file_name(Path::new(&image.file_name));
+        let Some(path) = image_paths.iter().find(|p| file_name(p) == wanted) else {
+            continue;
+        };
+
+        let rois: Vec<LabeledRoi> = dataset
+            .annotations
+            .iter()
+            .filter(|a| a.image_id == image.id)
+            .filter_map(|a| {
+                let [x, y, w, h] = a.bbox;
+                let rect = clip_to_image(
+                    x.round() as i32,
+                    y.round() as i32,
+                    w.round() as i32,
+                    h.round() as i32,
+                    image.width,
+                    image.height,
+                )?;
+                let label = dataset
+                    .categories
+                    .iter()
+                    .find(|c| c.id == a.category_id)
+                    .map(|c| c.name.clone())
+                    .unwrap_or_else(|| DEFAULT_CLASS.to_string());
+                Some(LabeledRoi::new(rect, label))
+            })
+            .collect();
+
+        if rois.is_empty() {
+            continue;
+        }
+        if save_imported(path, (image.width, image.height), &rois)? {
+            imported += 1;
+        }
+    }
+    Ok(imported)
+}
+
+// Saves ROIs imported from boxes drawn on an `annotated`-sized image, unless
+// the image at `path` has another size. Returns whether they were saved
+fn save_imported(path: &Path, annotated: (i32, i32), rois: &[LabeledRoi]) -> io::Result<bool> {
+    let (img_w, img_h) = match image_size(path) {
+        Ok(size) => size,
+        Err(e) => {
+            eprintln!("Skipping {}: {}", path.display(), e);
+            return Ok(false);
+        }
+    };
+    if annotated != (img_w, img_h) {
+        eprintln!(
+            "Skipping {}: annotated at {}x{}, image is {}x{}",
+            path.display(),
+            annotated.0,
+            annotated.1,
+            img_w,
+            img_h
+        );
+        return Ok(false);
+    }
+    save_rois(path, img_w, img_h, rois)?;
+    Ok(true)
+}
+
+/// Reverses `xml_escape`.
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Text of every `<tag>…</tag>` element in `xml`, in document order.
+/// Enough for the flat layout VOC files use; attributes and CDATA are not handled.
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut found = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        let Some(end) = after.find(&close) else {
+            break;
+        };
+        found.push(after[..end].trim());
+        rest = &after[end + close.len()..];
+    }
+    found
+}
+
+fn xml_number(xml: &str, tag: &str) -> Option<f64> {
+    xml_elements(xml, tag).first()?.parse().ok()
+}
+
+fn import_voc(source: &Path, image_paths: &[PathBuf]) -> io::Result<usize> {
+    let mut imported = 0;
+    for path in image_paths {
+        let xml_path = source.join(format!("{}.xml", file_stem(path)));
+        let Ok(xml) = fs::read_to_string(&xml_path) else {
+            continue;
+        };
+
+        let size = xml_elements(&xml, "size");
+        let (Some(img_w), Some(img_h)) = (
+            size.first().and_then(|s| xml_number(s, "width")),
+            size.first().and_then(|s| xml_number(s, "height")),
+        ) else {
+            eprintln!("Skipping {}: missing <size>", xml_path.display());
+            continue;
+        };
+        let (img_w, img_h) = (img_w as i32, img_h as i32);
+
+        let rois: Vec<LabeledRoi> = xml_elements(&xml, "object")
+            .iter()
+            .filter_map(|object| {
+                let label = xml_elements(object, "name")
+                    .first()
+                    .map(|n| xml_unescape(n))
+                    .unwrap_or_else(|| DEFAULT_CLASS.to_string());
+                let b = *xml_elements(object, "bndbox").first()?;
+                let xmin = xml_number(b, "xmin")?;
+                let ymin = xml_number(b, "ymin")?;
+                let xmax = xml_number(b, "xmax")?;
+                let ymax = xml_number(b, "ymax")?;
+                let x = xmin.round() as i32 - 1;
+                let y = ymin.round() as i32 - 1;
+                let rect = clip_to_image(
+                    x,
+                    y,
+                    xmax.round() as i32 - x,
+                    ymax.round() as i32 - y,
+                    img_w,
+                    img_h,
+                )?;
+                Some(LabeledRoi::new(rect, label))
+            })
+            .collect();
+
+        if rois.is_empty() {
+            continue;
+        }
+        if save_imported(path, (img_w, img_h), &rois)? {
+            imported += 1;
+        }
+    }
+    Ok(imported)
+}
+
+fn import_yolo(source: &Path, image_paths: &[PathBuf]) -> io::Result<usize> {
+    let class_names: Vec<String> = fs::read_to_string(source.join("classes.txt"))
+        .map(|text| text.lines().map(|l| l.trim().to_string()).collect())
+        .unwrap_or_default();
+
+    let mut imported = 0;
+    for path in image_paths {
+        let txt_path = source.join(format!("{}.txt", file_stem(path)));
+        let Ok(text) = fs::read_to_string(&txt_path) else {
+            continue;
+        };
+
+        if text.trim().is_empty() {
+            continue;
+        }
+        // YOLO boxes are normalized, so the real size has to come from the image
+        let (img_w, img_h) = match image_size(path) {
+            Ok(size) => size,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        let rois: Vec<LabeledRoi> = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let class_id: usize = fields.next()?.parse().ok()?;
+                let values: Vec<f64> = fields.filter_map(|v| v.parse().ok()).collect();
+                let [cx, cy, w, h] = values[..] else {
+                    return None;
+                };
+                let label = class_names
+                    .get(class_id)
+                    .filter(|n| !n.is_empty())
+                    .cloned()
+                    .unwrap_or_else(|| format!("class_{}", class_id));
+                let w_px = w * img_w as f64;
+                let h_px = h * img_h as f64;
+                let rect = clip_to_image(
+                    (cx * img_w as f64 - w_px / 2.0).round() as i32,
+                    (cy * img_h as f64 - h_px / 2.0).round() as i32,
+                    w_px.round() as i32,
+                    h_px.round() as i32,
+                    img_w,
+                    img_h,
+                )?;
+                Some(LabeledRoi::new(rect, label))
+            })
+            .collect();
+
+        if rois.is_empty() {
+            continue;
+        }
+        save_rois(path, img_w, img_h, &rois)?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::roi_sidecar::load_rois;
+
+    const SIZE: (i32, i32) = (640, 480);
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dataset_{}_{}", name, std::process::id()));
+        fs::create_dir_all(dir.join("out")).unwrap();
+        dir
+    }
+
+    fn sample_rois() -> Vec<LabeledRoi> {
+        vec![
+            LabeledRoi::new(Rect::new(0, 0, 5, 7), "leaf"),
+            LabeledRoi::new(Rect::new(13, 27, 101, 55), "stem"),
+            LabeledRoi::new(Rect::new(600, 400, 40, 80), "leaf"),
+        ]
+    }
+
+    // Exports the sample ROIs of one image, replaces them and imports them back
+    fn round_trip(format: AnnotationFormat, name: &str) -> Vec<LabeledRoi> {
+        let dir = temp_dir(name);
+        let images = [dir.join("photo.jpg")];
+        let image = &images[0];
+        let out = dir.join("out");
+        save_rois(image, SIZE.0, SIZE.1, &sample_rois()).unwrap();
+        assert_eq!(export_dataset(format, &images, &out).unwrap(), 1);
+
+        let placeholder = [LabeledRoi::new(Rect::new(1, 1, 1, 1), DEFAULT_CLASS)];
+        save_rois(image, SIZE.0, SIZE.1, &placeholder).unwrap();
+        let source = match format {
+            AnnotationFormat::Coco => out.join("annotations.json"),
+            _ => out,
+        };
+        assert_eq!(import_dataset(format, &source, &images).unwrap(), 1);
+        let imported = load_rois(image, SIZE.0, SIZE.1);
+        fs::remove_dir_all(&dir).unwrap();
+        imported
+    }
+
+    #[test]
+    fn coco_round_trip() {
+        assert_eq!(round_trip(AnnotationFormat::Coco, "coco"), sample_rois());
+    }
+
+    #[test]
+    fn voc_boxes_are_one_based_and_inclusive() {
+        let dir = temp_dir("voc_boxes");
+        let image = dir.join("photo.jpg");
+        save_rois(&image, SIZE.0, SIZE.1, &sample_rois()).unwrap();
+        export_dataset(AnnotationFormat::PascalVoc, &[image], &dir.join("out")).unwrap();
+        let xml = fs::read_to_string(dir.join("out").join("photo.xml")).unwrap();
+        let first = xml_elements(&xml, "bndbox")[0];
+        assert_eq!(xml_number(first, "xmin"), Some(1.0));
+        assert_eq!(xml_number(first, "ymin"), Some(1.0));
+        assert_eq!(xml_number(first, "xmax"), Some(5.0));
+        assert_eq!(xml_number(first, "ymax"), Some(7.0));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            round_trip(AnnotationFormat::PascalVoc, "voc"),
+            sample_rois()
+        );
+    }
+
+    #[test]
+    fn yolo_round_trip() {
+        assert_eq!(round_trip(AnnotationFormat::Yolo, "yolo"), sample_rois());
+    }
+
+    #[test]
+    fn coco_import_keeps_rois_it_has_no_boxes_for() {
+        let dir = temp_dir("coco_keep");
+        let (kept, resized, annotated) = (
+            dir.join("kept.jpg"),
+            dir.join("resized.jpg"),
+            dir.join("annotated.jpg"),
+        );
+        let roi = [LabeledRoi::new(Rect::new(2, 2, 4, 4), "leaf")];
+        save_rois(&kept, SIZE.0, SIZE.1, &roi).unwrap();
+        // Rotated since it was annotated
+        save_rois(&resized, SIZE.1, SIZE.0, &roi).unwrap();
+        save_rois(&annotated, SIZE.0, SIZE.1, &roi).unwrap();
+
+        let coco = r#"{
+            "images": [
+                {"id": 1, "file_name": "kept.jpg", "width": 640, "height": 480},
+                {"id": 2, "file_name": "resized.jpg", "width": 640, "height": 480},
+                {"id": 3, "file_name": "sub/annotated.jpg", "width": 640, "height": 480}
+            ],
+            "annotations": [
+                {"id": 1, "image_id": 2, "category_id": 1, "bbox": [10, 10, 20, 20]},
+                {"id": 2, "image_id": 3, "category_id": 1, "bbox": [10.4, 9.6, 20, 20]}
+            ],
+            "categories": [{"id": 1, "name": "stem"}]
+        }"#;
+        let source = dir.join("annotations.json");
+        fs::write(&source, coco).unwrap();
+        let paths = [kept.clone(), resized.clone(), annotated.clone()];
+        assert_eq!(
+            import_dataset(AnnotationFormat::Coco, &source, &paths).unwrap(),
+            1
+        );
+
+        assert_eq!(load_rois(&kept, SIZE.0, SIZE.1), roi);
+        assert_eq!(load_rois(&resized, SIZE.1, SIZE.0), roi);
+        assert_eq!(
+            load_rois(&annotated, SIZE.0, SIZE.1),
+            [LabeledRoi::new(Rect::new(10, 10, 20, 20), "stem")]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn xml_text_round_trips() {
+        let text = r#"a<b> & "c" 'd'"#;
+        assert_eq!(xml_unescape(&xml_escape(text)), text);
+    }
+}
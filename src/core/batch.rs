@@ -0,0 +1,335 @@
+// Batch processing: run a pipeline over every image of a folder
+//
+// Files are processed on tokio's blocking pool, at most `workers` at a time.
+// Outcomes are reported as each file finishes, in completion order, and a
+// CSV report of every file is written next to the outputs.
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::core::encode::{encode_image, EncodeOptions, ImageFormat};
+use crate::core::filters::decode_image;
+use crate::core::pipeline::Pipeline;
+use crate::core::roi_sidecar::{load_rois, save_rois};
+use crate::core::roi_stats::csv_field;
+
+pub const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+pub const DEFAULT_NAMING: &str = "{stem}_processed";
+pub const REPORT_FILE: &str = "batch_report.csv";
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// The images directly inside `folder`, sorted by path
+pub fn folder_images(folder: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(folder)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|p| p.is_file() && is_image(p))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// `path` with `.`, `..` and symlinks resolved as far as it exists, so two
+/// spellings of one file compare equal even before it is written
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(full) = fs::canonicalize(path) {
+        return full;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            fs::canonicalize(dir).map_or_else(|_| path.to_path_buf(), |dir| dir.join(name))
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Whether `a` and `b` name the same file or folder; neither has to exist
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    a == b || resolve(a) == resolve(b)
+}
+
+/// Pairs each input with `output(index, input)`. An input gets an error
+/// instead when its output would replace one of the inputs or was already
+/// claimed by an earlier input.
+pub fn claim_outputs(
+    inputs: &[PathBuf],
+    mut output: impl FnMut(usize, &Path) -> PathBuf,
+) -> Vec<Result<PathBuf, String>> {
+    let resolved_inputs: Vec<PathBuf> = inputs.iter().map(|p| resolve(p)).collect();
+    let mut claimed: HashMap<PathBuf, &Path> = HashMap::new();
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            let output = output(index, input);
+            let key = resolve(&output);
+            if resolved_inputs.contains(&key) {
+                return Err(format!("{} would replace an input image", output.display()));
+            }
+            match claimed.get(&key) {
+                Some(other) => Err(format!(
+                    "{} is already written for {}",
+                    output.display(),
+                    other.display()
+                )),
+                None => {
+                    claimed.insert(key, input);
+                    Ok(output)
+                }
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchJob {
+    pub pipeline: Pipeline,
+    pub out_dir: PathBuf,
+    /// Output file name without extension; `{stem}` is the input's file stem
+    /// and `{n}` its 1-based position in the batch, zero-padded to 4 digits
+    pub naming: String,
+    pub format: ImageFormat,
+}
+
+impl BatchJob {
+    /// Checks that the naming pattern gives each input its own output name
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.naming.contains("{stem}") && !self.naming.contains("{n}") {
+            return Err("the naming pattern needs {stem} or {n}".to_string());
+        }
+        if self.naming.contains(['/', '\\']) {
+            return Err("the naming pattern must not contain path separators".to_string());
+        }
+        Ok(())
+    }
+
+    /// `validate`, and also refuses to write into `input_dir` itself unless
+    /// the naming pattern changes the file names
+    pub fn validate_for(&self, input_dir: &Path) -> Result<(), String> {
+        self.validate()?;
+        if self.naming == "{stem}" && same_path(&self.out_dir, input_dir) {
+            return Err(
+                "the output folder is the input folder; pick another folder or a naming \
+                 pattern that changes the names"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn output_path(&self, index: usize, input: &Path) -> PathBuf {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let name = self
+            .naming
+            .replace("{stem}", &stem)
+            .replace("{n}", &format!("{:04}", index + 1));
+        self.out_dir
+            .join(format!("{}.{}", name, self.format.extension()))
+    }
+
+    /// Decodes, processes and writes one image to `output`, with the input's
+    /// ROIs moved along into the output's sidecar
+    pub fn process(&self, input: &Path, output: &Path) -> Result<(), String> {
+        let bytes = fs::read(input).map_err(|e| e.to_string())?;
+        let mat = decode_image(&bytes).map_err(|e| e.to_string())?;
+        let rois = load_rois(input, mat.cols(), mat.rows());
+        let (mat, rois) = self
+            .pipeline
+            .run_with_rois(mat, &rois, |_, _| Ok(()))
+            .map_err(|e| e.to_string())?;
+        let encoded =
+            encode_image(&mat, &EncodeOptions::new(self.format)).map_err(|e| e.to_string())?;
+        fs::write(output, encoded).map_err(|e| e.to_string())?;
+        save_rois(output, mat.cols(), mat.rows(), &rois).map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileStatus {
+    Written(PathBuf),
+    Failed(String),
+    /// Not started before the batch was cancelled
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileOutcome {
+    pub input: PathBuf,
+    pub status: FileStatus,
+}
+
+/// Output path per input; inputs whose output would replace an input image
+/// or whose name is already taken get an error
+pub fn plan_outputs(job: &BatchJob, inputs: &[PathBuf]) -> Vec<Result<PathBuf, String>> {
+    claim_outputs(inputs, |index, input| job.output_path(index, input))
+}
+
+/// Processes `inputs` with up to `workers` files in flight, calling `on_file`
+/// as each one finishes. Files not yet started when `cancel` is set are
+/// reported as cancelled. Returns all outcomes sorted by input path.
+pub async fn run_batch(
+    job: BatchJob,
+    inputs: Vec<PathBuf>,
+    workers: usize,
+    cancel: Arc<AtomicBool>,
+    mut on_file: impl FnMut(&FileOutcome),
+) -> io::Result<Vec<FileOutcome>> {
+    job.validate()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    fs::create_dir_all(&job.out_dir)?;
+
+    let plan = plan_outputs(&job, &inputs);
+    let job = Arc::new(job);
+    let slots = Arc::new(Semaphore::new(workers.max(1)));
+    let mut tasks = JoinSet::new();
+    for (input, planned) in inputs.into_iter().zip(plan) {
+        let job = job.clone();
+        let slots = slots.clone();
+        let cancel = cancel.clone();
+        tasks.spawn(async move {
+            let status = match planned {
+                Err(e) => FileStatus::Failed(e),
+                Ok(output) => {
+                    let _slot = slots
+                        .acquire_owned()
+                        .await
+                        .expect("semaphore is never closed");
+                    if cancel.load(Ordering::Relaxed) {
+                        FileStatus::Cancelled
+                    } else {
+                        let source = input.clone();
+                        let result = tokio::task::spawn_blocking(move || {
+                            job.process(&source, &output).map(|_| output)
+                        })
+                        .await;
+                        match result {
+                            Ok(Ok(output)) => FileStatus::Written(output),
+                            Ok(Err(e)) => FileStatus::Failed(e),
+                            Err(e) => FileStatus::Failed(format!("worker failed: {}", e)),
+                        }
+                    }
+                }
+            };
+            FileOutcome { input, status }
+        });
+    }
+
+    let mut outcomes = vec![];
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(outcome) => {
+                on_file(&outcome);
+                outcomes.push(outcome);
+            }
+            Err(e) => eprintln!("Batch task failed: {}", e),
+        }
+    }
+    outcomes.sort_by(|a, b| a.input.cmp(&b.input));
+    write_report(&job.out_dir.join(REPORT_FILE), &outcomes)?;
+    Ok(outcomes)
+}
+
+/// One row per input: `input,status,detail`, where detail is the output
+/// path or the error
+pub fn write_report(path: &Path, outcomes: &[FileOutcome]) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    writeln!(out, "input,status,detail")?;
+    for outcome in outcomes {
+        let (status, detail) = match &outcome.status {
+            FileStatus::Written(output) => ("ok", output.display().to_string()),
+            FileStatus::Failed(e) => ("failed", e.clone()),
+            FileStatus::Cancelled => ("cancelled", String::new()),
+        };
+        writeln!(
+            out,
+            "{},{},{}",
+            csv_field(&outcome.input.display().to_string()),
+            status,
+            csv_field(&detail)
+        )?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(naming: &str) -> BatchJob {
+        BatchJob {
+            pipeline: Pipeline::default(),
+            out_dir: PathBuf::from("out"),
+            naming: naming.to_string(),
+            format: ImageFormat::Jpeg,
+        }
+    }
+
+    #[test]
+    fn names_outputs_from_the_pattern() {
+        let job = job("{n}-{stem}_small");
+        assert_eq!(
+            job.output_path(6, Path::new("/data/cat.png")),
+            PathBuf::from("out/0007-cat_small.jpg")
+        );
+        assert!(job.validate().is_ok());
+        assert!(self::job("fixed").validate().is_err());
+        assert!(self::job("sub/{stem}").validate().is_err());
+    }
+
+    #[test]
+    fn plan_rejects_colliding_outputs() {
+        let inputs = [
+            PathBuf::from("a/cat.png"),
+            PathBuf::from("a/cat.jpg"),
+            PathBuf::from("a/dog.png"),
+        ];
+        let plan = plan_outputs(&job("{stem}"), &inputs);
+        assert_eq!(plan[0], Ok(PathBuf::from("out/cat.jpg")));
+        assert!(plan[1].is_err());
+        assert_eq!(plan[2], Ok(PathBuf::from("out/dog.jpg")));
+        assert!(plan_outputs(&job("{n}"), &inputs).iter().all(Result::is_ok));
+    }
+
+    #[test]
+    fn plan_refuses_to_replace_inputs() {
+        let dir = std::env::temp_dir().join(format!("batch_in_place_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let inputs = [dir.join("cat.png"), dir.join("dog.jpg")];
+        for input in &inputs {
+            fs::write(input, b"").unwrap();
+        }
+        let in_place = BatchJob {
+            out_dir: dir.join("."),
+            format: ImageFormat::Png,
+            ..job("{stem}")
+        };
+
+        assert!(in_place.validate_for(&dir).is_err());
+        let plan = plan_outputs(&in_place, &inputs);
+        assert!(plan[0].is_err());
+        assert_eq!(plan[1], Ok(dir.join(".").join("dog.png")));
+
+        let renamed = BatchJob {
+            naming: DEFAULT_NAMING.to_string(),
+            ..in_place
+        };
+        assert!(renamed.validate_for(&dir).is_ok());
+        assert!(plan_outputs(&renamed, &inputs).iter().all(Result::is_ok));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
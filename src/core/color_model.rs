@@ -0,0 +1,109 @@
+// Channel layout of decoded images and the colour space used for readouts
+//
+// `ChannelOrder` records what is actually stored in a Mat (OpenCV decodes to
+// BGR; the canvas may convert to RGB or gray). `ColorSpace` is what the user
+// wants to read values in. Every readout and export goes through
+// `to_color_space`, so the two never get mixed up.
+use opencv::{core::AlgorithmHint, imgproc, prelude::*};
+
+/// Memory layout of an 8-bit Mat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelOrder {
+    Gray,
+    Rgb,
+    Bgr,
+}
+
+impl ChannelOrder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelOrder::Gray => "GRAY",
+            ChannelOrder::Rgb => "RGB",
+            ChannelOrder::Bgr => "BGR",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+    Rgb,
+    Bgr,
+    Hsv,
+    Lab,
+    YCrCb,
+    Gray,
+}
+
+impl ColorSpace {
+    pub const ALL: [ColorSpace; 6] = [
+        ColorSpace::Rgb,
+        ColorSpace::Bgr,
+        ColorSpace::Hsv,
+        ColorSpace::Lab,
+        ColorSpace::YCrCb,
+        ColorSpace::Gray,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Rgb => "RGB",
+            ColorSpace::Bgr => "BGR",
+            ColorSpace::Hsv => "HSV",
+            ColorSpace::Lab => "Lab",
+            ColorSpace::YCrCb => "YCrCb",
+            ColorSpace::Gray => "GRAY",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ColorSpace> {
+        Self::ALL.iter().copied().find(|s| s.name() == name)
+    }
+
+    /// Channel names in storage order. For 8-bit HSV, H is in 0..=179.
+    pub fn channel_names(&self) -> &'static [&'static str] {
+        match self {
+            ColorSpace::Rgb => &["R", "G", "B"],
+            ColorSpace::Bgr => &["B", "G", "R"],
+            ColorSpace::Hsv => &["H", "S", "V"],
+            ColorSpace::Lab => &["L", "a", "b"],
+            ColorSpace::YCrCb => &["Y", "Cr", "Cb"],
+            ColorSpace::Gray => &["Gray"],
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channel_names().len()
+    }
+}
+
+fn convert(src: &Mat, code: i32) -> opencv::Result<Mat> {
+    let mut dst = Mat::default();
+    imgproc::cvt_color(src, &mut dst, code, 0, AlgorithmHint::ALGO_HINT_DEFAULT)?;
+    Ok(dst)
+}
+
+/// Converts `mat`, stored as `order`, into `space`.
+pub fn to_color_space(mat: &Mat, order: ChannelOrder, space: ColorSpace) -> opencv::Result<Mat> {
+    // Layouts that already match need no conversion
+    match (order, space) {
+        (ChannelOrder::Gray, ColorSpace::Gray)
+        | (ChannelOrder::Rgb, ColorSpace::Rgb)
+        | (ChannelOrder::Bgr, ColorSpace::Bgr) => return Ok(mat.clone()),
+        _ => {}
+    }
+
+    let bgr = match order {
+        ChannelOrder::Bgr => mat.clone(),
+        ChannelOrder::Rgb => convert(mat, imgproc::COLOR_RGB2BGR)?,
+        ChannelOrder::Gray => convert(mat, imgproc::COLOR_GRAY2BGR)?,
+    };
+    let code = match space {
+        ColorSpace::Bgr => return Ok(bgr),
+        ColorSpace::Rgb => imgproc::COLOR_BGR2RGB,
+        ColorSpace::Hsv => imgproc::COLOR_BGR2HSV,
+        ColorSpace::Lab => imgproc::COLOR_BGR2Lab,
+        ColorSpace::YCrCb => imgproc::COLOR_BGR2YCrCb,
+        ColorSpace::Gray => imgproc::COLOR_BGR2GRAY,
+    };
+    convert(&bgr, code)
+}
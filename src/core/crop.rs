@@ -0,0 +1,306 @@
+// Crop rectangles: aspect-ratio locks, dragging and typing a crop that stays
+// inside the image, and saving every ROI as an image file of its own
+use opencv::{core::Rect, prelude::*};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::filters::encode_png;
+use crate::core::pixel_export::safe_file_part;
+use crate::core::roi_model::LabeledRoi;
+use crate::core::transform::fit_rect;
+
+/// Width to height ratio a crop is held to while it is drawn or typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AspectLock {
+    #[default]
+    Free,
+    Ratio {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl AspectLock {
+    /// Locks offered without typing a ratio
+    pub const PRESETS: [AspectLock; 4] = [
+        AspectLock::Free,
+        AspectLock::Ratio {
+            width: 1,
+            height: 1,
+        },
+        AspectLock::Ratio {
+            width: 4,
+            height: 3,
+        },
+        AspectLock::Ratio {
+            width: 16,
+            height: 9,
+        },
+    ];
+
+    /// `free` or `<width>:<height>`; a zero side is read as free.
+    pub fn from_name(name: &str) -> Option<AspectLock> {
+        if name.eq_ignore_ascii_case("free") {
+            return Some(AspectLock::Free);
+        }
+        let (width, height) = name.split_once(':')?;
+        let width: u32 = width.trim().parse().ok()?;
+        let height: u32 = height.trim().parse().ok()?;
+        Some(AspectLock::ratio(width, height))
+    }
+
+    pub fn ratio(width: u32, height: u32) -> AspectLock {
+        if width == 0 || height == 0 {
+            AspectLock::Free
+        } else {
+            AspectLock::Ratio { width, height }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            AspectLock::Free => "free".to_string(),
+            AspectLock::Ratio { width, height } => format!("{}:{}", width, height),
+        }
+    }
+
+    fn factor(&self) -> Option<f64> {
+        match self {
+            AspectLock::Free => None,
+            AspectLock::Ratio { width, height } => Some(*width as f64 / *height as f64),
+        }
+    }
+}
+
+/// Part of `rect` that lies inside the image; `None` when nothing is left.
+pub fn clip_rect(rect: &Rect, image_width: i32, image_height: i32) -> Option<Rect> {
+    let x0 = rect.x.clamp(0, image_width.max(0));
+    let y0 = rect.y.clamp(0, image_height.max(0));
+    let x1 = (rect.x + rect.width).clamp(0, image_width.max(0));
+    let y1 = (rect.y + rect.height).clamp(0, image_height.max(0));
+    (x1 > x0 && y1 > y0).then(|| Rect::new(x0, y0, x1 - x0, y1 - y0))
+}
+
+/// Crop dragged from `anchor` to `cursor`, both pixel edges in image
+/// coordinates. It grows from the anchor towards the cursor, keeps `lock`
+/// and stops at the image border rather than leaving it.
+pub fn drag_rect(
+    anchor: (i32, i32),
+    cursor: (i32, i32),
+    lock: AspectLock,
+    image_width: i32,
+    image_height: i32,
+) -> Rect {
+    let anchor = (
+        anchor.0.clamp(0, image_width),
+        anchor.1.clamp(0, image_height),
+    );
+    let (dx, dy) = (cursor.0 - anchor.0, cursor.1 - anchor.1);
+    // Room between the anchor and the border on the side being dragged to
+    let room_x = if dx < 0 {
+        anchor.0
+    } else {
+        image_width - anchor.0
+    };
+    let room_y = if dy < 0 {
+        anchor.1
+    } else {
+        image_height - anchor.1
+    };
+
+    let (width, height) = match lock.factor() {
+        None => (dx.abs().min(room_x), dy.abs().min(room_y)),
+        Some(factor) => {
+            // The axis dragged further, relative to the ratio, sets the size
+            let wanted = (dx.abs() as f64).max(dy.abs() as f64 * factor);
+            let width = wanted
+                .min(room_x as f64)
+                .min(room_y as f64 * factor)
+                .floor();
+            (width as i32, (width / factor).round() as i32)
+        }
+    };
+    Rect::new(
+        if dx < 0 { anchor.0 - width } else { anchor.0 },
+        if dy < 0 { anchor.1 - height } else { anchor.1 },
+        width,
+        height,
+    )
+}
+
+/// Typed crop brought in line with `lock` and the image. The side that was
+/// not edited follows the ratio (height unless `height_edited`), the rect
+/// shrinks about its top-left corner if it is too large, then shifts inside.
+pub fn fit_crop(
+    rect: &Rect,
+    lock: AspectLock,
+    height_edited: bool,
+    image_width: i32,
+    image_height: i32,
+) -> Option<Rect> {
+    if rect.width <= 0 || rect.height <= 0 || image_width <= 0 || image_height <= 0 {
+        return None;
+    }
+    let Some(factor) = lock.factor() else {
+        return fit_rect(rect, image_width, image_height);
+    };
+    let wanted = if height_edited {
+        rect.height as f64 * factor
+    } else {
+        rect.width as f64
+    };
+    let width = wanted
+        .min(image_width as f64)
+        .min(image_height as f64 * factor)
+        .floor()
+        .max(1.0);
+    let height = ((width / factor).round() as i32).max(1);
+    let width = width as i32;
+    Some(Rect::new(
+        rect.x.clamp(0, image_width - width),
+        rect.y.clamp(0, image_height - height),
+        width,
+        height,
+    ))
+}
+
+/// Writes the pixels under every ROI of `mat` as a PNG named
+/// `<image stem>_roi<NNN>_<label>.png`, numbered like the pixel export.
+/// ROIs outside the image are skipped. Returns the number of files written.
+pub fn export_roi_crops(
+    mat: &Mat,
+    rois: &[LabeledRoi],
+    image_path: &Path,
+    out_dir: &Path,
+) -> io::Result<usize> {
+    fs::create_dir_all(out_dir)?;
+    let stem = image_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "image".to_string());
+    let to_io = |e: opencv::Error| io::Error::other(e.to_string());
+
+    let mut written = 0;
+    for (i, roi) in rois.iter().enumerate() {
+        let Some(rect) = clip_rect(&roi.rect, mat.cols(), mat.rows()) else {
+            continue;
+        };
+        let crop = Mat::roi(mat, rect)
+            .and_then(|m| m.try_clone())
+            .map_err(to_io)?;
+        let png = encode_png(&crop).map_err(to_io)?;
+        let name = format!(
+            "{}_roi{:03}_{}.png",
+            stem,
+            i + 1,
+            safe_file_part(&roi.label)
+        );
+        fs::write(out_dir.join(name), png)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIXTEEN_NINE: AspectLock = AspectLock::Ratio {
+        width: 16,
+        height: 9,
+    };
+
+    #[test]
+    fn aspect_names_round_trip() {
+        for lock in AspectLock::PRESETS {
+            assert_eq!(AspectLock::from_name(&lock.name()), Some(lock));
+        }
+        assert_eq!(
+            AspectLock::from_name("3:2"),
+            Some(AspectLock::Ratio {
+                width: 3,
+                height: 2
+            })
+        );
+        assert_eq!(AspectLock::from_name("5:0"), Some(AspectLock::Free));
+        assert_eq!(AspectLock::from_name("wide"), None);
+    }
+
+    #[test]
+    fn clip_rect_keeps_the_part_inside() {
+        assert_eq!(
+            clip_rect(&Rect::new(50, 50, 100, 100), 80, 120),
+            Some(Rect::new(50, 50, 30, 70))
+        );
+        assert_eq!(
+            clip_rect(&Rect::new(-5, -5, 10, 10), 80, 80),
+            Some(Rect::new(0, 0, 5, 5))
+        );
+        assert_eq!(clip_rect(&Rect::new(50, 50, 100, 100), 40, 40), None);
+    }
+
+    #[test]
+    fn free_drag_works_in_every_direction() {
+        let lock = AspectLock::Free;
+        assert_eq!(
+            drag_rect((10, 10), (30, 20), lock, 100, 100),
+            Rect::new(10, 10, 20, 10)
+        );
+        assert_eq!(
+            drag_rect((10, 10), (2, 4), lock, 100, 100),
+            Rect::new(2, 4, 8, 6)
+        );
+        // Dragging past the border stops at it
+        assert_eq!(
+            drag_rect((90, 10), (140, -20), lock, 100, 100),
+            Rect::new(90, 0, 10, 10)
+        );
+    }
+
+    #[test]
+    fn locked_drag_keeps_the_ratio_inside_the_image() {
+        let square = AspectLock::Ratio {
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(
+            drag_rect((10, 10), (30, 60), square, 100, 100),
+            Rect::new(10, 10, 50, 50)
+        );
+        assert_eq!(
+            drag_rect((50, 50), (0, 45), square, 100, 100),
+            Rect::new(0, 0, 50, 50)
+        );
+        // 16:9 limited by the height left below the anchor
+        assert_eq!(
+            drag_rect((0, 82), (200, 200), SIXTEEN_NINE, 400, 100),
+            Rect::new(0, 82, 32, 18)
+        );
+    }
+
+    #[test]
+    fn typed_crops_follow_the_lock() {
+        let four_three = AspectLock::Ratio {
+            width: 4,
+            height: 3,
+        };
+        assert_eq!(
+            fit_crop(&Rect::new(10, 10, 80, 5), four_three, false, 200, 200),
+            Some(Rect::new(10, 10, 80, 60))
+        );
+        assert_eq!(
+            fit_crop(&Rect::new(10, 10, 5, 30), four_three, true, 200, 200),
+            Some(Rect::new(10, 10, 40, 30))
+        );
+        // Too large for the image: shrinks, then shifts inside
+        assert_eq!(
+            fit_crop(&Rect::new(150, 150, 320, 1), SIXTEEN_NINE, false, 200, 200),
+            Some(Rect::new(0, 87, 200, 113))
+        );
+        assert_eq!(
+            fit_crop(&Rect::new(0, 0, 0, 10), AspectLock::Free, false, 10, 10),
+            None
+        );
+    }
+}
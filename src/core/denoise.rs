@@ -0,0 +1,68 @@
+// Edge-aware denoising and unsharp-mask sharpening
+//
+// Median and bilateral filtering are single `imgproc` calls made straight
+// from `Filter::apply`; the rest, and their toolbox defaults, live here.
+use opencv::{
+    core::{add_weighted, AlgorithmHint, Size, BORDER_DEFAULT},
+    imgproc, photo,
+    prelude::*,
+};
+
+pub const GAUSSIAN_KSIZE: i32 = 5;
+pub const MEDIAN_KSIZE: i32 = 5;
+/// Diameter, colour sigma and space sigma
+pub const BILATERAL_PARAMS: (i32, f64, f64) = (9, 75.0, 75.0);
+/// Luminance strength, colour strength, template window and search window
+pub const NL_MEANS_PARAMS: (f64, f64, i32, i32) = (10.0, 10.0, 7, 21);
+/// Blur sigma and amount
+pub const UNSHARP_PARAMS: (f64, f64) = (1.5, 1.0);
+
+/// Gaussian blur with a `ksize` x `ksize` kernel (0 to derive it from `sigma`);
+/// a `sigma` of 0 is derived from `ksize` instead
+pub fn gaussian_blur(input: &Mat, ksize: i32, sigma: f64) -> opencv::Result<Mat> {
+    let mut output = Mat::default();
+    imgproc::gaussian_blur(
+        input,
+        &mut output,
+        Size::new(ksize, ksize),
+        sigma,
+        sigma,
+        BORDER_DEFAULT,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(output)
+}
+
+/// Non-local means: the coloured variant for BGR images, the plain one for
+/// grayscale. `h` filters luminance, `h_color` the colour components.
+pub fn nl_means(
+    input: &Mat,
+    h: f64,
+    h_color: f64,
+    template: i32,
+    search: i32,
+) -> opencv::Result<Mat> {
+    let mut output = Mat::default();
+    if input.channels() == 1 {
+        photo::fast_nl_means_denoising(input, &mut output, h as f32, template, search)?;
+    } else {
+        photo::fast_nl_means_denoising_colored(
+            input,
+            &mut output,
+            h as f32,
+            h_color as f32,
+            template,
+            search,
+        )?;
+    }
+    Ok(output)
+}
+
+/// `input + amount * (input - blurred)`, where `blurred` is a Gaussian blur
+/// with `sigma`
+pub fn unsharp_mask(input: &Mat, sigma: f64, amount: f64) -> opencv::Result<Mat> {
+    let blurred = gaussian_blur(input, 0, sigma)?;
+    let mut output = Mat::default();
+    add_weighted(input, 1.0 + amount, &blurred, -amount, 0.0, &mut output, -1)?;
+    Ok(output)
+}
@@ -0,0 +1,154 @@
+// Output image formats and encoder options for saving processed images
+use opencv::{
+    core::Vector,
+    imgcodecs::{
+        imencode, IMWRITE_JPEG_QUALITY, IMWRITE_PNG_COMPRESSION, IMWRITE_TIFF_COMPRESSION,
+        IMWRITE_WEBP_QUALITY,
+    },
+    prelude::*,
+};
+
+pub const DEFAULT_QUALITY: i32 = 95;
+pub const DEFAULT_PNG_COMPRESSION: i32 = 3;
+// libtiff compression codes
+const TIFF_COMPRESSION_NONE: i32 = 1;
+const TIFF_COMPRESSION_LZW: i32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Bmp,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 5] = [
+        ImageFormat::Png,
+        ImageFormat::Jpeg,
+        ImageFormat::WebP,
+        ImageFormat::Tiff,
+        ImageFormat::Bmp,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::WebP => "WebP",
+            ImageFormat::Tiff => "TIFF",
+            ImageFormat::Bmp => "BMP",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.name() == name)
+    }
+
+    /// File extension without the dot; also selects the `imencode` codec
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Tiff => "tif",
+            ImageFormat::Bmp => "bmp",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "webp" => Some(ImageFormat::WebP),
+            "tif" | "tiff" => Some(ImageFormat::Tiff),
+            "bmp" => Some(ImageFormat::Bmp),
+            _ => None,
+        }
+    }
+}
+
+/// Format plus the encoder settings that apply to it; the others are ignored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodeOptions {
+    pub format: ImageFormat,
+    /// JPEG and WebP quality, 1-100
+    pub quality: i32,
+    /// PNG zlib level, 0-9
+    pub png_compression: i32,
+    /// LZW instead of uncompressed TIFF
+    pub tiff_lzw: bool,
+}
+
+impl EncodeOptions {
+    pub fn new(format: ImageFormat) -> Self {
+        EncodeOptions {
+            format,
+            quality: DEFAULT_QUALITY,
+            png_compression: DEFAULT_PNG_COMPRESSION,
+            tiff_lzw: true,
+        }
+    }
+
+    /// `imencode` parameters as flag/value pairs
+    pub fn imencode_params(&self) -> Vec<i32> {
+        match self.format {
+            ImageFormat::Png => vec![IMWRITE_PNG_COMPRESSION, self.png_compression.clamp(0, 9)],
+            ImageFormat::Jpeg => vec![IMWRITE_JPEG_QUALITY, self.quality.clamp(1, 100)],
+            ImageFormat::WebP => vec![IMWRITE_WEBP_QUALITY, self.quality.clamp(1, 100)],
+            ImageFormat::Tiff => vec![
+                IMWRITE_TIFF_COMPRESSION,
+                if self.tiff_lzw {
+                    TIFF_COMPRESSION_LZW
+                } else {
+                    TIFF_COMPRESSION_NONE
+                },
+            ],
+            ImageFormat::Bmp => vec![],
+        }
+    }
+}
+
+pub fn encode_image(mat: &Mat, options: &EncodeOptions) -> opencv::Result<Vec<u8>> {
+    let mut buf = Vector::new();
+    imencode(
+        &format!(".{}", options.format.extension()),
+        mat,
+        &mut buf,
+        &Vector::from_slice(&options.imencode_params()),
+    )?;
+    Ok(buf.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions_round_trip() {
+        for format in ImageFormat::ALL {
+            assert_eq!(
+                ImageFormat::from_extension(format.extension()),
+                Some(format)
+            );
+            assert_eq!(ImageFormat::from_name(format.name()), Some(format));
+        }
+        assert_eq!(ImageFormat::from_extension("JPEG"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_extension("gif"), None);
+    }
+
+    #[test]
+    fn passes_only_the_options_of_the_format() {
+        let mut options = EncodeOptions::new(ImageFormat::Jpeg);
+        options.quality = 150;
+        assert_eq!(options.imencode_params(), [IMWRITE_JPEG_QUALITY, 100]);
+        options.format = ImageFormat::Png;
+        assert_eq!(
+            options.imencode_params(),
+            [IMWRITE_PNG_COMPRESSION, DEFAULT_PNG_COMPRESSION]
+        );
+        options.format = ImageFormat::Bmp;
+        assert!(options.imencode_params().is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+// Carrying EXIF metadata from the original file over to a saved result
+//
+// `imencode` drops all metadata, so the raw EXIF block (a little TIFF
+// structure) is pulled out of the original JPEG, PNG or WebP and spliced into
+// the encoded output. Only JPEG (APP1 segment) and PNG (eXIf chunk) outputs
+// can carry it.
+use crate::core::encode::ImageFormat;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const ORIENTATION_TAG: u16 = 0x0112;
+
+pub fn supports_exif(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Jpeg | ImageFormat::Png)
+}
+
+fn be16(bytes: &[u8], at: usize) -> Option<usize> {
+    let b = bytes.get(at..at + 2)?;
+    Some(u16::from_be_bytes([b[0], b[1]]) as usize)
+}
+
+fn be32(bytes: &[u8], at: usize) -> Option<usize> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+fn le32(bytes: &[u8], at: usize) -> Option<usize> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// JPEG segments before the image data as (marker, data offset, data length)
+fn jpeg_segments(bytes: &[u8]) -> Vec<(u8, usize, usize)> {
+    let mut segments = vec![];
+    let mut pos = 2;
+    while bytes.get(pos) == Some(&0xFF) {
+        let Some(&marker) = bytes.get(pos + 1) else {
+            break;
+        };
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let Some(len) = be16(bytes, pos + 2).filter(|len| *len >= 2) else {
+            break;
+        };
+        segments.push((marker, pos + 4, len - 2));
+        pos += 2 + len;
+    }
+    segments
+}
+
+/// PNG chunks as (type, data offset, data length)
+fn png_chunks(bytes: &[u8]) -> Vec<([u8; 4], usize, usize)> {
+    let mut chunks = vec![];
+    let mut pos = PNG_SIGNATURE.len();
+    while let (Some(len), Some(kind)) = (be32(bytes, pos), bytes.get(pos + 4..pos + 8)) {
+        let kind = [kind[0], kind[1], kind[2], kind[3]];
+        chunks.push((kind, pos + 8, len));
+        if &kind == b"IEND" {
+            break;
+        }
+        pos += 12 + len;
+    }
+    chunks
+}
+
+/// The raw EXIF block (starting at the TIFF header) of a JPEG, PNG or WebP file
+pub fn read_exif(bytes: &[u8]) -> Option<Vec<u8>> {
+    let data = if bytes.starts_with(&[0xFF, 0xD8]) {
+        jpeg_segments(bytes)
+            .into_iter()
+            .filter(|(marker, _, _)| *marker == 0xE1)
+            .filter_map(|(_, at, len)| bytes.get(at..at + len))
+            .find(|data| data.starts_with(EXIF_HEADER))?
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        png_chunks(bytes)
+            .into_iter()
+            .find(|(kind, _, _)| kind == b"eXIf")
+            .and_then(|(_, at, len)| bytes.get(at..at + len))?
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        let mut pos = 12;
+        loop {
+            let kind = bytes.get(pos..pos + 4)?;
+            let len = le32(bytes, pos + 4)?;
+            if kind == b"EXIF" {
+                break bytes.get(pos + 8..pos + 8 + len)?;
+            }
+            pos += 8 + len + len % 2;
+        }
+    } else {
+        return None;
+    };
+    // Some writers keep the JPEG-style header in PNG and WebP too
+    let data = data.strip_prefix(EXIF_HEADER).unwrap_or(data);
+    (!data.is_empty()).then(|| data.to_vec())
+}
+
+/// Sets the IFD0 orientation tag to 1 (upright). Decoding already applied the
+/// original orientation to the pixels, so keeping it would rotate them twice.
+pub fn reset_orientation(exif: &mut [u8]) {
+    let little_endian = match exif.get(0..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return,
+    };
+    let read16 = |exif: &[u8], at: usize| -> Option<u16> {
+        let b = exif.get(at..at + 2)?;
+        Some(if little_endian {
+            u16::from_le_bytes([b[0], b[1]])
+        } else {
+            u16::from_be_bytes([b[0], b[1]])
+        })
+    };
+    let ifd = match exif.get(4..8) {
+        Some(b) if little_endian => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize,
+        Some(b) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize,
+        None => return,
+    };
+    let Some(count) = read16(exif, ifd) else {
+        return;
+    };
+    for i in 0..count as usize {
+        let entry = ifd + 2 + i * 12;
+        if read16(exif, entry) == Some(ORIENTATION_TAG) {
+            let one = if little_endian {
+                1u16.to_le_bytes()
+            } else {
+                1u16.to_be_bytes()
+            };
+            if let Some(value) = exif.get_mut(entry + 8..entry + 10) {
+                value.copy_from_slice(&one);
+            }
+            return;
+        }
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// `encoded` with `exif` added, or `None` if the format cannot carry it
+pub fn embed_exif(encoded: &[u8], format: ImageFormat, exif: &[u8]) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => {
+            if !encoded.starts_with(&[0xFF, 0xD8]) {
+                return None;
+            }
+            let len = u16::try_from(2 + EXIF_HEADER.len() + exif.len()).ok()?;
+            // After the JFIF APP0 segment when there is one, else right after SOI
+            let at = match jpeg_segments(encoded).first() {
+                Some((0xE0, data, len)) => data + len,
+                _ => 2,
+            };
+            let mut out = Vec::with_capacity(encoded.len() + len as usize + 2);
+            out.extend_from_slice(&encoded[..at]);
+            out.extend_from_slice(&[0xFF, 0xE1]);
+            out.extend_from_slice(&len.to_be_bytes());
+            out.extend_from_slice(EXIF_HEADER);
+            out.extend_from_slice(exif);
+            out.extend_from_slice(&encoded[at..]);
+            Some(out)
+        }
+        ImageFormat::Png => {
+            if !encoded.starts_with(PNG_SIGNATURE) {
+                return None;
+            }
+            // eXIf has to come before the image data
+            let (_, idat, _) = png_chunks(encoded)
+                .into_iter()
+                .find(|(kind, _, _)| kind == b"IDAT")?;
+            let at = idat - 8;
+            let mut chunk = b"eXIf".to_vec();
+            chunk.extend_from_slice(exif);
+            let mut out = Vec::with_capacity(encoded.len() + exif.len() + 12);
+            out.extend_from_slice(&encoded[..at]);
+            out.extend_from_slice(&u32::try_from(exif.len()).ok()?.to_be_bytes());
+            out.extend_from_slice(&chunk);
+            out.extend_from_slice(&crc32(&chunk).to_be_bytes());
+            out.extend_from_slice(&encoded[at..]);
+            Some(out)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Little-endian TIFF header with one IFD0 entry: orientation = 6 (90° CW)
+    fn sample_exif() -> Vec<u8> {
+        let mut exif = b"II*\0\x08\0\0\0".to_vec();
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        exif.extend_from_slice(&ORIENTATION_TAG.to_le_bytes());
+        exif.extend_from_slice(&3u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&[6, 0, 0, 0]);
+        exif.extend_from_slice(&0u32.to_le_bytes());
+        exif
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x06];
+        jpeg.extend_from_slice(b"JFIF");
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut body = kind.to_vec();
+        body.extend_from_slice(data);
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(&body);
+        chunk.extend_from_slice(&crc32(&body).to_be_bytes());
+        chunk
+    }
+
+    fn png() -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(png_chunk(b"IHDR", &[0; 13]));
+        png.extend(png_chunk(b"IDAT", &[1, 2, 3]));
+        png.extend(png_chunk(b"IEND", &[]));
+        png
+    }
+
+    #[test]
+    fn crc_matches_png_reference() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn round_trips_through_jpeg_and_png() {
+        let exif = sample_exif();
+        for (encoded, format) in [(jpeg(), ImageFormat::Jpeg), (png(), ImageFormat::Png)] {
+            assert_eq!(read_exif(&encoded), None);
+            let tagged = embed_exif(&encoded, format, &exif).unwrap();
+            assert_eq!(read_exif(&tagged), Some(exif.clone()));
+            assert_eq!(
+                tagged.len(),
+                encoded.len() + exif.len() + 10 + 2 * (format == ImageFormat::Png) as usize
+            );
+        }
+        assert_eq!(embed_exif(&png(), ImageFormat::Bmp, &exif), None);
+    }
+
+    #[test]
+    fn jpeg_keeps_jfif_first() {
+        let tagged = embed_exif(&jpeg(), ImageFormat::Jpeg, &sample_exif()).unwrap();
+        assert_eq!(&tagged[2..4], &[0xFF, 0xE0]);
+        assert_eq!(&tagged[10..12], &[0xFF, 0xE1]);
+    }
+
+    #[test]
+    fn reads_webp_exif_chunk() {
+        let exif = sample_exif();
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8 ".to_vec();
+        webp.extend_from_slice(&3u32.to_le_bytes());
+        webp.extend_from_slice(&[9, 9, 9, 0]);
+        webp.extend_from_slice(b"EXIF");
+        webp.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+        webp.extend_from_slice(&exif);
+        assert_eq!(read_exif(&webp), Some(exif));
+    }
+
+    #[test]
+    fn resets_orientation_in_both_byte_orders() {
+        let mut exif = sample_exif();
+        reset_orientation(&mut exif);
+        assert_eq!(exif[8 + 2 + 8], 1);
+
+        let mut big = b"MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x08\0\0".to_vec();
+        reset_orientation(&mut big);
+        assert_eq!(&big[18..20], &[0, 1]);
+    }
+}
@@ -0,0 +1,1430 @@
+// Image operations shared by the ImageUploader toolbox and `imgtool`
+//
+// Both run exactly this code on a BGR Mat decoded with `IMREAD_COLOR`, so
+// their results have the same pixels. The files may still differ, since the
+// GUI's Save as has its own encoder settings and can copy the EXIF along.
+use opencv::{
+    core::{
+        bitwise_not, no_array, rotate, AlgorithmHint, Point, Point2f, Rect, Size, StsBadArg,
+        Vector, BORDER_DEFAULT, ROTATE_180, ROTATE_90_CLOCKWISE, ROTATE_90_COUNTERCLOCKWISE,
+    },
+    imgcodecs::{imdecode, imencode, IMREAD_COLOR},
+    imgproc,
+    prelude::*,
+};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::core::crop::clip_rect;
+use crate::core::denoise::{
+    gaussian_blur, nl_means, unsharp_mask, BILATERAL_PARAMS, GAUSSIAN_KSIZE, MEDIAN_KSIZE,
+    NL_MEANS_PARAMS, UNSHARP_PARAMS,
+};
+use crate::core::geometry::{
+    flip_image, flipping, perspective, quarter_turns, rectify, rotate_by, rotation, scaling,
+    translation, warp_size, BorderMode, FlipAxis, Homography,
+};
+use crate::core::morphology::{morphology, KernelShape, MorphOp, MORPH_ITERATIONS, MORPH_SIZE};
+use crate::core::threshold::{
+    threshold, ThresholdMethod, ADAPTIVE_BLOCK_SIZE, ADAPTIVE_C, THRESHOLD_VALUE,
+};
+use crate::core::tone::{
+    apply_table, brightness_contrast_table, clahe, equalize, gamma_table, levels_table,
+    ToneChannels, CLAHE_CLIP_LIMIT, CLAHE_TILES,
+};
+
+// Parameters the toolbox buttons use
+pub const BLUR_KSIZE: i32 = 15;
+pub const RESIZE_SIZE: (i32, i32) = (200, 200);
+pub const CANNY_THRESHOLDS: (f64, f64) = (100.0, 200.0);
+pub const CANNY_APERTURE: i32 = 3;
+pub const CROP_RECT: (i32, i32, i32, i32) = (50, 50, 100, 100);
+
+/// Range of a tunable parameter, as the toolbox sliders show it. A parameter
+/// with `options` is a choice whose value is the index of the chosen option.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+    pub options: &'static [&'static str],
+}
+
+impl ParamSpec {
+    pub const fn new(name: &'static str, min: f64, max: f64, step: f64) -> Self {
+        ParamSpec {
+            name,
+            min,
+            max,
+            step,
+            options: &[],
+        }
+    }
+
+    pub const fn choice(name: &'static str, options: &'static [&'static str]) -> Self {
+        ParamSpec {
+            name,
+            min: 0.0,
+            max: (options.len() - 1) as f64,
+            step: 1.0,
+            options,
+        }
+    }
+
+    /// Clamps `value` into range and snaps it onto the step grid
+    pub fn snap(&self, value: f64) -> f64 {
+        let steps = ((value - self.min) / self.step).round();
+        let snapped = (self.min + steps * self.step).clamp(self.min, self.max);
+        // Drop float noise such as 1.5000000000000002 so specs print cleanly
+        (snapped * 1e9).round() / 1e9
+    }
+}
+
+const KSIZE: ParamSpec = ParamSpec::new("kernel size", 1.0, 99.0, 1.0);
+const WIDTH: ParamSpec = ParamSpec::new("width", 1.0, 4096.0, 1.0);
+const HEIGHT: ParamSpec = ParamSpec::new("height", 1.0, 4096.0, 1.0);
+const LOW_THRESHOLD: ParamSpec = ParamSpec::new("low threshold", 0.0, 1000.0, 1.0);
+const HIGH_THRESHOLD: ParamSpec = ParamSpec::new("high threshold", 0.0, 1000.0, 1.0);
+const APERTURE: ParamSpec = ParamSpec::new("aperture", 3.0, 7.0, 2.0);
+const METHOD: ParamSpec = ParamSpec::choice("method", &ThresholdMethod::NAMES);
+const LEVEL: ParamSpec = ParamSpec::new("threshold", 0.0, 255.0, 1.0);
+const BLOCK_SIZE: ParamSpec = ParamSpec::new("block size", 3.0, 255.0, 2.0);
+const OFFSET_C: ParamSpec = ParamSpec::new("offset C", -50.0, 50.0, 0.5);
+const INVERTED: ParamSpec = ParamSpec::choice("inverted", &["no", "yes"]);
+const MORPH_OP: ParamSpec = ParamSpec::choice("operation", &MorphOp::NAMES);
+const SHAPE: ParamSpec = ParamSpec::choice("element shape", &KernelShape::NAMES);
+const ELEMENT_SIZE: ParamSpec = ParamSpec::new("element size", 1.0, 99.0, 2.0);
+const ITERATIONS: ParamSpec = ParamSpec::new("iterations", 1.0, 20.0, 1.0);
+const ODD_KSIZE: ParamSpec = ParamSpec::new("kernel size", 1.0, 99.0, 2.0);
+const MEDIAN_SIZE: ParamSpec = ParamSpec::new("kernel size", 3.0, 99.0, 2.0);
+const SIGMA: ParamSpec = ParamSpec::new("sigma (0 = auto)", 0.0, 20.0, 0.1);
+const DIAMETER: ParamSpec = ParamSpec::new("diameter", 1.0, 25.0, 1.0);
+const SIGMA_COLOR: ParamSpec = ParamSpec::new("color sigma", 1.0, 200.0, 1.0);
+const SIGMA_SPACE: ParamSpec = ParamSpec::new("space sigma", 1.0, 200.0, 1.0);
+const STRENGTH: ParamSpec = ParamSpec::new("strength", 1.0, 50.0, 0.5);
+const COLOR_STRENGTH: ParamSpec = ParamSpec::new("color strength", 1.0, 50.0, 0.5);
+const TEMPLATE_WINDOW: ParamSpec = ParamSpec::new("template window", 3.0, 15.0, 2.0);
+const SEARCH_WINDOW: ParamSpec = ParamSpec::new("search window", 7.0, 41.0, 2.0);
+const RADIUS: ParamSpec = ParamSpec::new("radius sigma", 0.1, 20.0, 0.1);
+const AMOUNT: ParamSpec = ParamSpec::new("amount", 0.0, 5.0, 0.05);
+const CHANNELS: ParamSpec = ParamSpec::choice("apply to", &ToneChannels::NAMES);
+const CLIP_LIMIT: ParamSpec = ParamSpec::new("clip limit", 0.5, 40.0, 0.5);
+const TILES: ParamSpec = ParamSpec::new("tile grid", 1.0, 32.0, 1.0);
+const GAMMA: ParamSpec = ParamSpec::new("gamma", 0.1, 5.0, 0.05);
+const BRIGHTNESS: ParamSpec = ParamSpec::new("brightness", -255.0, 255.0, 1.0);
+const CONTRAST: ParamSpec = ParamSpec::new("contrast", 0.0, 3.0, 0.05);
+const BLACK_POINT: ParamSpec = ParamSpec::new("black point", 0.0, 254.0, 1.0);
+const WHITE_POINT: ParamSpec = ParamSpec::new("white point", 1.0, 255.0, 1.0);
+const AXIS: ParamSpec = ParamSpec::choice("axis", &FlipAxis::NAMES);
+const ANGLE: ParamSpec = ParamSpec::new("angle (counterclockwise)", -180.0, 180.0, 0.5);
+const BORDER: ParamSpec = ParamSpec::choice("border", &BorderMode::NAMES);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Box blur with a `ksize` x `ksize` kernel
+    Blur {
+        ksize: i32,
+    },
+    Resize {
+        width: i32,
+        height: i32,
+    },
+    Grayscale,
+    Invert,
+    /// Canny edges of the grayscale image; `aperture` is the Sobel size (3, 5 or 7)
+    Canny {
+        low: f64,
+        high: f64,
+        aperture: i32,
+    },
+    /// Part of `rect` inside the image; fails only if none of it is
+    Crop {
+        rect: Rect,
+    },
+    /// 90° clockwise
+    Rotate90,
+    Rotate180,
+    /// 90° counterclockwise
+    Rotate270,
+    Flip {
+        axis: FlipAxis,
+    },
+    /// Free rotation in degrees counterclockwise, on a canvas grown to fit,
+    /// with `border` filling the uncovered corners
+    RotateBy {
+        angle: f64,
+        border: BorderMode,
+    },
+    /// Rectifies the quadrilateral with these corners (top-left, top-right,
+    /// bottom-right, bottom-left, in input pixels) into an upright image
+    Perspective {
+        corners: [Point2f; 4],
+    },
+    /// Binary mask of the grayscale image; see [`threshold`] for the parameters
+    Threshold {
+        method: ThresholdMethod,
+        value: f64,
+        block_size: i32,
+        c: f64,
+        inverted: bool,
+    },
+    /// `op` with a `size` x `size` structuring element, `iterations` times
+    Morph {
+        op: MorphOp,
+        shape: KernelShape,
+        size: i32,
+        iterations: i32,
+    },
+    /// `sigma` 0 derives it from `ksize`
+    GaussianBlur {
+        ksize: i32,
+        sigma: f64,
+    },
+    Median {
+        ksize: i32,
+    },
+    /// Edge-preserving smoothing over a `diameter` neighbourhood
+    Bilateral {
+        diameter: i32,
+        sigma_color: f64,
+        sigma_space: f64,
+    },
+    /// Non-local means denoising; see [`nl_means`]
+    NlMeans {
+        h: f64,
+        h_color: f64,
+        template: i32,
+        search: i32,
+    },
+    /// Unsharp mask; see [`unsharp_mask`]
+    Sharpen {
+        sigma: f64,
+        amount: f64,
+    },
+    Equalize {
+        channels: ToneChannels,
+    },
+    /// Contrast-limited adaptive equalization over a `tiles` x `tiles` grid
+    Clahe {
+        clip_limit: f64,
+        tiles: i32,
+        channels: ToneChannels,
+    },
+    /// Above 1 brightens the midtones
+    Gamma {
+        gamma: f64,
+        channels: ToneChannels,
+    },
+    /// `contrast * (v - 128) + 128 + brightness`
+    BrightnessContrast {
+        brightness: f64,
+        contrast: f64,
+        channels: ToneChannels,
+    },
+    /// Stretches `black`..`white` over the full range; `gamma` bends the midtones
+    Levels {
+        black: f64,
+        white: f64,
+        gamma: f64,
+        channels: ToneChannels,
+    },
+}
+
+impl Filter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Blur { .. } => "blur",
+            Filter::Resize { .. } => "resize",
+            Filter::Grayscale => "grayscale",
+            Filter::Invert => "invert",
+            Filter::Canny { .. } => "canny",
+            Filter::Crop { .. } => "crop",
+            Filter::Rotate90 => "rotate",
+            Filter::Rotate180 => "rotate180",
+            Filter::Rotate270 => "rotate270",
+            Filter::Flip { .. } => "flip",
+            Filter::RotateBy { .. } => "angle",
+            Filter::Perspective { .. } => "warp",
+            Filter::Threshold { .. } => "threshold",
+            Filter::Morph { .. } => "morph",
+            Filter::GaussianBlur { .. } => "gaussian",
+            Filter::Median { .. } => "median",
+            Filter::Bilateral { .. } => "bilateral",
+            Filter::NlMeans { .. } => "nlmeans",
+            Filter::Sharpen { .. } => "sharpen",
+            Filter::Equalize { .. } => "equalize",
+            Filter::Clahe { .. } => "clahe",
+            Filter::Gamma { .. } => "gamma",
+            Filter::BrightnessContrast { .. } => "brightness",
+            Filter::Levels { .. } => "levels",
+        }
+    }
+
+    /// The parameters the toolbox exposes for tuning, with their current values
+    pub fn params(&self) -> Vec<(ParamSpec, f64)> {
+        match self {
+            Filter::Blur { ksize } => vec![(KSIZE, *ksize as f64)],
+            Filter::Resize { width, height } => {
+                vec![(WIDTH, *width as f64), (HEIGHT, *height as f64)]
+            }
+            Filter::Canny {
+                low,
+                high,
+                aperture,
+            } => vec![
+                (LOW_THRESHOLD, *low),
+                (HIGH_THRESHOLD, *high),
+                (APERTURE, *aperture as f64),
+            ],
+            Filter::Threshold {
+                method,
+                value,
+                block_size,
+                c,
+                inverted,
+            } => vec![
+                (METHOD, *method as usize as f64),
+                (LEVEL, *value),
+                (BLOCK_SIZE, *block_size as f64),
+                (OFFSET_C, *c),
+                (INVERTED, *inverted as u8 as f64),
+            ],
+            Filter::Morph {
+                op,
+                shape,
+                size,
+                iterations,
+            } => vec![
+                (MORPH_OP, *op as usize as f64),
+                (SHAPE, *shape as usize as f64),
+                (ELEMENT_SIZE, *size as f64),
+                (ITERATIONS, *iterations as f64),
+            ],
+            Filter::GaussianBlur { ksize, sigma } => {
+                vec![(ODD_KSIZE, *ksize as f64), (SIGMA, *sigma)]
+            }
+            Filter::Median { ksize } => vec![(MEDIAN_SIZE, *ksize as f64)],
+            Filter::Bilateral {
+                diameter,
+                sigma_color,
+                sigma_space,
+            } => vec![
+                (DIAMETER, *diameter as f64),
+                (SIGMA_COLOR, *sigma_color),
+                (SIGMA_SPACE, *sigma_space),
+            ],
+            Filter::NlMeans {
+                h,
+                h_color,
+                template,
+                search,
+            } => vec![
+                (STRENGTH, *h),
+                (COLOR_STRENGTH, *h_color),
+                (TEMPLATE_WINDOW, *template as f64),
+                (SEARCH_WINDOW, *search as f64),
+            ],
+            Filter::Sharpen { sigma, amount } => vec![(RADIUS, *sigma), (AMOUNT, *amount)],
+            Filter::Equalize { channels } => vec![(CHANNELS, *channels as usize as f64)],
+            Filter::Clahe {
+                clip_limit,
+                tiles,
+                channels,
+            } => vec![
+                (CLIP_LIMIT, *clip_limit),
+                (TILES, *tiles as f64),
+                (CHANNELS, *channels as usize as f64),
+            ],
+            Filter::Gamma { gamma, channels } => {
+                vec![(GAMMA, *gamma), (CHANNELS, *channels as usize as f64)]
+            }
+            Filter::BrightnessContrast {
+                brightness,
+                contrast,
+                channels,
+            } => vec![
+                (BRIGHTNESS, *brightness),
+                (CONTRAST, *contrast),
+                (CHANNELS, *channels as usize as f64),
+            ],
+            Filter::Levels {
+                black,
+                white,
+                gamma,
+                channels,
+            } => vec![
+                (BLACK_POINT, *black),
+                (WHITE_POINT, *white),
+                (GAMMA, *gamma),
+                (CHANNELS, *channels as usize as f64),
+            ],
+            Filter::Flip { axis } => vec![(AXIS, *axis as usize as f64)],
+            Filter::RotateBy { angle, border } => {
+                vec![(ANGLE, *angle), (BORDER, *border as usize as f64)]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Copy with the parameter at `index` (as listed by `params`) set to `value`,
+    /// clamped and snapped to what the operation accepts
+    pub fn with_param(&self, index: usize, value: f64) -> Filter {
+        let mut filter = self.clone();
+        let Some((spec, _)) = self.params().get(index).copied() else {
+            return filter;
+        };
+        let value = spec.snap(value);
+        match (&mut filter, index) {
+            (Filter::Blur { ksize }, 0) => *ksize = value as i32,
+            (Filter::Resize { width, .. }, 0) => *width = value as i32,
+            (Filter::Resize { height, .. }, 1) => *height = value as i32,
+            (Filter::Canny { low, .. }, 0) => *low = value,
+            (Filter::Canny { high, .. }, 1) => *high = value,
+            (Filter::Canny { aperture, .. }, 2) => *aperture = value as i32,
+            (Filter::Threshold { method, .. }, 0) => *method = ThresholdMethod::ALL[value as usize],
+            (Filter::Threshold { value: level, .. }, 1) => *level = value,
+            (Filter::Threshold { block_size, .. }, 2) => *block_size = value as i32,
+            (Filter::Threshold { c, .. }, 3) => *c = value,
+            (Filter::Threshold { inverted, .. }, 4) => *inverted = value != 0.0,
+            (Filter::Morph { op, .. }, 0) => *op = MorphOp::ALL[value as usize],
+            (Filter::Morph { shape, .. }, 1) => *shape = KernelShape::ALL[value as usize],
+            (Filter::Morph { size, .. }, 2) => *size = value as i32,
+            (Filter::Morph { iterations, .. }, 3) => *iterations = value as i32,
+            (Filter::GaussianBlur { ksize, .. }, 0) => *ksize = value as i32,
+            (Filter::GaussianBlur { sigma, .. }, 1) => *sigma = value,
+            (Filter::Median { ksize }, 0) => *ksize = value as i32,
+            (Filter::Bilateral { diameter, .. }, 0) => *diameter = value as i32,
+            (Filter::Bilateral { sigma_color, .. }, 1) => *sigma_color = value,
+            (Filter::Bilateral { sigma_space, .. }, 2) => *sigma_space = value,
+            (Filter::NlMeans { h, .. }, 0) => *h = value,
+            (Filter::NlMeans { h_color, .. }, 1) => *h_color = value,
+            (Filter::NlMeans { template, .. }, 2) => *template = value as i32,
+            (Filter::NlMeans { search, .. }, 3) => *search = value as i32,
+            (Filter::Sharpen { sigma, .. }, 0) => *sigma = value,
+            (Filter::Sharpen { amount, .. }, 1) => *amount = value,
+            (Filter::Equalize { channels }, 0)
+            | (Filter::Clahe { channels, .. }, 2)
+            | (Filter::Gamma { channels, .. }, 1)
+            | (Filter::BrightnessContrast { channels, .. }, 2)
+            | (Filter::Levels { channels, .. }, 3) => *channels = ToneChannels::ALL[value as usize],
+            (Filter::Clahe { clip_limit, .. }, 0) => *clip_limit = value,
+            (Filter::Clahe { tiles, .. }, 1) => *tiles = value as i32,
+            (Filter::Gamma { gamma, .. }, 0) | (Filter::Levels { gamma, .. }, 2) => *gamma = value,
+            (Filter::BrightnessContrast { brightness, .. }, 0) => *brightness = value,
+            (Filter::BrightnessContrast { contrast, .. }, 1) => *contrast = value,
+            // Black stays below white, so the step always parses back
+            (Filter::Levels { black, white, .. }, 0) => *black = value.min(*white - 1.0),
+            (Filter::Levels { black, white, .. }, 1) => *white = value.max(*black + 1.0),
+            (Filter::Flip { axis }, 0) => *axis = FlipAxis::ALL[value as usize],
+            (Filter::RotateBy { angle, .. }, 0) => *angle = value,
+            (Filter::RotateBy { border, .. }, 1) => *border = BorderMode::ALL[value as usize],
+            _ => {}
+        }
+        filter
+    }
+
+    pub fn apply(&self, input: &Mat) -> opencv::Result<Mat> {
+        let mut output = Mat::default();
+        match self {
+            Filter::Blur { ksize } => imgproc::blur(
+                input,
+                &mut output,
+                Size::new(*ksize, *ksize),
+                Point::new(-1, -1),
+                BORDER_DEFAULT,
+            )?,
+            Filter::Resize { width, height } => imgproc::resize(
+                input,
+                &mut output,
+                Size::new(*width, *height),
+                0.0,
+                0.0,
+                imgproc::INTER_LINEAR,
+            )?,
+            Filter::Grayscale => return to_gray(input),
+            Filter::Invert => bitwise_not(input, &mut output, &no_array())?,
+            Filter::Canny {
+                low,
+                high,
+                aperture,
+            } => imgproc::canny(&to_gray(input)?, &mut output, *low, *high, *aperture, false)?,
+            Filter::Crop { rect } => {
+                let inside = clip_rect(rect, input.cols(), input.rows()).ok_or_else(|| {
+                    opencv::Error::new(
+                        StsBadArg,
+                        format!(
+                            "crop {}x{} at ({}, {}) lies outside the {}x{} image",
+                            rect.width,
+                            rect.height,
+                            rect.x,
+                            rect.y,
+                            input.cols(),
+                            input.rows()
+                        ),
+                    )
+                })?;
+                return Mat::roi(input, inside)?.try_clone();
+            }
+            Filter::Rotate90 => rotate(input, &mut output, ROTATE_90_CLOCKWISE)?,
+            Filter::Rotate180 => rotate(input, &mut output, ROTATE_180)?,
+            Filter::Rotate270 => rotate(input, &mut output, ROTATE_90_COUNTERCLOCKWISE)?,
+            Filter::Flip { axis } => return flip_image(input, *axis),
+            Filter::RotateBy { angle, border } => return rotate_by(input, *angle, *border),
+            Filter::Perspective { corners } => return rectify(input, corners),
+            Filter::Threshold {
+                method,
+                value,
+                block_size,
+                c,
+                inverted,
+            } => {
+                let gray = to_gray(input)?;
+                return Ok(threshold(&gray, *method, *value, *block_size, *c, *inverted)?.0);
+            }
+            Filter::Morph {
+                op,
+                shape,
+                size,
+                iterations,
+            } => return morphology(input, *op, *shape, *size, *iterations),
+            Filter::GaussianBlur { ksize, sigma } => return gaussian_blur(input, *ksize, *sigma),
+            Filter::Median { ksize } => imgproc::median_blur(input, &mut output, *ksize)?,
+            Filter::Bilateral {
+                diameter,
+                sigma_color,
+                sigma_space,
+            } => imgproc::bilateral_filter(
+                input,
+                &mut output,
+                *diameter,
+                *sigma_color,
+                *sigma_space,
+                BORDER_DEFAULT,
+            )?,
+            Filter::NlMeans {
+                h,
+                h_color,
+                template,
+                search,
+            } => return nl_means(input, *h, *h_color, *template, *search),
+            Filter::Sharpen { sigma, amount } => return unsharp_mask(input, *sigma, *amount),
+            Filter::Equalize { channels } => return equalize(input, *channels),
+            Filter::Clahe {
+                clip_limit,
+                tiles,
+                channels,
+            } => return clahe(input, *clip_limit, *tiles, *channels),
+            Filter::Gamma { gamma, channels } => {
+                return apply_table(input, &gamma_table(*gamma), *channels)
+            }
+            Filter::BrightnessContrast {
+                brightness,
+                contrast,
+                channels,
+            } => {
+                let table = brightness_contrast_table(*brightness, *contrast);
+                return apply_table(input, &table, *channels);
+            }
+            Filter::Levels {
+                black,
+                white,
+                gamma,
+                channels,
+            } => return apply_table(input, &levels_table(*black, *white, *gamma), *channels),
+        }
+        Ok(output)
+    }
+
+    /// Where pixels of an `input`-sized image end up, for the steps that move
+    /// them; `None` for steps that leave every pixel in place
+    pub fn geometry(&self, input: Size) -> opencv::Result<Option<Homography>> {
+        let matrix = match self {
+            Filter::Resize { width, height } => scaling(input, Size::new(*width, *height)),
+            Filter::Crop { rect } => {
+                let inside = clip_rect(rect, input.width, input.height).unwrap_or(*rect);
+                translation(-inside.x as f64, -inside.y as f64)
+            }
+            Filter::Rotate90 => quarter_turns(1, input),
+            Filter::Rotate180 => quarter_turns(2, input),
+            Filter::Rotate270 => quarter_turns(3, input),
+            Filter::Flip { axis } => flipping(*axis, input),
+            Filter::RotateBy { angle, .. } => rotation(*angle, input).0,
+            Filter::Perspective { corners } => perspective(corners, warp_size(corners))?,
+            _ => return Ok(None),
+        };
+        Ok(Some(matrix))
+    }
+
+    /// Whether this filter moves pixels, see [`Filter::geometry`]
+    pub fn is_geometric(&self) -> bool {
+        matches!(
+            self,
+            Filter::Resize { .. }
+                | Filter::Crop { .. }
+                | Filter::Rotate90
+                | Filter::Rotate180
+                | Filter::Rotate270
+                | Filter::Flip { .. }
+                | Filter::RotateBy { .. }
+                | Filter::Perspective { .. }
+        )
+    }
+
+    /// Size of the result for an `input`-sized image, worked out without
+    /// touching any pixels
+    pub fn output_size(&self, input: Size) -> Size {
+        match self {
+            Filter::Resize { width, height } => Size::new(*width, *height),
+            Filter::Crop { rect } => clip_rect(rect, input.width, input.height)
+                .map_or(input, |inside| Size::new(inside.width, inside.height)),
+            Filter::Rotate90 | Filter::Rotate270 => Size::new(input.height, input.width),
+            Filter::RotateBy { angle, .. } => rotation(*angle, input).1,
+            Filter::Perspective { corners } => warp_size(corners),
+            _ => input,
+        }
+    }
+
+    /// The same step for an image `factor` times the size it was set up for:
+    /// sizes, positions and kernels measured in pixels are scaled, then snapped
+    /// to what the operation accepts. Lets a live preview run on a smaller copy
+    /// of the image.
+    pub fn scaled(&self, factor: f64) -> Filter {
+        // Parameters, by their index in `params`, that are lengths in pixels
+        let lengths: &[usize] = match self {
+            Filter::Blur { .. } => &[0],
+            Filter::Resize { .. } => &[0, 1],
+            Filter::Threshold { .. } | Filter::Morph { .. } => &[2],
+            Filter::GaussianBlur { .. } => &[0, 1],
+            Filter::Median { .. } | Filter::Sharpen { .. } => &[0],
+            Filter::Bilateral { .. } => &[0, 2],
+            // Smaller windows keep NL-means, the slowest step, quick to preview
+            Filter::NlMeans { .. } => &[2, 3],
+            Filter::Crop { rect } => {
+                let edge = |v: i32| (v as f64 * factor).round() as i32;
+                let (x, y) = (edge(rect.x), edge(rect.y));
+                let rect = Rect::new(
+                    x,
+                    y,
+                    (edge(rect.x + rect.width) - x).max(1),
+                    (edge(rect.y + rect.height) - y).max(1),
+                );
+                return Filter::Crop { rect };
+            }
+            Filter::Perspective { corners } => {
+                let factor = factor as f32;
+                let corners = corners.map(|c| Point2f::new(c.x * factor, c.y * factor));
+                return Filter::Perspective { corners };
+            }
+            _ => &[],
+        };
+        let params = self.params();
+        lengths.iter().fold(self.clone(), |filter, &index| {
+            filter.with_param(index, params[index].1 * factor)
+        })
+    }
+
+    /// Whether tuning this filter shows the histogram of its input
+    pub fn has_histogram(&self) -> bool {
+        matches!(self, Filter::Threshold { .. } | Filter::Levels { .. })
+    }
+
+    /// Values to mark on the histogram of `input` (the step's own input, not
+    /// the source image): the global level a threshold picks, none for
+    /// adaptive methods, or the black and white points of levels
+    pub fn histogram_markers(&self, input: &Mat) -> opencv::Result<Vec<f64>> {
+        match self {
+            Filter::Threshold {
+                method,
+                value,
+                block_size,
+                c,
+                inverted,
+            } if !method.is_adaptive() => {
+                let gray = to_gray(input)?;
+                let (_, level) = threshold(&gray, *method, *value, *block_size, *c, *inverted)?;
+                Ok(level.into_iter().collect())
+            }
+            Filter::Levels { black, white, .. } => Ok(vec![*black, *white]),
+            _ => Ok(vec![]),
+        }
+    }
+}
+
+pub fn to_gray(input: &Mat) -> opencv::Result<Mat> {
+    if input.channels() == 1 {
+        return input.try_clone();
+    }
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        input,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(gray)
+}
+
+fn write_channels(f: &mut fmt::Formatter, channels: ToneChannels) -> fmt::Result {
+    match channels {
+        ToneChannels::Luminance => Ok(()),
+        ToneChannels::PerChannel => f.write_str(":channels"),
+    }
+}
+
+/// Same spec syntax `FromStr` accepts, e.g. `resize=200x200`.
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::Blur { ksize } => write!(f, "blur={}", ksize),
+            Filter::Resize { width, height } => write!(f, "resize={}x{}", width, height),
+            Filter::Canny {
+                low,
+                high,
+                aperture: CANNY_APERTURE,
+            } => write!(f, "canny={}:{}", low, high),
+            Filter::Canny {
+                low,
+                high,
+                aperture,
+            } => write!(f, "canny={}:{}:{}", low, high, aperture),
+            Filter::Crop { rect } => write!(
+                f,
+                "crop={}:{}:{}:{}",
+                rect.x, rect.y, rect.width, rect.height
+            ),
+            Filter::Flip { axis } => write!(f, "flip={}", axis.name()),
+            Filter::RotateBy {
+                angle,
+                border: BorderMode::Black,
+            } => write!(f, "angle={}", angle),
+            Filter::RotateBy { angle, border } => write!(f, "angle={}:{}", angle, border.name()),
+            Filter::Perspective { corners } => {
+                f.write_str("warp=")?;
+                for (i, corner) in corners.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ":" };
+                    write!(f, "{}{}:{}", separator, corner.x, corner.y)?;
+                }
+                Ok(())
+            }
+            Filter::Threshold {
+                method,
+                value,
+                block_size,
+                c,
+                inverted,
+            } => {
+                write!(f, "threshold={}", method.name())?;
+                match method {
+                    ThresholdMethod::Fixed => write!(f, ":{}", value)?,
+                    ThresholdMethod::AdaptiveMean | ThresholdMethod::AdaptiveGaussian => {
+                        write!(f, ":{}:{}", block_size, c)?
+                    }
+                    _ => {}
+                }
+                if *inverted {
+                    f.write_str(":inv")?;
+                }
+                Ok(())
+            }
+            Filter::Morph {
+                op,
+                shape,
+                size,
+                iterations,
+            } => {
+                write!(f, "morph={}:{}:{}", op.name(), shape.name(), size)?;
+                if *iterations != MORPH_ITERATIONS {
+                    write!(f, ":{}", iterations)?;
+                }
+                Ok(())
+            }
+            Filter::GaussianBlur { ksize, sigma } if *sigma == 0.0 => {
+                write!(f, "gaussian={}", ksize)
+            }
+            Filter::GaussianBlur { ksize, sigma } => write!(f, "gaussian={}:{}", ksize, sigma),
+            Filter::Median { ksize } => write!(f, "median={}", ksize),
+            Filter::Bilateral {
+                diameter,
+                sigma_color,
+                sigma_space,
+            } => write!(f, "bilateral={}:{}:{}", diameter, sigma_color, sigma_space),
+            Filter::NlMeans {
+                h,
+                h_color,
+                template,
+                search,
+            } => write!(f, "nlmeans={}:{}:{}:{}", h, h_color, template, search),
+            Filter::Sharpen { sigma, amount } => write!(f, "sharpen={}:{}", sigma, amount),
+            Filter::Equalize {
+                channels: ToneChannels::Luminance,
+            } => f.write_str("equalize"),
+            Filter::Equalize { .. } => f.write_str("equalize=channels"),
+            Filter::Clahe {
+                clip_limit,
+                tiles,
+                channels,
+            } => {
+                write!(f, "clahe={}:{}", clip_limit, tiles)?;
+                write_channels(f, *channels)
+            }
+            Filter::Gamma { gamma, channels } => {
+                write!(f, "gamma={}", gamma)?;
+                write_channels(f, *channels)
+            }
+            Filter::BrightnessContrast {
+                brightness,
+                contrast,
+                channels,
+            } => {
+                write!(f, "brightness={}:{}", brightness, contrast)?;
+                write_channels(f, *channels)
+            }
+            Filter::Levels {
+                black,
+                white,
+                gamma,
+                channels,
+            } => {
+                write!(f, "levels={}:{}", black, white)?;
+                if *gamma != 1.0 {
+                    write!(f, ":{}", gamma)?;
+                }
+                write_channels(f, *channels)
+            }
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+/// A positive odd whole number, as kernel and window sizes must be
+fn is_odd(value: f64) -> bool {
+    value >= 1.0 && value.fract() == 0.0 && value % 2.0 == 1.0
+}
+
+fn parse_numbers<T: FromStr>(params: &str, separator: char, count: usize) -> Option<Vec<T>> {
+    let values: Vec<T> = params
+        .split(separator)
+        .map(|v| v.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    (values.len() == count).then_some(values)
+}
+
+/// Tone adjustments: `equalize`, `clahe=<clip>:<tiles>`, `gamma=<gamma>`,
+/// `brightness=<brightness>:<contrast>` and `levels=<black>:<white>[:<gamma>]`,
+/// each on luminance unless followed by `:channels` (or just `=channels`).
+/// Without params they leave the image unchanged, except CLAHE at 2:8.
+fn parse_tone(name: &str, params: Option<&str>) -> Option<Filter> {
+    let (params, channels) = match params.map(str::trim) {
+        None => (None, ToneChannels::Luminance),
+        Some("channels") => (None, ToneChannels::PerChannel),
+        Some(p) => match p.strip_suffix(":channels") {
+            Some(rest) => (Some(rest), ToneChannels::PerChannel),
+            None => (Some(p), ToneChannels::Luminance),
+        },
+    };
+    let numbers: Vec<f64> = match params {
+        Some(p) => p
+            .split(':')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?,
+        None => vec![],
+    };
+    let filter = match (name, numbers.as_slice()) {
+        ("equalize", []) => Filter::Equalize { channels },
+        ("clahe", []) => Filter::Clahe {
+            clip_limit: CLAHE_CLIP_LIMIT,
+            tiles: CLAHE_TILES,
+            channels,
+        },
+        ("clahe", &[clip_limit, tiles])
+            if clip_limit > 0.0 && tiles >= 1.0 && tiles.fract() == 0.0 =>
+        {
+            Filter::Clahe {
+                clip_limit,
+                tiles: tiles as i32,
+                channels,
+            }
+        }
+        ("gamma", []) => Filter::Gamma {
+            gamma: 1.0,
+            channels,
+        },
+        ("gamma", &[gamma]) if gamma > 0.0 => Filter::Gamma { gamma, channels },
+        ("brightness", []) => Filter::BrightnessContrast {
+            brightness: 0.0,
+            contrast: 1.0,
+            channels,
+        },
+        ("brightness", &[brightness, contrast]) if contrast >= 0.0 => Filter::BrightnessContrast {
+            brightness,
+            contrast,
+            channels,
+        },
+        ("levels", []) => Filter::Levels {
+            black: 0.0,
+            white: 255.0,
+            gamma: 1.0,
+            channels,
+        },
+        ("levels", &[black, white, ref gamma @ ..])
+            if gamma.len() <= 1 && 0.0 <= black && black < white && white <= 255.0 =>
+        {
+            let gamma = gamma.first().copied().unwrap_or(1.0);
+            if gamma <= 0.0 {
+                return None;
+            }
+            Filter::Levels {
+                black,
+                white,
+                gamma,
+                channels,
+            }
+        }
+        _ => return None,
+    };
+    Some(filter)
+}
+
+/// Parses `name` or `name=params`. Without params the toolbox defaults are used:
+/// `blur=15`, `resize=200x200`, `canny=100:200:3`, `crop=50:50:100:100`,
+/// `threshold=fixed:128`, `morph=open:rect:3:1`, `gaussian=5:0`, `median=5`,
+/// `bilateral=9:75:75`, `nlmeans=10:10:7:21`, `sharpen=1.5:1`, `flip=horizontal`,
+/// `angle=0:black`. `warp` has no default; it takes the x and y of each corner,
+/// top-left, top-right, bottom-right then bottom-left.
+///
+/// Threshold params are the method, its own numbers and an optional `:inv`:
+/// `fixed[:level]`, `otsu`, `triangle`, `mean[:block:c]`, `gaussian[:block:c]`.
+/// Morph params are `op[:shape[:size[:iterations]]]`.
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (name, params) = match spec.split_once('=') {
+            Some((name, params)) => (name.trim(), Some(params)),
+            None => (spec.trim(), None),
+        };
+        let invalid = || format!("invalid parameters for {}: {}", name, spec);
+
+        let filter = match (name, params) {
+            ("blur", None) => Filter::Blur { ksize: BLUR_KSIZE },
+            ("blur", Some(p)) => match p.trim().parse() {
+                Ok(ksize) if ksize > 0 => Filter::Blur { ksize },
+                _ => return Err(invalid()),
+            },
+            ("resize", None) => Filter::Resize {
+                width: RESIZE_SIZE.0,
+                height: RESIZE_SIZE.1,
+            },
+            ("resize", Some(p)) => match parse_numbers::<i32>(p, 'x', 2).as_deref() {
+                Some(&[width, height]) if width > 0 && height > 0 => {
+                    Filter::Resize { width, height }
+                }
+                _ => return Err(invalid()),
+            },
+            ("grayscale" | "gray", None) => Filter::Grayscale,
+            ("invert", None) => Filter::Invert,
+            ("canny", None) => Filter::Canny {
+                low: CANNY_THRESHOLDS.0,
+                high: CANNY_THRESHOLDS.1,
+                aperture: CANNY_APERTURE,
+            },
+            ("canny", Some(p)) => {
+                let values = parse_numbers::<f64>(p, ':', 2)
+                    .or_else(|| parse_numbers::<f64>(p, ':', 3))
+                    .ok_or_else(invalid)?;
+                let aperture = match values.get(2) {
+                    None => CANNY_APERTURE,
+                    Some(&a) if [3.0, 5.0, 7.0].contains(&a) => a as i32,
+                    Some(_) => return Err(invalid()),
+                };
+                Filter::Canny {
+                    low: values[0],
+                    high: values[1],
+                    aperture,
+                }
+            }
+            ("crop", None) => Filter::Crop {
+                rect: Rect::new(CROP_RECT.0, CROP_RECT.1, CROP_RECT.2, CROP_RECT.3),
+            },
+            ("crop", Some(p)) => match parse_numbers::<i32>(p, ':', 4).as_deref() {
+                Some(&[x, y, w, h]) if w > 0 && h > 0 => Filter::Crop {
+                    rect: Rect::new(x, y, w, h),
+                },
+                _ => return Err(invalid()),
+            },
+            ("rotate" | "rotate90", None) => Filter::Rotate90,
+            ("rotate180", None) => Filter::Rotate180,
+            ("rotate270", None) => Filter::Rotate270,
+            ("flip", None) => Filter::Flip {
+                axis: FlipAxis::Horizontal,
+            },
+            ("flip", Some(p)) => Filter::Flip {
+                axis: FlipAxis::from_name(p.trim()).ok_or_else(invalid)?,
+            },
+            ("angle", None) => Filter::RotateBy {
+                angle: 0.0,
+                border: BorderMode::Black,
+            },
+            ("angle", Some(p)) => {
+                let (angle, border) = match p.split_once(':') {
+                    Some((angle, border)) => (
+                        angle,
+                        BorderMode::from_name(border.trim()).ok_or_else(invalid)?,
+                    ),
+                    None => (p, BorderMode::Black),
+                };
+                match angle.trim().parse::<f64>() {
+                    Ok(angle) if angle.is_finite() => Filter::RotateBy { angle, border },
+                    _ => return Err(invalid()),
+                }
+            }
+            ("warp", None) => return Err("warp needs its four corners".to_string()),
+            ("warp", Some(p)) => {
+                let values = parse_numbers::<f32>(p, ':', 8).ok_or_else(invalid)?;
+                let corners = [0, 2, 4, 6].map(|i| Point2f::new(values[i], values[i + 1]));
+                let size = warp_size(&corners);
+                if size.width < 2 || size.height < 2 {
+                    return Err(invalid());
+                }
+                Filter::Perspective { corners }
+            }
+            ("threshold", None) => Filter::Threshold {
+                method: ThresholdMethod::Fixed,
+                value: THRESHOLD_VALUE,
+                block_size: ADAPTIVE_BLOCK_SIZE,
+                c: ADAPTIVE_C,
+                inverted: false,
+            },
+            ("threshold", Some(p)) => {
+                let (p, inverted) = match p.trim().strip_suffix(":inv") {
+                    Some(rest) => (rest, true),
+                    None => (p.trim(), false),
+                };
+                let (method, numbers) = match p.split_once(':') {
+                    Some((method, numbers)) => (method, Some(numbers)),
+                    None => (p, None),
+                };
+                let method = ThresholdMethod::from_name(method).ok_or_else(invalid)?;
+                let mut value = THRESHOLD_VALUE;
+                let mut block_size = ADAPTIVE_BLOCK_SIZE;
+                let mut c = ADAPTIVE_C;
+                match (method, numbers) {
+                    (_, None) => {}
+                    (ThresholdMethod::Fixed, Some(n)) => match n.trim().parse() {
+                        Ok(level) if (0.0..=255.0).contains(&level) => value = level,
+                        _ => return Err(invalid()),
+                    },
+                    (
+                        ThresholdMethod::AdaptiveMean | ThresholdMethod::AdaptiveGaussian,
+                        Some(n),
+                    ) => match parse_numbers::<f64>(n, ':', 2).as_deref() {
+                        Some(&[block, offset]) if block >= 3.0 && is_odd(block) => {
+                            block_size = block as i32;
+                            c = offset;
+                        }
+                        _ => return Err(invalid()),
+                    },
+                    _ => return Err(invalid()),
+                }
+                Filter::Threshold {
+                    method,
+                    value,
+                    block_size,
+                    c,
+                    inverted,
+                }
+            }
+            ("morph", None) => Filter::Morph {
+                op: MorphOp::Open,
+                shape: KernelShape::Rect,
+                size: MORPH_SIZE,
+                iterations: MORPH_ITERATIONS,
+            },
+            ("morph", Some(p)) => {
+                let mut parts = p.trim().split(':');
+                let op = parts
+                    .next()
+                    .and_then(MorphOp::from_name)
+                    .ok_or_else(invalid)?;
+                let shape = match parts.next() {
+                    Some(shape) => KernelShape::from_name(shape).ok_or_else(invalid)?,
+                    None => KernelShape::Rect,
+                };
+                let mut count = |default: i32| match parts.next().map(|n| n.parse::<i32>()) {
+                    None => Ok(default),
+                    Some(Ok(n)) if n > 0 => Ok(n),
+                    Some(_) => Err(invalid()),
+                };
+                let size = count(MORPH_SIZE)?;
+                let iterations = count(MORPH_ITERATIONS)?;
+                if parts.next().is_some() {
+                    return Err(invalid());
+                }
+                Filter::Morph {
+                    op,
+                    shape,
+                    size,
+                    iterations,
+                }
+            }
+            ("gaussian", None) => Filter::GaussianBlur {
+                ksize: GAUSSIAN_KSIZE,
+                sigma: 0.0,
+            },
+            ("gaussian", Some(p)) => {
+                let values = parse_numbers::<f64>(p, ':', 1)
+                    .or_else(|| parse_numbers::<f64>(p, ':', 2))
+                    .ok_or_else(invalid)?;
+                let ksize = values[0];
+                let sigma = values.get(1).copied().unwrap_or(0.0);
+                if !is_odd(ksize) || sigma < 0.0 {
+                    return Err(invalid());
+                }
+                Filter::GaussianBlur {
+                    ksize: ksize as i32,
+                    sigma,
+                }
+            }
+            ("median", None) => Filter::Median {
+                ksize: MEDIAN_KSIZE,
+            },
+            ("median", Some(p)) => match p.trim().parse::<f64>() {
+                Ok(ksize) if is_odd(ksize) && ksize >= 3.0 => Filter::Median {
+                    ksize: ksize as i32,
+                },
+                _ => return Err(invalid()),
+            },
+            ("bilateral", None) => Filter::Bilateral {
+                diameter: BILATERAL_PARAMS.0,
+                sigma_color: BILATERAL_PARAMS.1,
+                sigma_space: BILATERAL_PARAMS.2,
+            },
+            ("bilateral", Some(p)) => match parse_numbers::<f64>(p, ':', 3).as_deref() {
+                Some(&[diameter, sigma_color, sigma_space])
+                    if diameter >= 1.0
+                        && diameter.fract() == 0.0
+                        && sigma_color > 0.0
+                        && sigma_space > 0.0 =>
+                {
+                    Filter::Bilateral {
+                        diameter: diameter as i32,
+                        sigma_color,
+                        sigma_space,
+                    }
+                }
+                _ => return Err(invalid()),
+            },
+            ("nlmeans", None) => Filter::NlMeans {
+                h: NL_MEANS_PARAMS.0,
+                h_color: NL_MEANS_PARAMS.1,
+                template: NL_MEANS_PARAMS.2,
+                search: NL_MEANS_PARAMS.3,
+            },
+            ("nlmeans", Some(p)) => {
+                let values = parse_numbers::<f64>(p, ':', 2)
+                    .or_else(|| parse_numbers::<f64>(p, ':', 4))
+                    .ok_or_else(invalid)?;
+                let template = values.get(2).copied().unwrap_or(NL_MEANS_PARAMS.2 as f64);
+                let search = values.get(3).copied().unwrap_or(NL_MEANS_PARAMS.3 as f64);
+                if values[0] <= 0.0 || values[1] < 0.0 || !is_odd(template) || !is_odd(search) {
+                    return Err(invalid());
+                }
+                Filter::NlMeans {
+                    h: values[0],
+                    h_color: values[1],
+                    template: template as i32,
+                    search: search as i32,
+                }
+            }
+            ("sharpen", None) => Filter::Sharpen {
+                sigma: UNSHARP_PARAMS.0,
+                amount: UNSHARP_PARAMS.1,
+            },
+            ("sharpen", Some(p)) => match parse_numbers::<f64>(p, ':', 2).as_deref() {
+                Some(&[sigma, amount]) if sigma > 0.0 && amount >= 0.0 => {
+                    Filter::Sharpen { sigma, amount }
+                }
+                _ => return Err(invalid()),
+            },
+            ("equalize" | "clahe" | "gamma" | "brightness" | "levels", params) => {
+                parse_tone(name, params).ok_or_else(invalid)?
+            }
+            (
+                "grayscale" | "gray" | "invert" | "rotate" | "rotate90" | "rotate180" | "rotate270",
+                Some(_),
+            ) => return Err(format!("{} takes no parameters", name)),
+            _ => return Err(format!("unknown operation: {}", name)),
+        };
+        Ok(filter)
+    }
+}
+
+/// Decodes image file bytes the way the GUI does (3-channel BGR).
+pub fn decode_image(bytes: &[u8]) -> opencv::Result<Mat> {
+    imdecode(&Vector::from_slice(bytes), IMREAD_COLOR)
+}
+
+pub fn encode_png(mat: &Mat) -> opencv::Result<Vec<u8>> {
+    let mut buf = Vector::new();
+    imencode(".png", mat, &mut buf, &Vector::new())?;
+    Ok(buf.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_defaults_and_params() {
+        assert_eq!("blur".parse(), Ok(Filter::Blur { ksize: BLUR_KSIZE }));
+        assert_eq!(
+            "resize=64x32".parse(),
+            Ok(Filter::Resize {
+                width: 64,
+                height: 32
+            })
+        );
+        assert_eq!(
+            "canny=50:150".parse(),
+            Ok(Filter::Canny {
+                low: 50.0,
+                high: 150.0,
+                aperture: CANNY_APERTURE
+            })
+        );
+        assert_eq!(
+            "canny=50:150:5".parse(),
+            Ok(Filter::Canny {
+                low: 50.0,
+                high: 150.0,
+                aperture: 5
+            })
+        );
+        assert_eq!(
+            " crop=1:2:3:4".parse(),
+            Ok(Filter::Crop {
+                rect: Rect::new(1, 2, 3, 4)
+            })
+        );
+        assert_eq!("rotate".parse(), Ok(Filter::Rotate90));
+        assert_eq!(
+            "angle=-12.5:reflect".parse(),
+            Ok(Filter::RotateBy {
+                angle: -12.5,
+                border: BorderMode::Reflect
+            })
+        );
+        assert_eq!(
+            "warp=1:2:30:2:30:20:1:20".parse(),
+            Ok(Filter::Perspective {
+                corners: [
+                    Point2f::new(1.0, 2.0),
+                    Point2f::new(30.0, 2.0),
+                    Point2f::new(30.0, 20.0),
+                    Point2f::new(1.0, 20.0)
+                ]
+            })
+        );
+        assert_eq!(
+            "nlmeans=5:3".parse(),
+            Ok(Filter::NlMeans {
+                h: 5.0,
+                h_color: 3.0,
+                template: NL_MEANS_PARAMS.2,
+                search: NL_MEANS_PARAMS.3
+            })
+        );
+        assert_eq!(
+            "threshold=gaussian:15:-3:inv".parse(),
+            Ok(Filter::Threshold {
+                method: ThresholdMethod::AdaptiveGaussian,
+                value: THRESHOLD_VALUE,
+                block_size: 15,
+                c: -3.0,
+                inverted: true
+            })
+        );
+        assert_eq!(
+            "morph=dilate:cross".parse(),
+            Ok(Filter::Morph {
+                op: MorphOp::Dilate,
+                shape: KernelShape::Cross,
+                size: MORPH_SIZE,
+                iterations: MORPH_ITERATIONS
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_specs() {
+        assert!("blur=0".parse::<Filter>().is_err());
+        assert!("resize=64".parse::<Filter>().is_err());
+        assert!("crop=1:2:3".parse::<Filter>().is_err());
+        assert!("canny=1:2:4".parse::<Filter>().is_err());
+        assert!("invert=1".parse::<Filter>().is_err());
+        assert!("emboss".parse::<Filter>().is_err());
+        assert!("threshold=fixed:300".parse::<Filter>().is_err());
+        assert!("threshold=otsu:100".parse::<Filter>().is_err());
+        assert!("threshold=mean:4:2".parse::<Filter>().is_err());
+        assert!("threshold=binary".parse::<Filter>().is_err());
+        assert!("morph=shrink".parse::<Filter>().is_err());
+        assert!("morph=open:disk".parse::<Filter>().is_err());
+        assert!("morph=open:rect:0".parse::<Filter>().is_err());
+        assert!("morph=open:rect:3:1:1".parse::<Filter>().is_err());
+        assert!("gaussian=4".parse::<Filter>().is_err());
+        assert!("median=1".parse::<Filter>().is_err());
+        assert!("bilateral=9:75".parse::<Filter>().is_err());
+        assert!("nlmeans=10:10:6:21".parse::<Filter>().is_err());
+        assert!("sharpen=0:1".parse::<Filter>().is_err());
+        assert!("equalize=2".parse::<Filter>().is_err());
+        assert!("clahe=2:1.5".parse::<Filter>().is_err());
+        assert!("gamma=0".parse::<Filter>().is_err());
+        assert!("levels=200:100".parse::<Filter>().is_err());
+        assert!("levels=0:255:1:2".parse::<Filter>().is_err());
+        assert!("rotate180=1".parse::<Filter>().is_err());
+        assert!("flip=diagonal".parse::<Filter>().is_err());
+        assert!("angle=15:mirror".parse::<Filter>().is_err());
+        assert!("warp".parse::<Filter>().is_err());
+        assert!("warp=0:0:10:0:10:10".parse::<Filter>().is_err());
+        assert!("warp=5:5:5:5:5:5:5:5".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for spec in [
+            "blur=9",
+            "resize=10x20",
+            "grayscale",
+            "canny=1:2",
+            "canny=1:2:7",
+            "crop=0:0:5:5",
+            "threshold=fixed:128",
+            "threshold=otsu:inv",
+            "threshold=mean:11:2.5",
+            "morph=open:rect:3",
+            "morph=blackhat:ellipse:7:4",
+            "gaussian=5",
+            "gaussian=7:1.5",
+            "median=3",
+            "bilateral=9:75:50",
+            "nlmeans=10:12:7:21",
+            "sharpen=1.5:0.8",
+            "equalize",
+            "equalize=channels",
+            "clahe=2.5:8:channels",
+            "gamma=1.5",
+            "brightness=-20:1.2",
+            "levels=10:240",
+            "levels=10:240:0.8:channels",
+            "rotate180",
+            "rotate270",
+            "flip=vertical",
+            "angle=30",
+            "angle=-7.5:wrap",
+            "warp=10.5:4:200:0:210:150:0:160",
+        ] {
+            let filter: Filter = spec.parse().unwrap();
+            assert_eq!(filter.to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn with_param_clamps_and_snaps() {
+        let canny: Filter = "canny".parse().unwrap();
+        assert_eq!(canny.params().len(), 3);
+        assert_eq!(
+            canny.with_param(2, 6.2).with_param(0, -5.0),
+            Filter::Canny {
+                low: 0.0,
+                high: CANNY_THRESHOLDS.1,
+                aperture: 7
+            }
+        );
+        let blur = Filter::Blur { ksize: 3 };
+        assert_eq!(blur.with_param(0, 500.0), Filter::Blur { ksize: 99 });
+        assert_eq!(blur.with_param(1, 5.0), blur);
+        assert!(Filter::Invert.params().is_empty());
+        let threshold: Filter = "threshold".parse().unwrap();
+        assert_eq!(
+            threshold.with_param(0, 1.0).with_param(4, 1.0).to_string(),
+            "threshold=otsu:inv"
+        );
+        assert_eq!(
+            threshold.with_param(0, 9.0).with_param(2, 8.0).to_string(),
+            "threshold=gaussian:9:2"
+        );
+        let levels: Filter = "levels=100:200".parse().unwrap();
+        assert_eq!(
+            levels.with_param(0, 230.0).with_param(2, 1.234).to_string(),
+            "levels=199:200:1.25"
+        );
+        assert_eq!(
+            levels.with_param(3, 1.0).to_string(),
+            "levels=100:200:channels"
+        );
+        let angle: Filter = "angle".parse().unwrap();
+        assert_eq!(
+            angle.with_param(0, 200.0).with_param(1, 2.0).to_string(),
+            "angle=180:reflect"
+        );
+    }
+
+    #[test]
+    fn geometric_steps_map_pixels() {
+        let size = Size::new(40, 30);
+        assert_eq!(Filter::Invert.geometry(size).unwrap(), None);
+        assert!(!Filter::Invert.is_geometric());
+        for spec in ["resize", "crop", "rotate", "rotate270", "flip", "angle=10"] {
+            let filter: Filter = spec.parse().unwrap();
+            assert!(filter.is_geometric());
+            assert!(filter.geometry(size).unwrap().is_some());
+        }
+        assert_eq!(
+            Filter::Rotate90.geometry(size).unwrap(),
+            Some(quarter_turns(1, size))
+        );
+        // A crop hanging over the top-left edge starts at the image corner
+        let crop = Filter::Crop {
+            rect: Rect::new(-5, 10, 20, 20),
+        };
+        assert_eq!(crop.geometry(size).unwrap(), Some(translation(0.0, -10.0)));
+        assert_eq!(crop.output_size(size), Size::new(15, 20));
+        assert_eq!(
+            Filter::Rotate270.output_size(size),
+            Size::new(size.height, size.width)
+        );
+        assert_eq!(Filter::Invert.output_size(size), size);
+    }
+
+    #[test]
+    fn scaling_shrinks_lengths_in_pixels() {
+        assert_eq!(
+            Filter::Crop {
+                rect: Rect::new(10, 21, 40, 40)
+            }
+            .scaled(0.25),
+            Filter::Crop {
+                rect: Rect::new(3, 5, 10, 10)
+            }
+        );
+        // Snapped to odd block sizes, never below the smallest one
+        let threshold: Filter = "threshold=gaussian:31:5".parse().unwrap();
+        assert_eq!(threshold.scaled(0.25).params()[2].1, 7.0);
+        assert_eq!(threshold.scaled(0.01).params()[2].1, 3.0);
+        let nl_means: Filter = "nlmeans=10:10:7:21".parse().unwrap();
+        assert_eq!(
+            nl_means.scaled(0.25),
+            Filter::NlMeans {
+                h: 10.0,
+                h_color: 10.0,
+                template: 3,
+                search: 7
+            }
+        );
+        // Levels and thresholds in intensity are no lengths
+        let canny: Filter = "canny=50:150".parse().unwrap();
+        assert_eq!(canny.scaled(0.5), canny);
+    }
+}
@@ -0,0 +1,382 @@
+// Geometric steps: quarter turns, flips, free rotation and perspective
+// rectification, and carrying ROIs through them
+//
+// Each geometric step is described by a 3x3 homography from pixel centres of
+// its input to pixel centres of its output, so ROIs follow all of them (and
+// resize and crop) the same way.
+use opencv::{
+    core::{
+        flip, Point2f, Rect, Scalar, Size, Vector, BORDER_CONSTANT, BORDER_REFLECT_101,
+        BORDER_REPLICATE, BORDER_WRAP, DECOMP_LU,
+    },
+    imgproc,
+    prelude::*,
+};
+
+use crate::core::roi_model::LabeledRoi;
+
+/// Maps `(x, y, 1)` of a pixel centre in a step's input to its output
+pub type Homography = [[f64; 3]; 3];
+
+pub const IDENTITY: Homography = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlipAxis {
+    /// Mirrors left to right
+    Horizontal,
+    /// Turns upside down
+    Vertical,
+}
+
+impl FlipAxis {
+    pub const ALL: [FlipAxis; 2] = [FlipAxis::Horizontal, FlipAxis::Vertical];
+
+    pub const NAMES: [&'static str; 2] = ["horizontal", "vertical"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|axis| axis.name() == name)
+    }
+
+    fn code(&self) -> i32 {
+        match self {
+            FlipAxis::Horizontal => 1,
+            FlipAxis::Vertical => 0,
+        }
+    }
+}
+
+/// How a free rotation fills the corners the rotated image doesn't cover
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BorderMode {
+    Black,
+    /// Repeats the edge pixels
+    Replicate,
+    /// Mirrors the image at its edges
+    Reflect,
+    /// Tiles the image
+    Wrap,
+}
+
+impl BorderMode {
+    pub const ALL: [BorderMode; 4] = [
+        BorderMode::Black,
+        BorderMode::Replicate,
+        BorderMode::Reflect,
+        BorderMode::Wrap,
+    ];
+
+    pub const NAMES: [&'static str; 4] = ["black", "replicate", "reflect", "wrap"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|mode| mode.name() == name)
+    }
+
+    fn code(&self) -> i32 {
+        match self {
+            BorderMode::Black => BORDER_CONSTANT,
+            BorderMode::Replicate => BORDER_REPLICATE,
+            BorderMode::Reflect => BORDER_REFLECT_101,
+            BorderMode::Wrap => BORDER_WRAP,
+        }
+    }
+}
+
+pub fn translation(dx: f64, dy: f64) -> Homography {
+    [[1.0, 0.0, dx], [0.0, 1.0, dy], [0.0, 0.0, 1.0]]
+}
+
+/// Stretching an image of size `from` to `to`
+pub fn scaling(from: Size, to: Size) -> Homography {
+    let sx = to.width as f64 / from.width as f64;
+    let sy = to.height as f64 / from.height as f64;
+    // Pixel edges scale, so the centres move by half a pixel
+    [
+        [sx, 0.0, (sx - 1.0) / 2.0],
+        [0.0, sy, (sy - 1.0) / 2.0],
+        [0.0, 0.0, 1.0],
+    ]
+}
+
+/// `turns` quarter turns clockwise of an image of `size`
+pub fn quarter_turns(turns: i32, size: Size) -> Homography {
+    let right = (size.width - 1) as f64;
+    let bottom = (size.height - 1) as f64;
+    match turns.rem_euclid(4) {
+        0 => IDENTITY,
+        1 => [[0.0, -1.0, bottom], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        2 => [[-1.0, 0.0, right], [0.0, -1.0, bottom], [0.0, 0.0, 1.0]],
+        _ => [[0.0, 1.0, 0.0], [-1.0, 0.0, right], [0.0, 0.0, 1.0]],
+    }
+}
+
+pub fn flipping(axis: FlipAxis, size: Size) -> Homography {
+    match axis {
+        FlipAxis::Horizontal => [
+            [-1.0, 0.0, (size.width - 1) as f64],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ],
+        FlipAxis::Vertical => [
+            [1.0, 0.0, 0.0],
+            [0.0, -1.0, (size.height - 1) as f64],
+            [0.0, 0.0, 1.0],
+        ],
+    }
+}
+
+/// Rotation by `angle` degrees counterclockwise about the centre of an image
+/// of `size`, onto a canvas grown to hold all of it. Returns the matrix and
+/// that canvas size.
+pub fn rotation(angle: f64, size: Size) -> (Homography, Size) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (width, height) = (size.width as f64, size.height as f64);
+    // Drop float noise first so right angles don't grow the canvas by a pixel
+    let fit = |v: f64| ((v * 1e6).round() / 1e6).ceil() as i32;
+    let canvas = Size::new(
+        fit(width * cos.abs() + height * sin.abs()),
+        fit(width * sin.abs() + height * cos.abs()),
+    );
+    let (cx, cy) = ((width - 1.0) / 2.0, (height - 1.0) / 2.0);
+    let (ox, oy) = (
+        (canvas.width - 1) as f64 / 2.0,
+        (canvas.height - 1) as f64 / 2.0,
+    );
+    // cv::getRotationMatrix2D, with the centre moved to the canvas centre
+    let matrix = [
+        [cos, sin, ox - cos * cx - sin * cy],
+        [-sin, cos, oy + sin * cx - cos * cy],
+        [0.0, 0.0, 1.0],
+    ];
+    (matrix, canvas)
+}
+
+pub fn map_point(m: &Homography, x: f64, y: f64) -> (f64, f64) {
+    let w = m[2][0] * x + m[2][1] * y + m[2][2];
+    (
+        (m[0][0] * x + m[0][1] * y + m[0][2]) / w,
+        (m[1][0] * x + m[1][1] * y + m[1][2]) / w,
+    )
+}
+
+/// Bounding box of `rect` after `m`, clipped to an output of size `bounds`;
+/// `None` once it has left the image
+pub fn map_rect(m: &Homography, rect: Rect, bounds: Size) -> Option<Rect> {
+    // The covered area reaches half a pixel beyond the edge pixels' centres
+    let (left, top) = (rect.x as f64 - 0.5, rect.y as f64 - 0.5);
+    let (right, bottom) = (left + rect.width as f64, top + rect.height as f64);
+    let corners = [(left, top), (right, top), (right, bottom), (left, bottom)]
+        .map(|(x, y)| map_point(m, x, y));
+    if corners
+        .iter()
+        .any(|(x, y)| !x.is_finite() || !y.is_finite())
+    {
+        return None;
+    }
+    // Back to pixel edges, without the float noise that would round outwards
+    let edge = |v: f64| ((v + 0.5) * 1e6).round() / 1e6;
+    let xs = corners.map(|(x, _)| edge(x));
+    let ys = corners.map(|(_, y)| edge(y));
+    let min = |vs: [f64; 4]| vs.into_iter().fold(f64::INFINITY, f64::min).floor();
+    let max = |vs: [f64; 4]| vs.into_iter().fold(f64::NEG_INFINITY, f64::max).ceil();
+    let (x0, y0) = (min(xs).max(0.0), min(ys).max(0.0));
+    let x1 = max(xs).min(bounds.width as f64);
+    let y1 = max(ys).min(bounds.height as f64);
+    (x1 > x0 && y1 > y0)
+        .then(|| Rect::new(x0 as i32, y0 as i32, (x1 - x0) as i32, (y1 - y0) as i32))
+}
+
+/// `rois` after `m`, dropping those that end up outside the output
+pub fn map_rois(m: &Homography, rois: &[LabeledRoi], bounds: Size) -> Vec<LabeledRoi> {
+    rois.iter()
+        .filter_map(|roi| {
+            map_rect(m, roi.rect, bounds).map(|rect| LabeledRoi {
+                rect,
+                ..roi.clone()
+            })
+        })
+        .collect()
+}
+
+/// Orders four points clicked in any order as top-left, top-right,
+/// bottom-right and bottom-left
+pub fn order_corners(points: [Point2f; 4]) -> [Point2f; 4] {
+    let cx = points.iter().map(|p| p.x).sum::<f32>() / 4.0;
+    let cy = points.iter().map(|p| p.y).sum::<f32>() / 4.0;
+    let mut ordered = points;
+    // With y pointing down, increasing angles go clockwise
+    ordered.sort_by(|a, b| {
+        let angle = |p: &Point2f| (p.y - cy).atan2(p.x - cx);
+        angle(a).total_cmp(&angle(b))
+    });
+    let top_left = (0..4)
+        .min_by(|&i, &j| {
+            let sum = |p: Point2f| p.x + p.y;
+            sum(ordered[i]).total_cmp(&sum(ordered[j]))
+        })
+        .unwrap_or(0);
+    ordered.rotate_left(top_left);
+    ordered
+}
+
+/// Size of the rectified image: the longer of each pair of opposite sides
+pub fn warp_size(corners: &[Point2f; 4]) -> Size {
+    let side = |a: Point2f, b: Point2f| ((a.x - b.x) as f64).hypot((a.y - b.y) as f64);
+    let [top_left, top_right, bottom_right, bottom_left] = *corners;
+    let width = side(top_left, top_right).max(side(bottom_left, bottom_right));
+    let height = side(top_left, bottom_left).max(side(top_right, bottom_right));
+    // The corners are pixel centres, one pixel in from the edges
+    Size::new(width.round() as i32 + 1, height.round() as i32 + 1)
+}
+
+/// Homography taking `corners` (top-left, top-right, bottom-right,
+/// bottom-left) to the corners of a `size` image
+pub fn perspective(corners: &[Point2f; 4], size: Size) -> opencv::Result<Homography> {
+    let right = (size.width - 1) as f32;
+    let bottom = (size.height - 1) as f32;
+    let target = [
+        Point2f::new(0.0, 0.0),
+        Point2f::new(right, 0.0),
+        Point2f::new(right, bottom),
+        Point2f::new(0.0, bottom),
+    ];
+    let matrix = imgproc::get_perspective_transform(
+        &Vector::from_slice(corners),
+        &Vector::from_slice(&target),
+        DECOMP_LU,
+    )?;
+    let mut homography = IDENTITY;
+    for (r, row) in homography.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = *matrix.at_2d::<f64>(r as i32, c as i32)?;
+        }
+    }
+    Ok(homography)
+}
+
+pub fn flip_image(input: &Mat, axis: FlipAxis) -> opencv::Result<Mat> {
+    let mut output = Mat::default();
+    flip(input, &mut output, axis.code())?;
+    Ok(output)
+}
+
+/// Free rotation; see [`rotation`]
+pub fn rotate_by(input: &Mat, angle: f64, border: BorderMode) -> opencv::Result<Mat> {
+    let (matrix, canvas) = rotation(angle, Size::new(input.cols(), input.rows()));
+    let affine = Mat::from_slice_2d(&matrix[..2])?;
+    let mut output = Mat::default();
+    imgproc::warp_affine(
+        input,
+        &mut output,
+        &affine,
+        canvas,
+        imgproc::INTER_LINEAR,
+        border.code(),
+        Scalar::default(),
+    )?;
+    Ok(output)
+}
+
+/// Rectifies the quadrilateral `corners` into an upright [`warp_size`] image
+pub fn rectify(input: &Mat, corners: &[Point2f; 4]) -> opencv::Result<Mat> {
+    let size = warp_size(corners);
+    let matrix = Mat::from_slice_2d(&perspective(corners, size)?)?;
+    let mut output = Mat::default();
+    imgproc::warp_perspective(
+        input,
+        &mut output,
+        &matrix,
+        size,
+        imgproc::INTER_LINEAR,
+        BORDER_CONSTANT,
+        Scalar::default(),
+    )?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Size = Size {
+        width: 40,
+        height: 30,
+    };
+
+    #[test]
+    fn quarter_turns_and_flips_move_rects_exactly() {
+        let rect = Rect::new(0, 0, 10, 5);
+        // Top-left corner of a landscape image ends up top-right after a turn
+        let turned = Size::new(SIZE.height, SIZE.width);
+        assert_eq!(
+            map_rect(&quarter_turns(1, SIZE), rect, turned),
+            Some(Rect::new(25, 0, 5, 10))
+        );
+        assert_eq!(
+            map_rect(&quarter_turns(2, SIZE), rect, SIZE),
+            Some(Rect::new(30, 25, 10, 5))
+        );
+        assert_eq!(
+            map_rect(&quarter_turns(3, SIZE), rect, turned),
+            Some(Rect::new(0, 30, 5, 10))
+        );
+        assert_eq!(
+            map_rect(&flipping(FlipAxis::Horizontal, SIZE), rect, SIZE),
+            Some(Rect::new(30, 0, 10, 5))
+        );
+        assert_eq!(
+            map_rect(&flipping(FlipAxis::Vertical, SIZE), rect, SIZE),
+            Some(Rect::new(0, 25, 10, 5))
+        );
+    }
+
+    #[test]
+    fn rotation_grows_the_canvas() {
+        let (matrix, canvas) = rotation(90.0, SIZE);
+        assert_eq!(canvas, Size::new(30, 40));
+        // Counterclockwise: the top-left corner goes to the bottom-left
+        assert_eq!(
+            map_rect(&matrix, Rect::new(0, 0, 10, 5), canvas),
+            Some(Rect::new(0, 30, 5, 10))
+        );
+        let (_, canvas) = rotation(45.0, Size::new(10, 10));
+        assert_eq!(canvas, Size::new(15, 15));
+        assert_eq!(rotation(0.0, SIZE), (IDENTITY, SIZE));
+    }
+
+    #[test]
+    fn scaling_and_cropping_clip_rects() {
+        let half = Size::new(20, 15);
+        assert_eq!(
+            map_rect(&scaling(SIZE, half), Rect::new(10, 10, 10, 10), half),
+            Some(Rect::new(5, 5, 5, 5))
+        );
+        let crop = translation(-20.0, -20.0);
+        let bounds = Size::new(10, 10);
+        assert_eq!(
+            map_rect(&crop, Rect::new(15, 25, 10, 10), bounds),
+            Some(Rect::new(0, 5, 5, 5))
+        );
+        assert_eq!(map_rect(&crop, Rect::new(0, 0, 10, 10), bounds), None);
+    }
+
+    #[test]
+    fn orders_clicked_corners() {
+        let corners = [
+            Point2f::new(90.0, 95.0),
+            Point2f::new(10.0, 5.0),
+            Point2f::new(5.0, 80.0),
+            Point2f::new(100.0, 10.0),
+        ];
+        let ordered = order_corners(corners);
+        assert_eq!(ordered, [corners[1], corners[3], corners[0], corners[2]]);
+        assert_eq!(warp_size(&ordered), Size::new(91, 87));
+    }
+}
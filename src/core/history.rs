@@ -0,0 +1,143 @@
+// Undo/redo history of editor states with a memory budget
+//
+// Each entry is a full snapshot of the edited state (ROI list, image bytes, ...)
+// taken just before an operation, so undoing never has to re-run a filter.
+use std::collections::VecDeque;
+
+/// Approximate heap footprint of a history entry, used to enforce the budget.
+pub trait HistorySize {
+    fn history_bytes(&self) -> usize;
+}
+
+impl<T: HistorySize> HistorySize for Vec<T> {
+    fn history_bytes(&self) -> usize {
+        self.iter().map(HistorySize::history_bytes).sum()
+    }
+}
+
+impl HistorySize for Vec<u8> {
+    fn history_bytes(&self) -> usize {
+        self.len()
+    }
+}
+
+impl HistorySize for String {
+    fn history_bytes(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: HistorySize> HistorySize for Option<T> {
+    fn history_bytes(&self) -> usize {
+        self.as_ref().map(HistorySize::history_bytes).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct History<T> {
+    undo: VecDeque<T>,
+    redo: Vec<T>,
+    budget_bytes: usize,
+    used_bytes: usize,
+}
+
+impl<T: HistorySize> History<T> {
+    pub fn new(budget_bytes: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: vec![],
+            budget_bytes,
+            used_bytes: 0,
+        }
+    }
+
+    /// Stores the state from just before an edit. Any redo entries are dropped.
+    pub fn record(&mut self, before: T) {
+        for entry in self.redo.drain(..) {
+            self.used_bytes -= entry.history_bytes();
+        }
+        self.used_bytes += before.history_bytes();
+        self.undo.push_back(before);
+        self.enforce_budget();
+    }
+
+    /// Swaps `current` for the previous state, or returns `None` if there is none.
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let previous = self.undo.pop_back()?;
+        self.used_bytes -= previous.history_bytes();
+        self.used_bytes += current.history_bytes();
+        self.redo.push(current);
+        self.enforce_budget();
+        Some(previous)
+    }
+
+    /// Re-applies the last undone state.
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let next = self.redo.pop()?;
+        self.used_bytes -= next.history_bytes();
+        self.used_bytes += current.history_bytes();
+        self.undo.push_back(current);
+        self.enforce_budget();
+        Some(next)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.used_bytes = 0;
+    }
+
+    // Oldest undo steps go first; the most recent one is always kept so a
+    // single oversized state can still be undone.
+    fn enforce_budget(&mut self) {
+        while self.used_bytes > self.budget_bytes && self.undo.len() > 1 {
+            if let Some(oldest) = self.undo.pop_front() {
+                self.used_bytes -= oldest.history_bytes();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_swap_states() {
+        let mut history = History::new(1024);
+        history.record("a".to_string());
+        assert_eq!(history.undo("b".to_string()), Some("a".to_string()));
+        assert_eq!(history.redo("a".to_string()), Some("b".to_string()));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn record_clears_redo() {
+        let mut history = History::new(1024);
+        history.record("a".to_string());
+        history.undo("b".to_string());
+        history.record("c".to_string());
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn budget_evicts_oldest_but_keeps_one() {
+        let mut history = History::new(4);
+        history.record("aaa".to_string());
+        history.record("bbb".to_string());
+        assert_eq!(history.undo("x".to_string()), Some("bbb".to_string()));
+        assert!(!history.can_undo());
+
+        let mut history = History::new(1);
+        history.record("oversized".to_string());
+        assert!(history.can_undo());
+    }
+}
@@ -0,0 +1,92 @@
+// Magnified view of the pixels around the cursor
+use opencv::{
+    core::{copy_make_border, Rect, Scalar, Size, BORDER_CONSTANT},
+    imgproc,
+    prelude::*,
+};
+
+use crate::core::color_model::{to_color_space, ChannelOrder, ColorSpace};
+use crate::core::crop::clip_rect;
+
+/// Pixels from the centre to the loupe's edge, so it shows 15 x 15 pixels
+pub const LOUPE_RADIUS: i32 = 7;
+/// On-screen size of one magnified pixel
+pub const LOUPE_CELL_PX: i32 = 10;
+
+// Part of the image a loupe centred on `center` covers, and the padding
+// (left, top, right, bottom) standing in for what lies beyond the image
+fn loupe_window(
+    center: (i32, i32),
+    radius: i32,
+    image_width: i32,
+    image_height: i32,
+) -> Option<(Rect, [i32; 4])> {
+    let side = 2 * radius + 1;
+    let window = Rect::new(center.0 - radius, center.1 - radius, side, side);
+    let inside = clip_rect(&window, image_width, image_height)?;
+    let padding = [
+        inside.x - window.x,
+        inside.y - window.y,
+        window.x + side - (inside.x + inside.width),
+        window.y + side - (inside.y + inside.height),
+    ];
+    Some((inside, padding))
+}
+
+/// The `(2 * radius + 1)`-pixel square around `center` of `mat` (stored as
+/// `order`), as BGR scaled up `cell` times without smoothing. Pixels past the
+/// image edge are black. `None` when the square misses the image entirely.
+pub fn loupe(
+    mat: &Mat,
+    order: ChannelOrder,
+    center: (i32, i32),
+    radius: i32,
+    cell: i32,
+) -> opencv::Result<Option<Mat>> {
+    let Some((inside, [left, top, right, bottom])) =
+        loupe_window(center, radius, mat.cols(), mat.rows())
+    else {
+        return Ok(None);
+    };
+    let bgr = to_color_space(&Mat::roi(mat, inside)?.try_clone()?, order, ColorSpace::Bgr)?;
+    let mut padded = Mat::default();
+    copy_make_border(
+        &bgr,
+        &mut padded,
+        top,
+        bottom,
+        left,
+        right,
+        BORDER_CONSTANT,
+        Scalar::default(),
+    )?;
+    let side = (2 * radius + 1) * cell;
+    let mut magnified = Mat::default();
+    imgproc::resize(
+        &padded,
+        &mut magnified,
+        Size::new(side, side),
+        0.0,
+        0.0,
+        imgproc::INTER_NEAREST,
+    )?;
+    Ok(Some(magnified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loupe_window_pads_past_the_edges() {
+        assert_eq!(
+            loupe_window((50, 50), 7, 100, 100),
+            Some((Rect::new(43, 43, 15, 15), [0, 0, 0, 0]))
+        );
+        assert_eq!(
+            loupe_window((2, 97), 7, 100, 100),
+            Some((Rect::new(0, 90, 10, 10), [5, 0, 0, 5]))
+        );
+        assert_eq!(loupe_window((-8, 50), 7, 100, 100), None);
+    }
+}
@@ -0,0 +1,30 @@
+// UI-free building blocks: coordinate transforms, the ROI model, hit testing,
+// pixel access and magnification, cropping, image filters (denoising,
+// thresholding, morphology, tone, geometry) and pipelines, batch processing,
+// folder thumbnails and the sidecar/dataset/statistics I/O.
+//
+// Nothing in here depends on Dioxus; it is also built as the library behind
+// the `imgtool` binary, so the GUI and the command line share one behaviour.
+pub mod annotations;
+pub mod batch;
+pub mod color_model;
+pub mod crop;
+pub mod denoise;
+pub mod encode;
+pub mod exif;
+pub mod filters;
+pub mod geometry;
+pub mod history;
+pub mod loupe;
+pub mod morphology;
+pub mod pipeline;
+pub mod pixel_export;
+pub mod recipe;
+pub mod roi_edit;
+pub mod roi_model;
+pub mod roi_sidecar;
+pub mod roi_stats;
+pub mod threshold;
+pub mod thumbnails;
+pub mod tone;
+pub mod transform;
@@ -0,0 +1,135 @@
+// Morphological operations for cleaning up masks: erode, dilate and the
+// compound operations OpenCV builds from them
+use opencv::{
+    core::{Point, Size, BORDER_CONSTANT},
+    imgproc,
+    prelude::*,
+};
+
+pub const MORPH_SIZE: i32 = 3;
+pub const MORPH_ITERATIONS: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MorphOp {
+    Erode,
+    Dilate,
+    /// Erode then dilate: removes specks smaller than the element
+    Open,
+    /// Dilate then erode: fills holes smaller than the element
+    Close,
+    /// Dilation minus erosion: the outlines
+    Gradient,
+    /// Image minus its opening: small bright details
+    TopHat,
+    /// Closing minus the image: small dark details
+    BlackHat,
+}
+
+impl MorphOp {
+    pub const ALL: [MorphOp; 7] = [
+        MorphOp::Erode,
+        MorphOp::Dilate,
+        MorphOp::Open,
+        MorphOp::Close,
+        MorphOp::Gradient,
+        MorphOp::TopHat,
+        MorphOp::BlackHat,
+    ];
+
+    pub const NAMES: [&'static str; 7] = [
+        "erode", "dilate", "open", "close", "gradient", "tophat", "blackhat",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|op| op.name() == name)
+    }
+
+    fn code(&self) -> i32 {
+        match self {
+            MorphOp::Erode => imgproc::MORPH_ERODE,
+            MorphOp::Dilate => imgproc::MORPH_DILATE,
+            MorphOp::Open => imgproc::MORPH_OPEN,
+            MorphOp::Close => imgproc::MORPH_CLOSE,
+            MorphOp::Gradient => imgproc::MORPH_GRADIENT,
+            MorphOp::TopHat => imgproc::MORPH_TOPHAT,
+            MorphOp::BlackHat => imgproc::MORPH_BLACKHAT,
+        }
+    }
+}
+
+/// Shape of the structuring element
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KernelShape {
+    Rect,
+    Ellipse,
+    Cross,
+}
+
+impl KernelShape {
+    pub const ALL: [KernelShape; 3] = [KernelShape::Rect, KernelShape::Ellipse, KernelShape::Cross];
+
+    pub const NAMES: [&'static str; 3] = ["rect", "ellipse", "cross"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|shape| shape.name() == name)
+    }
+
+    fn code(&self) -> i32 {
+        match self {
+            KernelShape::Rect => imgproc::MORPH_RECT,
+            KernelShape::Ellipse => imgproc::MORPH_ELLIPSE,
+            KernelShape::Cross => imgproc::MORPH_CROSS,
+        }
+    }
+}
+
+/// `op` with a `size` x `size` element of `shape`, repeated `iterations` times.
+/// Works per channel, so colour images are fine too; pixels beyond the border
+/// never win an erosion or dilation.
+pub fn morphology(
+    input: &Mat,
+    op: MorphOp,
+    shape: KernelShape,
+    size: i32,
+    iterations: i32,
+) -> opencv::Result<Mat> {
+    let anchor = Point::new(-1, -1);
+    let kernel = imgproc::get_structuring_element(shape.code(), Size::new(size, size), anchor)?;
+    let mut output = Mat::default();
+    imgproc::morphology_ex(
+        input,
+        &mut output,
+        op.code(),
+        &kernel,
+        anchor,
+        iterations,
+        BORDER_CONSTANT,
+        imgproc::morphology_default_border_value()?,
+    )?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for op in MorphOp::ALL {
+            assert_eq!(MorphOp::from_name(op.name()), Some(op));
+        }
+        for shape in KernelShape::ALL {
+            assert_eq!(KernelShape::from_name(shape.name()), Some(shape));
+        }
+        assert_eq!(MorphOp::from_name("blackhat"), Some(MorphOp::BlackHat));
+        assert_eq!(KernelShape::from_name("disk"), None);
+    }
+}
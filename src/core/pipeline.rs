@@ -0,0 +1,279 @@
+// Non-destructive filter pipeline
+//
+// The toolbox never modifies the loaded image: it keeps an ordered list of
+// steps and recomputes the result from the pristine source whenever a step is
+// added, toggled, reordered or edited.
+use opencv::{core::Size, imgproc, prelude::*};
+use std::fmt;
+
+use crate::core::filters::Filter;
+use crate::core::geometry::map_rois;
+use crate::core::history::HistorySize;
+use crate::core::roi_model::LabeledRoi;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub filter: Filter,
+    /// Disabled steps pass their input through unchanged
+    pub enabled: bool,
+}
+
+/// A step that failed while running the pipeline
+#[derive(Debug)]
+pub struct StepError {
+    pub index: usize,
+    pub filter: Filter,
+    pub error: opencv::Error,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "step {} ({}): {}",
+            self.index + 1,
+            self.filter,
+            self.error
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pipeline {
+    pub steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn new(filters: impl IntoIterator<Item = Filter>) -> Self {
+        Pipeline {
+            steps: filters
+                .into_iter()
+                .map(|filter| Step {
+                    filter,
+                    enabled: true,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn push(&mut self, filter: Filter) {
+        self.steps.push(Step {
+            filter,
+            enabled: true,
+        });
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.steps.len() {
+            self.steps.remove(index);
+        }
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(step) = self.steps.get_mut(index) {
+            step.enabled = !step.enabled;
+        }
+    }
+
+    pub fn set_filter(&mut self, index: usize, filter: Filter) {
+        if let Some(step) = self.steps.get_mut(index) {
+            step.filter = filter;
+        }
+    }
+
+    /// Moves the step at `from` so that it ends up at position `to`
+    pub fn move_step(&mut self, from: usize, to: usize) {
+        if from < self.steps.len() && to < self.steps.len() {
+            let step = self.steps.remove(from);
+            self.steps.insert(to, step);
+        }
+    }
+
+    /// The enabled filters, in order
+    pub fn active_filters(&self) -> impl Iterator<Item = &Filter> {
+        self.steps
+            .iter()
+            .filter(|step| step.enabled)
+            .map(|step| &step.filter)
+    }
+
+    pub fn run(&self, source: Mat) -> Result<Mat, StepError> {
+        self.run_with(source, |_, _| Ok(()))
+    }
+
+    /// Runs every step on `source`, calling `inspect` with each step's output
+    /// (the unchanged input for disabled steps)
+    pub fn run_with(
+        &self,
+        source: Mat,
+        mut inspect: impl FnMut(usize, &Mat) -> opencv::Result<()>,
+    ) -> Result<Mat, StepError> {
+        let mut current = source;
+        for (index, step) in self.steps.iter().enumerate() {
+            let fail = |error| StepError {
+                index,
+                filter: step.filter.clone(),
+                error,
+            };
+            if step.enabled {
+                current = step.filter.apply(&current).map_err(fail)?;
+            }
+            inspect(index, &current).map_err(fail)?;
+        }
+        Ok(current)
+    }
+
+    /// `run_with`, also carrying `rois` (in `source` pixels) through the
+    /// geometric steps; ROIs that leave the image are dropped
+    pub fn run_with_rois(
+        &self,
+        source: Mat,
+        rois: &[LabeledRoi],
+        mut inspect: impl FnMut(usize, &Mat) -> opencv::Result<()>,
+    ) -> Result<(Mat, Vec<LabeledRoi>), StepError> {
+        let mut size = Size::new(source.cols(), source.rows());
+        let mut rois = rois.to_vec();
+        let output = self.run_with(source, |index, mat| {
+            let output = Size::new(mat.cols(), mat.rows());
+            let step = &self.steps[index];
+            if step.enabled && !rois.is_empty() {
+                if let Some(matrix) = step.filter.geometry(size)? {
+                    rois = map_rois(&matrix, &rois, output);
+                }
+            }
+            size = output;
+            inspect(index, mat)
+        })?;
+        Ok((output, rois))
+    }
+
+    /// The same steps for a source `factor` times the size, see [`Filter::scaled`]
+    pub fn scaled(&self, factor: f64) -> Pipeline {
+        Pipeline {
+            steps: self
+                .steps
+                .iter()
+                .map(|step| Step {
+                    filter: step.filter.scaled(factor),
+                    enabled: step.enabled,
+                })
+                .collect(),
+        }
+    }
+
+    /// Output size for a `size` source, and `rois` carried along as
+    /// `run_with_rois` would, without running any step
+    pub fn layout(
+        &self,
+        size: Size,
+        rois: &[LabeledRoi],
+    ) -> opencv::Result<(Size, Vec<LabeledRoi>)> {
+        let mut size = size;
+        let mut rois = rois.to_vec();
+        for filter in self.active_filters() {
+            let output = filter.output_size(size);
+            if !rois.is_empty() {
+                if let Some(matrix) = filter.geometry(size)? {
+                    rois = map_rois(&matrix, &rois, output);
+                }
+            }
+            size = output;
+        }
+        Ok((size, rois))
+    }
+}
+
+impl HistorySize for Pipeline {
+    fn history_bytes(&self) -> usize {
+        self.steps.len() * std::mem::size_of::<Step>()
+    }
+}
+
+/// Downscales `mat` so its longer side is at most `max_side` pixels
+pub fn thumbnail(mat: &Mat, max_side: i32) -> opencv::Result<Mat> {
+    let longest = mat.cols().max(mat.rows());
+    if longest <= max_side {
+        return mat.try_clone();
+    }
+    let ratio = max_side as f64 / longest as f64;
+    let size = Size::new(
+        ((mat.cols() as f64 * ratio).round() as i32).max(1),
+        ((mat.rows() as f64 * ratio).round() as i32).max(1),
+    );
+    let mut small = Mat::default();
+    imgproc::resize(mat, &mut small, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+    Ok(small)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencv::core::Rect;
+
+    fn names(pipeline: &Pipeline) -> Vec<&'static str> {
+        pipeline.steps.iter().map(|s| s.filter.name()).collect()
+    }
+
+    #[test]
+    fn edits_steps_in_place() {
+        let mut pipeline = Pipeline::new([Filter::Grayscale, Filter::Invert, Filter::Rotate90]);
+        pipeline.move_step(2, 0);
+        assert_eq!(names(&pipeline), ["rotate", "grayscale", "invert"]);
+        pipeline.move_step(0, 2);
+        assert_eq!(names(&pipeline), ["grayscale", "invert", "rotate"]);
+
+        pipeline.toggle(1);
+        assert!(!pipeline.steps[1].enabled);
+        assert_eq!(
+            pipeline.active_filters().cloned().collect::<Vec<_>>(),
+            [Filter::Grayscale, Filter::Rotate90]
+        );
+
+        pipeline.set_filter(0, Filter::Blur { ksize: 3 });
+        pipeline.remove(2);
+        assert_eq!(names(&pipeline), ["blur", "invert"]);
+    }
+
+    #[test]
+    fn layout_follows_the_geometric_steps() {
+        let mut pipeline = Pipeline::new([
+            Filter::Crop {
+                rect: Rect::new(10, 0, 200, 50),
+            },
+            Filter::Grayscale,
+            Filter::Rotate90,
+            Filter::Resize {
+                width: 10,
+                height: 10,
+            },
+        ]);
+        pipeline.toggle(3);
+        let rois = [LabeledRoi::new(Rect::new(20, 0, 10, 5), "leaf")];
+        let (size, moved) = pipeline.layout(Size::new(100, 50), &rois).unwrap();
+        assert_eq!(size, Size::new(50, 90));
+        assert_eq!(moved[0].rect, Rect::new(45, 10, 5, 10));
+    }
+
+    #[test]
+    fn scaling_keeps_the_steps() {
+        let mut pipeline = Pipeline::new([Filter::Blur { ksize: 15 }, Filter::Invert]);
+        pipeline.toggle(1);
+        let scaled = pipeline.scaled(0.5);
+        assert_eq!(scaled.steps[0].filter, Filter::Blur { ksize: 8 });
+        assert_eq!(scaled.steps[1].filter, Filter::Invert);
+        assert!(!scaled.steps[1].enabled);
+    }
+
+    #[test]
+    fn ignores_out_of_range_indices() {
+        let mut pipeline = Pipeline::new([Filter::Invert]);
+        pipeline.move_step(0, 3);
+        pipeline.toggle(5);
+        pipeline.remove(1);
+        assert_eq!(pipeline, Pipeline::new([Filter::Invert]));
+    }
+}
@@ -0,0 +1,262 @@
+// ROI pixel extraction and export to CSV, NumPy `.npy` and raw binary
+use opencv::{
+    core::{Rect, Vec3b},
+    prelude::*,
+};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::color_model::ColorSpace;
+use crate::core::roi_model::LabeledRoi;
+
+/// 8-bit pixel values of one ROI, row-major with interleaved channels (HWC).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoiPixels {
+    /// ROI clipped to the image, in image coordinates
+    pub rect: Rect,
+    pub label: String,
+    pub color_space: ColorSpace,
+    pub data: Vec<u8>,
+}
+
+impl RoiPixels {
+    /// Reads the pixels under `roi` from a Mat already converted to
+    /// `color_space`. Returns `None` if the ROI lies outside the image or the
+    /// image isn't 8-bit 1/3-channel.
+    pub fn from_mat(
+        mat: &Mat,
+        roi: &LabeledRoi,
+        color_space: ColorSpace,
+    ) -> opencv::Result<Option<RoiPixels>> {
+        let x0 = roi.rect.x.max(0);
+        let y0 = roi.rect.y.max(0);
+        let x1 = (roi.rect.x + roi.rect.width).min(mat.cols());
+        let y1 = (roi.rect.y + roi.rect.height).min(mat.rows());
+        if x1 <= x0 || y1 <= y0 {
+            return Ok(None);
+        }
+        let rect = Rect::new(x0, y0, x1 - x0, y1 - y0);
+
+        let mut data = Vec::with_capacity((rect.width * rect.height) as usize * 3);
+        match mat.typ() {
+            opencv::core::CV_8UC1 => {
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        data.push(*mat.at_2d::<u8>(y, x)?);
+                    }
+                }
+            }
+            opencv::core::CV_8UC3 => {
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let p = mat.at_2d::<Vec3b>(y, x)?;
+                        data.extend_from_slice(&[p[0], p[1], p[2]]);
+                    }
+                }
+            }
+            _ => return Ok(None),
+        }
+
+        // A gray Mat is always reported as such, whatever the caller assumed
+        let color_space = if mat.channels() == 1 {
+            ColorSpace::Gray
+        } else {
+            color_space
+        };
+
+        Ok(Some(RoiPixels {
+            rect,
+            label: roi.label.clone(),
+            color_space,
+            data,
+        }))
+    }
+
+    pub fn channels(&self) -> usize {
+        self.color_space.channels()
+    }
+
+    /// Values of all channels at `(row, col)` relative to the ROI origin.
+    pub fn pixel(&self, row: i32, col: i32) -> &[u8] {
+        let c = self.channels();
+        let start = (row as usize * self.rect.width as usize + col as usize) * c;
+        &self.data[start..start + c]
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PixelMetadata<'a> {
+    label: &'a str,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    channels: usize,
+    color_space: &'a str,
+    channel_names: &'a [&'a str],
+    dtype: &'a str,
+    layout: &'a str,
+    files: Vec<String>,
+}
+
+/// One row per pixel: image coordinates followed by the channel values.
+pub fn write_csv(pixels: &RoiPixels, path: &Path) -> io::Result<()> {
+    let mut csv = String::from("x,y");
+    for name in pixels.color_space.channel_names() {
+        csv.push(',');
+        csv.push_str(name);
+    }
+    csv.push('\n');
+
+    for row in 0..pixels.rect.height {
+        for col in 0..pixels.rect.width {
+            csv.push_str(&format!("{},{}", pixels.rect.x + col, pixels.rect.y + row));
+            for v in pixels.pixel(row, col) {
+                csv.push_str(&format!(",{}", v));
+            }
+            csv.push('\n');
+        }
+    }
+    fs::write(path, csv)
+}
+
+/// NumPy format 1.0, `uint8` with shape `(h, w)` or `(h, w, c)`.
+pub fn write_npy(pixels: &RoiPixels, path: &Path) -> io::Result<()> {
+    let shape = if pixels.channels() == 1 {
+        format!("({}, {})", pixels.rect.height, pixels.rect.width)
+    } else {
+        format!(
+            "({}, {}, {})",
+            pixels.rect.height,
+            pixels.rect.width,
+            pixels.channels()
+        )
+    };
+    let mut header = format!(
+        "{{'descr': '|u1', 'fortran_order': False, 'shape': {}, }}",
+        shape
+    );
+    // Magic (6) + version (2) + header length (2) + header must be a multiple of 64
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(10 + header.len() + pixels.data.len());
+    out.extend_from_slice(b"\x93NUMPY\x01\x00");
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(&pixels.data);
+    fs::write(path, out)
+}
+
+/// Bare pixel bytes; layout and shape are described in the metadata JSON.
+pub fn write_raw(pixels: &RoiPixels, path: &Path) -> io::Result<()> {
+    fs::write(path, &pixels.data)
+}
+
+pub(crate) fn safe_file_part(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Writes `.csv`, `.npy`, `.bin` and a `.json` metadata file for every ROI,
+/// named `<image stem>_roi<NNN>_<label>.*`. `mat` must already be in
+/// `color_space`. Returns the number of ROIs written.
+pub fn export_roi_pixels(
+    mat: &Mat,
+    rois: &[LabeledRoi],
+    color_space: ColorSpace,
+    image_path: &Path,
+    out_dir: &Path,
+) -> io::Result<usize> {
+    fs::create_dir_all(out_dir)?;
+    let stem = image_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "image".to_string());
+
+    let mut written = 0;
+    for (i, roi) in rois.iter().enumerate() {
+        let pixels = match RoiPixels::from_mat(mat, roi, color_space) {
+            Ok(Some(pixels)) => pixels,
+            Ok(None) => continue,
+            Err(e) => return Err(io::Error::other(e.to_string())),
+        };
+
+        let base = format!("{}_roi{:03}_{}", stem, i + 1, safe_file_part(&roi.label));
+        let csv = format!("{}.csv", base);
+        let npy = format!("{}.npy", base);
+        let bin = format!("{}.bin", base);
+        write_csv(&pixels, &out_dir.join(&csv))?;
+        write_npy(&pixels, &out_dir.join(&npy))?;
+        write_raw(&pixels, &out_dir.join(&bin))?;
+
+        let metadata = PixelMetadata {
+            label: &pixels.label,
+            x: pixels.rect.x,
+            y: pixels.rect.y,
+            width: pixels.rect.width,
+            height: pixels.rect.height,
+            channels: pixels.channels(),
+            color_space: pixels.color_space.name(),
+            channel_names: pixels.color_space.channel_names(),
+            dtype: "uint8",
+            layout: "HWC, row-major, channels interleaved",
+            files: vec![csv, npy, bin],
+        };
+        let json = serde_json::to_string_pretty(&metadata)?;
+        fs::write(out_dir.join(format!("{}.json", base)), json)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roi(x: i32, y: i32, width: i32, height: i32) -> LabeledRoi {
+        LabeledRoi::new(Rect::new(x, y, width, height), "roi")
+    }
+
+    #[test]
+    fn reads_gray_pixels_clipped_to_image() {
+        let mat = Mat::from_slice_2d(&[[1u8, 2, 3], [4, 5, 6]]).unwrap();
+        let pixels = RoiPixels::from_mat(&mat, &roi(1, -1, 5, 5), ColorSpace::Rgb)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pixels.rect, Rect::new(1, 0, 2, 2));
+        assert_eq!(pixels.color_space, ColorSpace::Gray);
+        assert_eq!(pixels.data, vec![2, 3, 5, 6]);
+        assert_eq!(pixels.pixel(1, 0), &[5]);
+    }
+
+    #[test]
+    fn reads_interleaved_color_pixels() {
+        let mat = Mat::from_slice_2d(&[[Vec3b::from([1, 2, 3]), Vec3b::from([4, 5, 6])]]).unwrap();
+        let pixels = RoiPixels::from_mat(&mat, &roi(0, 0, 2, 1), ColorSpace::Bgr)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pixels.channels(), 3);
+        assert_eq!(pixels.pixel(0, 1), &[4, 5, 6]);
+    }
+
+    #[test]
+    fn roi_outside_image_has_no_pixels() {
+        let mat = Mat::from_slice_2d(&[[1u8, 2], [3, 4]]).unwrap();
+        assert!(
+            RoiPixels::from_mat(&mat, &roi(5, 5, 2, 2), ColorSpace::Gray)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
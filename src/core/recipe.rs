@@ -0,0 +1,491 @@
+// Shareable filter recipes: a pipeline saved as TOML or JSON
+//
+//   version = 1
+//
+//   [[steps]]
+//   op = "canny"
+//   params = { low = 50.0, high = 150.0, aperture = 3.0 }
+//
+//   [[steps]]
+//   op = "threshold"
+//   params = { method = "otsu", inverted = true }
+//
+// `op` is the operation name `imgtool` accepts; missing params take the
+// toolbox defaults. The format follows the file extension (`.toml`, else JSON).
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::filters::Filter;
+use crate::core::geometry::{BorderMode, FlipAxis};
+use crate::core::morphology::{KernelShape, MorphOp};
+use crate::core::pipeline::{Pipeline, Step};
+use crate::core::threshold::ThresholdMethod;
+use crate::core::tone::ToneChannels;
+
+pub const RECIPE_VERSION: u32 = 1;
+
+// Parameter names of the warp corners
+const CORNER_KEYS: [(&str, &str); 4] = [("x1", "y1"), ("x2", "y2"), ("x3", "y3"), ("x4", "y4")];
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A parameter value; most are numbers, a few are names or switches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParamValue {
+    Flag(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl From<f64> for ParamValue {
+    fn from(value: f64) -> Self {
+        ParamValue::Number(value)
+    }
+}
+
+impl From<&'static str> for ParamValue {
+    fn from(name: &'static str) -> Self {
+        ParamValue::Text(name.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecipeStep {
+    pub op: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, ParamValue>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl From<&Step> for RecipeStep {
+    fn from(step: &Step) -> Self {
+        let params: Vec<(&str, ParamValue)> = match &step.filter {
+            Filter::Blur { ksize } => vec![("ksize", (*ksize as f64).into())],
+            Filter::Resize { width, height } => vec![
+                ("width", (*width as f64).into()),
+                ("height", (*height as f64).into()),
+            ],
+            Filter::Canny {
+                low,
+                high,
+                aperture,
+            } => vec![
+                ("low", (*low).into()),
+                ("high", (*high).into()),
+                ("aperture", (*aperture as f64).into()),
+            ],
+            Filter::Crop { rect } => vec![
+                ("x", (rect.x as f64).into()),
+                ("y", (rect.y as f64).into()),
+                ("width", (rect.width as f64).into()),
+                ("height", (rect.height as f64).into()),
+            ],
+            // Only the numbers the method uses
+            Filter::Threshold {
+                method,
+                value,
+                block_size,
+                c,
+                inverted,
+            } => {
+                let mut params = vec![
+                    ("method", method.name().into()),
+                    ("inverted", ParamValue::Flag(*inverted)),
+                ];
+                if *method == ThresholdMethod::Fixed {
+                    params.push(("value", (*value).into()));
+                }
+                if method.is_adaptive() {
+                    params.push(("block_size", (*block_size as f64).into()));
+                    params.push(("c", (*c).into()));
+                }
+                params
+            }
+            Filter::Morph {
+                op,
+                shape,
+                size,
+                iterations,
+            } => vec![
+                ("operation", op.name().into()),
+                ("shape", shape.name().into()),
+                ("size", (*size as f64).into()),
+                ("iterations", (*iterations as f64).into()),
+            ],
+            Filter::GaussianBlur { ksize, sigma } => {
+                vec![
+                    ("ksize", (*ksize as f64).into()),
+                    ("sigma", (*sigma).into()),
+                ]
+            }
+            Filter::Median { ksize } => vec![("ksize", (*ksize as f64).into())],
+            Filter::Bilateral {
+                diameter,
+                sigma_color,
+                sigma_space,
+            } => vec![
+                ("diameter", (*diameter as f64).into()),
+                ("sigma_color", (*sigma_color).into()),
+                ("sigma_space", (*sigma_space).into()),
+            ],
+            Filter::NlMeans {
+                h,
+                h_color,
+                template,
+                search,
+            } => vec![
+                ("h", (*h).into()),
+                ("h_color", (*h_color).into()),
+                ("template", (*template as f64).into()),
+                ("search", (*search as f64).into()),
+            ],
+            Filter::Sharpen { sigma, amount } => {
+                vec![("sigma", (*sigma).into()), ("amount", (*amount).into())]
+            }
+            Filter::Equalize { channels } => vec![("channels", channels.name().into())],
+            Filter::Clahe {
+                clip_limit,
+                tiles,
+                channels,
+            } => vec![
+                ("clip_limit", (*clip_limit).into()),
+                ("tiles", (*tiles as f64).into()),
+                ("channels", channels.name().into()),
+            ],
+            Filter::Gamma { gamma, channels } => vec![
+                ("gamma", (*gamma).into()),
+                ("channels", channels.name().into()),
+            ],
+            Filter::BrightnessContrast {
+                brightness,
+                contrast,
+                channels,
+            } => vec![
+                ("brightness", (*brightness).into()),
+                ("contrast", (*contrast).into()),
+                ("channels", channels.name().into()),
+            ],
+            Filter::Levels {
+                black,
+                white,
+                gamma,
+                channels,
+            } => vec![
+                ("black", (*black).into()),
+                ("white", (*white).into()),
+                ("gamma", (*gamma).into()),
+                ("channels", channels.name().into()),
+            ],
+            Filter::Flip { axis } => vec![("axis", axis.name().into())],
+            Filter::RotateBy { angle, border } => {
+                vec![("angle", (*angle).into()), ("border", border.name().into())]
+            }
+            // x1, y1 (top-left) to x4, y4 (bottom-left)
+            Filter::Perspective { corners } => {
+                let mut params = vec![];
+                for (corner, keys) in corners.iter().zip(CORNER_KEYS) {
+                    params.push((keys.0, (corner.x as f64).into()));
+                    params.push((keys.1, (corner.y as f64).into()));
+                }
+                params
+            }
+            Filter::Grayscale
+            | Filter::Invert
+            | Filter::Rotate90
+            | Filter::Rotate180
+            | Filter::Rotate270 => vec![],
+        };
+        RecipeStep {
+            op: step.filter.name().to_string(),
+            params: params
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+            enabled: step.enabled,
+        }
+    }
+}
+
+impl TryFrom<&RecipeStep> for Step {
+    type Error = String;
+
+    fn try_from(step: &RecipeStep) -> Result<Self, Self::Error> {
+        let mut filter: Filter = match step.op.as_str() {
+            // No default corners: they all come from the params
+            "warp" => Filter::Perspective {
+                corners: Default::default(),
+            },
+            op => op.parse()?,
+        };
+        for (key, value) in &step.params {
+            let number = || match value {
+                ParamValue::Number(n) => Ok(*n),
+                _ => Err(format!("{}: {} must be a number", step.op, key)),
+            };
+            let int = || {
+                let n = number()?;
+                if n.fract() == 0.0 {
+                    Ok(n as i32)
+                } else {
+                    Err(format!("{}: {} must be a whole number", step.op, key))
+                }
+            };
+            let flag = || match value {
+                ParamValue::Flag(b) => Ok(*b),
+                _ => Err(format!("{}: {} must be true or false", step.op, key)),
+            };
+            let name = || match value {
+                ParamValue::Text(name) => Ok(name.as_str()),
+                _ => Err(format!("{}: {} must be a name", step.op, key)),
+            };
+            let unknown = || format!("{}: unknown {} {:?}", step.op, key, value);
+            match (&mut filter, key.as_str()) {
+                (Filter::Blur { ksize }, "ksize")
+                | (Filter::GaussianBlur { ksize, .. }, "ksize")
+                | (Filter::Median { ksize }, "ksize") => *ksize = int()?,
+                (Filter::GaussianBlur { sigma, .. } | Filter::Sharpen { sigma, .. }, "sigma") => {
+                    *sigma = number()?
+                }
+                (Filter::Bilateral { diameter, .. }, "diameter") => *diameter = int()?,
+                (Filter::Bilateral { sigma_color, .. }, "sigma_color") => *sigma_color = number()?,
+                (Filter::Bilateral { sigma_space, .. }, "sigma_space") => *sigma_space = number()?,
+                (Filter::NlMeans { h, .. }, "h") => *h = number()?,
+                (Filter::NlMeans { h_color, .. }, "h_color") => *h_color = number()?,
+                (Filter::NlMeans { template, .. }, "template") => *template = int()?,
+                (Filter::NlMeans { search, .. }, "search") => *search = int()?,
+                (Filter::Sharpen { amount, .. }, "amount") => *amount = number()?,
+                (Filter::Resize { width, .. }, "width") => *width = int()?,
+                (Filter::Resize { height, .. }, "height") => *height = int()?,
+                (Filter::Canny { low, .. }, "low") => *low = number()?,
+                (Filter::Canny { high, .. }, "high") => *high = number()?,
+                (Filter::Canny { aperture, .. }, "aperture") => *aperture = int()?,
+                (Filter::Crop { rect }, "x") => rect.x = int()?,
+                (Filter::Crop { rect }, "y") => rect.y = int()?,
+                (Filter::Crop { rect }, "width") => rect.width = int()?,
+                (Filter::Crop { rect }, "height") => rect.height = int()?,
+                (Filter::Threshold { method, .. }, "method") => {
+                    *method = ThresholdMethod::from_name(name()?).ok_or_else(unknown)?
+                }
+                (Filter::Threshold { value, .. }, "value") => *value = number()?,
+                (Filter::Threshold { block_size, .. }, "block_size") => *block_size = int()?,
+                (Filter::Threshold { c, .. }, "c") => *c = number()?,
+                (Filter::Threshold { inverted, .. }, "inverted") => *inverted = flag()?,
+                (Filter::Morph { op, .. }, "operation") => {
+                    *op = MorphOp::from_name(name()?).ok_or_else(unknown)?
+                }
+                (Filter::Morph { shape, .. }, "shape") => {
+                    *shape = KernelShape::from_name(name()?).ok_or_else(unknown)?
+                }
+                (Filter::Morph { size, .. }, "size") => *size = int()?,
+                (Filter::Morph { iterations, .. }, "iterations") => *iterations = int()?,
+                (Filter::Equalize { channels }, "channels")
+                | (Filter::Clahe { channels, .. }, "channels")
+                | (Filter::Gamma { channels, .. }, "channels")
+                | (Filter::BrightnessContrast { channels, .. }, "channels")
+                | (Filter::Levels { channels, .. }, "channels") => {
+                    *channels = ToneChannels::from_name(name()?).ok_or_else(unknown)?
+                }
+                (Filter::Clahe { clip_limit, .. }, "clip_limit") => *clip_limit = number()?,
+                (Filter::Clahe { tiles, .. }, "tiles") => *tiles = int()?,
+                (Filter::Gamma { gamma, .. } | Filter::Levels { gamma, .. }, "gamma") => {
+                    *gamma = number()?
+                }
+                (Filter::BrightnessContrast { brightness, .. }, "brightness") => {
+                    *brightness = number()?
+                }
+                (Filter::BrightnessContrast { contrast, .. }, "contrast") => *contrast = number()?,
+                (Filter::Levels { black, .. }, "black") => *black = number()?,
+                (Filter::Levels { white, .. }, "white") => *white = number()?,
+                (Filter::Flip { axis }, "axis") => {
+                    *axis = FlipAxis::from_name(name()?).ok_or_else(unknown)?
+                }
+                (Filter::RotateBy { angle, .. }, "angle") => *angle = number()?,
+                (Filter::RotateBy { border, .. }, "border") => {
+                    *border = BorderMode::from_name(name()?).ok_or_else(unknown)?
+                }
+                (Filter::Perspective { corners }, key) => {
+                    let corner = CORNER_KEYS.iter().position(|(x, y)| key == *x || key == *y);
+                    let Some(i) = corner else {
+                        return Err(format!("{} has no parameter {}", step.op, key));
+                    };
+                    if key == CORNER_KEYS[i].0 {
+                        corners[i].x = number()? as f32;
+                    } else {
+                        corners[i].y = number()? as f32;
+                    }
+                }
+                _ => return Err(format!("{} has no parameter {}", step.op, key)),
+            }
+        }
+        // Same validation as `imgtool` op specs
+        let filter = filter.to_string().parse()?;
+        Ok(Step {
+            filter,
+            enabled: step.enabled,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    pub version: u32,
+    pub steps: Vec<RecipeStep>,
+}
+
+impl From<&Pipeline> for Recipe {
+    fn from(pipeline: &Pipeline) -> Self {
+        Recipe {
+            version: RECIPE_VERSION,
+            steps: pipeline.steps.iter().map(RecipeStep::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<&Recipe> for Pipeline {
+    type Error = String;
+
+    fn try_from(recipe: &Recipe) -> Result<Self, Self::Error> {
+        if recipe.version > RECIPE_VERSION {
+            return Err(format!(
+                "recipe version {} is newer than {}",
+                recipe.version, RECIPE_VERSION
+            ));
+        }
+        let steps = recipe
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| Step::try_from(step).map_err(|e| format!("step {}: {}", i + 1, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Pipeline { steps })
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+}
+
+fn invalid_data(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+pub fn recipe_to_string(recipe: &Recipe, toml: bool) -> io::Result<String> {
+    if toml {
+        toml::to_string_pretty(recipe).map_err(invalid_data)
+    } else {
+        Ok(serde_json::to_string_pretty(recipe)?)
+    }
+}
+
+pub fn recipe_from_str(text: &str, toml: bool) -> io::Result<Recipe> {
+    if toml {
+        toml::from_str(text).map_err(invalid_data)
+    } else {
+        Ok(serde_json::from_str(text)?)
+    }
+}
+
+pub fn save_recipe(path: &Path, pipeline: &Pipeline) -> io::Result<()> {
+    let text = recipe_to_string(&Recipe::from(pipeline), is_toml(path))?;
+    fs::write(path, text)
+}
+
+pub fn load_recipe(path: &Path) -> io::Result<Pipeline> {
+    let text = fs::read_to_string(path)?;
+    let recipe = recipe_from_str(&text, is_toml(path))?;
+    Pipeline::try_from(&recipe).map_err(invalid_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencv::core::Rect;
+
+    fn sample() -> Pipeline {
+        let mut pipeline = Pipeline::new([
+            Filter::Blur { ksize: 7 },
+            Filter::Grayscale,
+            Filter::Canny {
+                low: 12.5,
+                high: 80.0,
+                aperture: 5,
+            },
+            Filter::Crop {
+                rect: Rect::new(1, 2, 30, 40),
+            },
+            "threshold=mean:15:-2.5:inv".parse().unwrap(),
+            "morph=close:ellipse:5:2".parse().unwrap(),
+            "clahe=3:4:channels".parse().unwrap(),
+            "levels=16:235:1.2".parse().unwrap(),
+            "gaussian=7:1.5".parse().unwrap(),
+            "nlmeans=6:8:5:15".parse().unwrap(),
+            "sharpen=2:0.5".parse().unwrap(),
+            Filter::Rotate270,
+            "flip=vertical".parse().unwrap(),
+            "angle=-7.5:reflect".parse().unwrap(),
+            "warp=10.5:4:200:0:210:150:0:160".parse().unwrap(),
+        ]);
+        pipeline.toggle(1);
+        pipeline
+    }
+
+    #[test]
+    fn round_trips_through_json_and_toml() {
+        let pipeline = sample();
+        for toml in [false, true] {
+            let text = recipe_to_string(&Recipe::from(&pipeline), toml).unwrap();
+            let recipe = recipe_from_str(&text, toml).unwrap();
+            assert_eq!(recipe.version, RECIPE_VERSION);
+            assert_eq!(Pipeline::try_from(&recipe), Ok(pipeline.clone()));
+        }
+    }
+
+    #[test]
+    fn missing_params_take_defaults() {
+        let recipe = recipe_from_str(
+            "version = 1\n[[steps]]\nop = \"resize\"\nparams = { width = 64 }\n[[steps]]\nop = \"invert\"\n\
+             [[steps]]\nop = \"threshold\"\nparams = { method = \"otsu\", inverted = true }\n",
+            true,
+        )
+        .unwrap();
+        let pipeline = Pipeline::try_from(&recipe).unwrap();
+        assert_eq!(
+            pipeline,
+            Pipeline::new([
+                Filter::Resize {
+                    width: 64,
+                    height: crate::core::filters::RESIZE_SIZE.1
+                },
+                Filter::Invert,
+                "threshold=otsu:inv".parse().unwrap()
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_steps_and_newer_versions() {
+        let bad = |json: &str| Pipeline::try_from(&recipe_from_str(json, false).unwrap());
+        assert!(bad(r#"{"version":1,"steps":[{"op":"emboss"}]}"#).is_err());
+        assert!(bad(r#"{"version":1,"steps":[{"op":"blur","params":{"sigma":2}}]}"#).is_err());
+        assert!(bad(r#"{"version":1,"steps":[{"op":"blur","params":{"ksize":2.5}}]}"#).is_err());
+        assert!(bad(r#"{"version":1,"steps":[{"op":"canny","params":{"aperture":4}}]}"#).is_err());
+        assert!(bad(r#"{"version":1,"steps":[{"op":"blur","params":{"ksize":"big"}}]}"#).is_err());
+        assert!(
+            bad(r#"{"version":1,"steps":[{"op":"threshold","params":{"method":"binary"}}]}"#)
+                .is_err()
+        );
+        assert!(
+            bad(r#"{"version":1,"steps":[{"op":"threshold","params":{"inverted":1}}]}"#).is_err()
+        );
+        assert!(bad(r#"{"version":1,"steps":[{"op":"morph","params":{"shape":3}}]}"#).is_err());
+        assert!(bad(r#"{"version":99,"steps":[]}"#).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+// Geometry for editing existing ROIs: hit testing, resize handles, moving and clamping
+use opencv::core::Rect;
+
+use crate::core::roi_model::LabeledRoi;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Handle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+impl Handle {
+    pub const ALL: [Handle; 8] = [
+        Handle::TopLeft,
+        Handle::Top,
+        Handle::TopRight,
+        Handle::Right,
+        Handle::BottomRight,
+        Handle::Bottom,
+        Handle::BottomLeft,
+        Handle::Left,
+    ];
+
+    /// Position of the handle in image coordinates.
+    pub fn anchor(&self, rect: &Rect) -> (f32, f32) {
+        let left = rect.x as f32;
+        let top = rect.y as f32;
+        let right = (rect.x + rect.width) as f32;
+        let bottom = (rect.y + rect.height) as f32;
+        let mid_x = left + rect.width as f32 / 2.0;
+        let mid_y = top + rect.height as f32 / 2.0;
+        match self {
+            Handle::TopLeft => (left, top),
+            Handle::Top => (mid_x, top),
+            Handle::TopRight => (right, top),
+            Handle::Right => (right, mid_y),
+            Handle::BottomRight => (right, bottom),
+            Handle::Bottom => (mid_x, bottom),
+            Handle::BottomLeft => (left, bottom),
+            Handle::Left => (left, mid_y),
+        }
+    }
+
+    pub fn cursor(&self) -> &'static str {
+        match self {
+            Handle::TopLeft | Handle::BottomRight => "nwse-resize",
+            Handle::TopRight | Handle::BottomLeft => "nesw-resize",
+            Handle::Top | Handle::Bottom => "ns-resize",
+            Handle::Left | Handle::Right => "ew-resize",
+        }
+    }
+
+    fn moves_left(&self) -> bool {
+        matches!(self, Handle::TopLeft | Handle::Left | Handle::BottomLeft)
+    }
+
+    fn moves_right(&self) -> bool {
+        matches!(self, Handle::TopRight | Handle::Right | Handle::BottomRight)
+    }
+
+    fn moves_top(&self) -> bool {
+        matches!(self, Handle::TopLeft | Handle::Top | Handle::TopRight)
+    }
+
+    fn moves_bottom(&self) -> bool {
+        matches!(
+            self,
+            Handle::BottomLeft | Handle::Bottom | Handle::BottomRight
+        )
+    }
+}
+
+pub fn point_in_rect(x: i32, y: i32, rect: &Rect) -> bool {
+    x >= rect.x && y >= rect.y && x < rect.x + rect.width && y < rect.y + rect.height
+}
+
+/// Index of the topmost (last drawn) ROI containing image pixel `(x, y)`.
+pub fn roi_at(rois: &[LabeledRoi], x: i32, y: i32) -> Option<usize> {
+    rois.iter().rposition(|roi| point_in_rect(x, y, &roi.rect))
+}
+
+/// Handle of `rect` within `tolerance` image pixels of `(x, y)`, if any.
+pub fn hit_handle(rect: &Rect, x: f32, y: f32, tolerance: f32) -> Option<Handle> {
+    Handle::ALL.iter().copied().find(|handle| {
+        let (hx, hy) = handle.anchor(rect);
+        (x - hx).abs() <= tolerance && (y - hy).abs() <= tolerance
+    })
+}
+
+/// Shifts `rect` by `(dx, dy)`, keeping it fully inside the image.
+pub fn move_rect(rect: &Rect, dx: i32, dy: i32, image_width: i32, image_height: i32) -> Rect {
+    let max_x = (image_width - rect.width).max(0);
+    let max_y = (image_height - rect.height).max(0);
+    Rect::new(
+        (rect.x + dx).clamp(0, max_x),
+        (rect.y + dy).clamp(0, max_y),
+        rect.width,
+        rect.height,
+    )
+}
+
+/// Drags the edges owned by `handle` by `(dx, dy)`. Edges stop at the image
+/// border and never cross the opposite edge, so the result is at least 1x1.
+pub fn resize_rect(
+    rect: &Rect,
+    handle: Handle,
+    dx: i32,
+    dy: i32,
+    image_width: i32,
+    image_height: i32,
+) -> Rect {
+    let mut left = rect.x;
+    let mut top = rect.y;
+    let mut right = rect.x + rect.width;
+    let mut bottom = rect.y + rect.height;
+
+    if handle.moves_left() {
+        left = (left + dx).clamp(0, (right - 1).max(0));
+    }
+    if handle.moves_right() {
+        right = (right + dx).clamp(left + 1, image_width.max(left + 1));
+    }
+    if handle.moves_top() {
+        top = (top + dy).clamp(0, (bottom - 1).max(0));
+    }
+    if handle.moves_bottom() {
+        bottom = (bottom + dy).clamp(top + 1, image_height.max(top + 1));
+    }
+
+    Rect::new(left, top, right - left, bottom - top)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn point_in_rect_is_half_open() {
+        let rect = Rect::new(2, 2, 3, 3);
+        assert!(point_in_rect(2, 2, &rect));
+        assert!(point_in_rect(4, 4, &rect));
+        assert!(!point_in_rect(5, 4, &rect));
+        assert!(!point_in_rect(1, 3, &rect));
+    }
+
+    #[test]
+    fn roi_at_prefers_topmost() {
+        let rois = vec![
+            LabeledRoi::new(Rect::new(0, 0, 10, 10), "a"),
+            LabeledRoi::new(Rect::new(5, 5, 10, 10), "b"),
+        ];
+        assert_eq!(roi_at(&rois, 1, 1), Some(0));
+        assert_eq!(roi_at(&rois, 6, 6), Some(1));
+        assert_eq!(roi_at(&rois, 20, 20), None);
+    }
+
+    #[test]
+    fn hit_handle_uses_tolerance() {
+        let rect = Rect::new(10, 10, 20, 20);
+        assert_eq!(hit_handle(&rect, 11.0, 9.0, 2.0), Some(Handle::TopLeft));
+        assert_eq!(hit_handle(&rect, 20.0, 30.0, 2.0), Some(Handle::Bottom));
+        assert_eq!(hit_handle(&rect, 20.0, 20.0, 2.0), None);
+    }
+
+    #[test]
+    fn move_rect_stays_inside_image() {
+        let rect = Rect::new(5, 5, 10, 10);
+        assert_eq!(move_rect(&rect, -20, 3, 50, 50), Rect::new(0, 8, 10, 10));
+        assert_eq!(
+            move_rect(&rect, 100, 100, 50, 50),
+            Rect::new(40, 40, 10, 10)
+        );
+    }
+
+    #[test]
+    fn resize_rect_never_inverts() {
+        let rect = Rect::new(10, 10, 10, 10);
+        assert_eq!(
+            resize_rect(&rect, Handle::Right, -50, 0, 100, 100),
+            Rect::new(10, 10, 1, 10)
+        );
+        assert_eq!(
+            resize_rect(&rect, Handle::TopLeft, -20, -20, 100, 100),
+            Rect::new(0, 0, 20, 20)
+        );
+        assert_eq!(
+            resize_rect(&rect, Handle::BottomRight, 500, 500, 100, 100),
+            Rect::new(10, 10, 90, 90)
+        );
+    }
+}
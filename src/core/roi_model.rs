@@ -0,0 +1,178 @@
+// Labelled ROIs and the per-folder class palette
+use opencv::core::Rect;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::history::HistorySize;
+
+pub const DEFAULT_CLASS: &str = "roi";
+const PALETTE_FILE: &str = ".roi_classes.json";
+const FALLBACK_COLOR: &str = "#6b7280";
+
+// Colours handed out to new classes, in order
+const CLASS_COLORS: [&str; 8] = [
+    "#ef4444", "#3b82f6", "#22c55e", "#f59e0b", "#a855f7", "#ec4899", "#14b8a6", "#f97316",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabeledRoi {
+    pub rect: Rect,
+    pub label: String,
+    pub note: String,
+}
+
+impl LabeledRoi {
+    pub fn new(rect: Rect, label: impl Into<String>) -> Self {
+        LabeledRoi {
+            rect,
+            label: label.into(),
+            note: String::new(),
+        }
+    }
+}
+
+impl HistorySize for LabeledRoi {
+    fn history_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.label.len() + self.note.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoiClass {
+    pub name: String,
+    /// CSS colour, e.g. `#ef4444`
+    pub color: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassPalette {
+    pub classes: Vec<RoiClass>,
+}
+
+impl Default for ClassPalette {
+    fn default() -> Self {
+        ClassPalette {
+            classes: vec![RoiClass {
+                name: DEFAULT_CLASS.to_string(),
+                color: CLASS_COLORS[0].to_string(),
+            }],
+        }
+    }
+}
+
+impl ClassPalette {
+    pub fn palette_path(folder: &Path) -> PathBuf {
+        folder.join(PALETTE_FILE)
+    }
+
+    /// Palette stored in `folder`, or the default single-class palette.
+    pub fn load(folder: &Path) -> Self {
+        let path = Self::palette_path(folder);
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match serde_json::from_str::<ClassPalette>(&text) {
+            Ok(palette) if !palette.classes.is_empty() => palette,
+            Ok(_) => Self::default(),
+            Err(e) => {
+                eprintln!("Failed to parse {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, folder: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(Self::palette_path(folder), json)
+    }
+
+    pub fn color_for(&self, label: &str) -> &str {
+        self.classes
+            .iter()
+            .find(|c| c.name == label)
+            .map(|c| c.color.as_str())
+            .unwrap_or(FALLBACK_COLOR)
+    }
+
+    pub fn contains(&self, label: &str) -> bool {
+        self.classes.iter().any(|c| c.name == label)
+    }
+
+    /// Adds `name` with the next unused colour; returns false if it already exists.
+    pub fn add_class(&mut self, name: &str) -> bool {
+        let name = name.trim();
+        if name.is_empty() || self.contains(name) {
+            return false;
+        }
+        let color = CLASS_COLORS
+            .iter()
+            .find(|c| !self.classes.iter().any(|existing| existing.color == **c))
+            .unwrap_or(&CLASS_COLORS[self.classes.len() % CLASS_COLORS.len()]);
+        self.classes.push(RoiClass {
+            name: name.to_string(),
+            color: color.to_string(),
+        });
+        true
+    }
+
+    pub fn set_color(&mut self, name: &str, color: &str) {
+        if let Some(class) = self.classes.iter_mut().find(|c| c.name == name) {
+            class.color = color.to_string();
+        }
+    }
+
+    /// Removes a class; the last remaining class cannot be removed.
+    pub fn remove_class(&mut self, name: &str) {
+        if self.classes.len() > 1 {
+            self.classes.retain(|c| c.name != name);
+        }
+    }
+}
+
+/// Distinct labels in palette order, followed by labels the palette doesn't know
+/// (e.g. from an imported dataset) in order of first appearance.
+pub fn group_labels(palette: &ClassPalette, rois: &[LabeledRoi]) -> Vec<String> {
+    let mut labels: Vec<String> = palette.classes.iter().map(|c| c.name.clone()).collect();
+    for roi in rois {
+        if !labels.contains(&roi.label) {
+            labels.push(roi.label.clone());
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_adds_unique_classes_with_unused_colors() {
+        let mut palette = ClassPalette::default();
+        assert!(palette.add_class(" car "));
+        assert!(!palette.add_class("car"));
+        assert!(!palette.add_class("  "));
+        assert_eq!(palette.classes[1].name, "car");
+        assert_ne!(palette.color_for("car"), palette.color_for(DEFAULT_CLASS));
+        assert_eq!(palette.color_for("unknown"), FALLBACK_COLOR);
+    }
+
+    #[test]
+    fn palette_keeps_last_class() {
+        let mut palette = ClassPalette::default();
+        palette.remove_class(DEFAULT_CLASS);
+        assert!(palette.contains(DEFAULT_CLASS));
+    }
+
+    #[test]
+    fn group_labels_appends_unknown_labels_in_order() {
+        let palette = ClassPalette::default();
+        let rois = vec![
+            LabeledRoi::new(Rect::new(0, 0, 1, 1), "dog"),
+            LabeledRoi::new(Rect::new(0, 0, 1, 1), DEFAULT_CLASS),
+            LabeledRoi::new(Rect::new(0, 0, 1, 1), "cat"),
+            LabeledRoi::new(Rect::new(0, 0, 1, 1), "dog"),
+        ];
+        assert_eq!(group_labels(&palette, &rois), vec!["roi", "dog", "cat"]);
+    }
+}
@@ -0,0 +1,206 @@
+// Per-image ROI persistence: `photo.jpg` keeps its ROIs in `photo.jpg.rois.json`
+use opencv::core::Rect;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::roi_model::{LabeledRoi, DEFAULT_CLASS};
+
+// v2 added `label` and `note`; v1 files load with the default class
+pub const SIDECAR_VERSION: u32 = 2;
+const SIDECAR_SUFFIX: &str = ".rois.json";
+
+fn default_label() -> String {
+    DEFAULT_CLASS.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SidecarRoi {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    #[serde(default = "default_label")]
+    pub label: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+}
+
+impl From<&LabeledRoi> for SidecarRoi {
+    fn from(roi: &LabeledRoi) -> Self {
+        SidecarRoi {
+            x: roi.rect.x,
+            y: roi.rect.y,
+            width: roi.rect.width,
+            height: roi.rect.height,
+            label: roi.label.clone(),
+            note: roi.note.clone(),
+        }
+    }
+}
+
+impl From<&SidecarRoi> for LabeledRoi {
+    fn from(r: &SidecarRoi) -> Self {
+        LabeledRoi {
+            rect: Rect::new(r.x, r.y, r.width, r.height),
+            label: r.label.clone(),
+            note: r.note.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoiSidecar {
+    pub version: u32,
+    pub image_width: i32,
+    pub image_height: i32,
+    pub rois: Vec<SidecarRoi>,
+}
+
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+    let mut name = image_path.as_os_str().to_owned();
+    name.push(SIDECAR_SUFFIX);
+    PathBuf::from(name)
+}
+
+pub fn read_sidecar(image_path: &Path) -> Option<RoiSidecar> {
+    let path = sidecar_path(image_path);
+    let text = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<RoiSidecar>(&text) {
+        Ok(sidecar) if sidecar.version <= SIDECAR_VERSION => Some(sidecar),
+        Ok(sidecar) => {
+            eprintln!(
+                "Skipping {}: schema version {} is newer than {}",
+                path.display(),
+                sidecar.version,
+                SIDECAR_VERSION
+            );
+            None
+        }
+        Err(e) => {
+            eprintln!("Failed to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// ROIs stored for `image_path`, or an empty list when there is no usable sidecar.
+/// A sidecar written for different image dimensions is ignored, since its
+/// rectangles no longer line up with the pixels.
+pub fn load_rois(image_path: &Path, image_width: i32, image_height: i32) -> Vec<LabeledRoi> {
+    match read_sidecar(image_path) {
+        Some(sidecar)
+            if sidecar.image_width == image_width && sidecar.image_height == image_height =>
+        {
+            sidecar.rois.iter().map(LabeledRoi::from).collect()
+        }
+        Some(sidecar) => {
+            eprintln!(
+                "Ignoring ROIs for {}: saved for {}x{}, image is {}x{}",
+                image_path.display(),
+                sidecar.image_width,
+                sidecar.image_height,
+                image_width,
+                image_height
+            );
+            vec![]
+        }
+        None => vec![],
+    }
+}
+
+/// Writes the sidecar for `image_path`. An empty ROI list removes the sidecar
+/// so browsing a folder doesn't leave empty files behind.
+pub fn save_rois(
+    image_path: &Path,
+    image_width: i32,
+    image_height: i32,
+    rois: &[LabeledRoi],
+) -> std::io::Result<()> {
+    let path = sidecar_path(image_path);
+    if rois.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let sidecar = RoiSidecar {
+        version: SIDECAR_VERSION,
+        image_width,
+        image_height,
+        rois: rois.iter().map(SidecarRoi::from).collect(),
+    };
+    let json = serde_json::to_string_pretty(&sidecar)?;
+
+    // Write to a temp file first so a crash never leaves a half-written sidecar
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(&tmp, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_image(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("sidecar_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("photo.jpg");
+        (dir, image)
+    }
+
+    #[test]
+    fn rois_round_trip_with_label_and_note() {
+        let (dir, image) = temp_image("round_trip");
+        let mut roi = LabeledRoi::new(Rect::new(3, 4, 10, 20), "leaf");
+        roi.note = "spotted".to_string();
+        let rois = vec![roi, LabeledRoi::new(Rect::new(0, 0, 5, 5), DEFAULT_CLASS)];
+        save_rois(&image, 100, 80, &rois).unwrap();
+        assert_eq!(load_rois(&image, 100, 80), rois);
+        // Written for another size, the rectangles don't apply
+        assert!(load_rois(&image, 80, 100).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_list_removes_the_sidecar() {
+        let (dir, image) = temp_image("empty");
+        let rois = vec![LabeledRoi::new(Rect::new(1, 1, 2, 2), DEFAULT_CLASS)];
+        save_rois(&image, 10, 10, &rois).unwrap();
+        assert!(sidecar_path(&image).exists());
+        save_rois(&image, 10, 10, &[]).unwrap();
+        assert!(!sidecar_path(&image).exists());
+        // Nothing to remove is fine too
+        save_rois(&image, 10, 10, &[]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let (dir, image) = temp_image("version");
+        let sidecar = RoiSidecar {
+            version: SIDECAR_VERSION + 1,
+            image_width: 10,
+            image_height: 10,
+            rois: vec![],
+        };
+        fs::write(
+            sidecar_path(&image),
+            serde_json::to_string(&sidecar).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(read_sidecar(&image), None);
+        fs::write(
+            sidecar_path(&image),
+            serde_json::to_string(&RoiSidecar {
+                version: SIDECAR_VERSION,
+                ..sidecar
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(read_sidecar(&image).is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
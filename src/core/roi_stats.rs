@@ -0,0 +1,283 @@
+// Per-ROI, per-channel statistics and the folder-wide CSV summary
+use opencv::{
+    imgcodecs::{imread, IMREAD_COLOR},
+    prelude::*,
+};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::color_model::{to_color_space, ChannelOrder, ColorSpace};
+use crate::core::pixel_export::RoiPixels;
+use crate::core::roi_model::LabeledRoi;
+use crate::core::roi_sidecar::{load_rois, sidecar_path};
+
+/// Percentiles reported next to the median.
+pub const PERCENTILES: [f64; 4] = [5.0, 25.0, 75.0, 95.0];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStats {
+    pub name: &'static str,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: u8,
+    pub max: u8,
+    pub median: u8,
+    /// Values at [`PERCENTILES`], in the same order
+    pub percentiles: Vec<u8>,
+    pub histogram: Vec<u32>,
+}
+
+impl ChannelStats {
+    fn from_histogram(name: &'static str, histogram: Vec<u32>) -> Self {
+        let count: u64 = histogram.iter().map(|&c| c as u64).sum();
+        let sum: f64 = histogram
+            .iter()
+            .enumerate()
+            .map(|(v, &c)| v as f64 * c as f64)
+            .sum();
+        let mean = if count > 0 { sum / count as f64 } else { 0.0 };
+        let variance = if count > 0 {
+            histogram
+                .iter()
+                .enumerate()
+                .map(|(v, &c)| (v as f64 - mean).powi(2) * c as f64)
+                .sum::<f64>()
+                / count as f64
+        } else {
+            0.0
+        };
+
+        let min = histogram.iter().position(|&c| c > 0).unwrap_or(0) as u8;
+        let max = histogram.iter().rposition(|&c| c > 0).unwrap_or(0) as u8;
+
+        ChannelStats {
+            name,
+            mean,
+            std_dev: variance.sqrt(),
+            min,
+            max,
+            median: percentile(&histogram, count, 50.0),
+            percentiles: PERCENTILES
+                .iter()
+                .map(|&p| percentile(&histogram, count, p))
+                .collect(),
+            histogram,
+        }
+    }
+}
+
+/// Nearest-rank percentile from a 256-bin histogram.
+fn percentile(histogram: &[u32], count: u64, p: f64) -> u8 {
+    if count == 0 {
+        return 0;
+    }
+    let rank = ((p / 100.0) * count as f64).ceil().max(1.0) as u64;
+    let mut seen = 0u64;
+    for (value, &c) in histogram.iter().enumerate() {
+        seen += c as u64;
+        if seen >= rank {
+            return value as u8;
+        }
+    }
+    255
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoiStats {
+    pub label: String,
+    pub rect: opencv::core::Rect,
+    pub pixel_count: usize,
+    pub channels: Vec<ChannelStats>,
+}
+
+impl RoiStats {
+    pub fn from_pixels(pixels: &RoiPixels) -> Self {
+        let names = pixels.color_space.channel_names();
+        let n = names.len();
+        let mut histograms = vec![vec![0u32; 256]; n];
+        for px in pixels.data.chunks_exact(n) {
+            for (c, &v) in px.iter().enumerate() {
+                histograms[c][v as usize] += 1;
+            }
+        }
+
+        RoiStats {
+            label: pixels.label.clone(),
+            rect: pixels.rect,
+            pixel_count: pixels.data.len() / n,
+            channels: names
+                .iter()
+                .zip(histograms)
+                .map(|(name, hist)| ChannelStats::from_histogram(name, hist))
+                .collect(),
+        }
+    }
+}
+
+/// Statistics for every ROI that overlaps the image, in ROI order. `mat` must
+/// already be in `color_space`.
+pub fn compute_roi_stats(
+    mat: &Mat,
+    rois: &[LabeledRoi],
+    color_space: ColorSpace,
+) -> Vec<(usize, RoiStats)> {
+    rois.iter()
+        .enumerate()
+        .filter_map(|(i, roi)| {
+            let pixels = RoiPixels::from_mat(mat, roi, color_space).ok()??;
+            Some((i, RoiStats::from_pixels(&pixels)))
+        })
+        .collect()
+}
+
+/// Loads `path` converted to the readout colour space.
+fn load_mat(path: &Path, color_space: ColorSpace) -> opencv::Result<Mat> {
+    let image = imread(&path.to_string_lossy(), IMREAD_COLOR)?;
+    to_color_space(&image, ChannelOrder::Bgr, color_space)
+}
+
+/// One CSV row per ROI and channel for every image in `image_paths` that has
+/// a sidecar saved for its current size. Returns the number of ROIs summarised.
+pub fn export_folder_summary(
+    image_paths: &[PathBuf],
+    color_space: ColorSpace,
+    out_path: &Path,
+) -> io::Result<usize> {
+    let mut csv =
+        String::from("image,roi,label,x,y,width,height,pixels,channel,mean,std,min,max,median");
+    for p in PERCENTILES {
+        csv.push_str(&format!(",p{}", p));
+    }
+    csv.push('\n');
+
+    let mut count = 0;
+    for path in image_paths {
+        // Images without a sidecar aren't decoded at all
+        if !sidecar_path(path).is_file() {
+            continue;
+        }
+        let mat = match load_mat(path, color_space) {
+            Ok(mat) => mat,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let rois = load_rois(path, mat.cols(), mat.rows());
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        for (i, stats) in compute_roi_stats(&mat, &rois, color_space) {
+            for ch in &stats.channels {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{},{:.3},{:.3},{},{},{}",
+                    csv_field(&name),
+                    i + 1,
+                    csv_field(&stats.label),
+                    stats.rect.x,
+                    stats.rect.y,
+                    stats.rect.width,
+                    stats.rect.height,
+                    stats.pixel_count,
+                    ch.name,
+                    ch.mean,
+                    ch.std_dev,
+                    ch.min,
+                    ch.max,
+                    ch.median
+                ));
+                for v in &ch.percentiles {
+                    csv.push_str(&format!(",{}", v));
+                }
+                csv.push('\n');
+            }
+            count += 1;
+        }
+    }
+
+    fs::write(out_path, csv)?;
+    Ok(count)
+}
+
+pub(crate) fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// SVG polyline points for a histogram scaled into a `width` x `height` box.
+pub fn histogram_points(histogram: &[u32], width: f64, height: f64) -> String {
+    let peak = histogram.iter().copied().max().unwrap_or(0).max(1) as f64;
+    let step = width / histogram.len().max(1) as f64;
+    histogram
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| {
+            format!(
+                "{:.1},{:.1}",
+                i as f64 * step,
+                height - (c as f64 / peak) * height
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(values: &[(usize, u32)]) -> Vec<u32> {
+        let mut histogram = vec![0u32; 256];
+        for &(value, count) in values {
+            histogram[value] = count;
+        }
+        histogram
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let hist = histogram(&[(10, 1), (20, 1), (30, 2)]);
+        assert_eq!(percentile(&hist, 4, 0.0), 10);
+        assert_eq!(percentile(&hist, 4, 25.0), 10);
+        assert_eq!(percentile(&hist, 4, 26.0), 20);
+        assert_eq!(percentile(&hist, 4, 50.0), 20);
+        assert_eq!(percentile(&hist, 4, 100.0), 30);
+        assert_eq!(percentile(&histogram(&[]), 0, 50.0), 0);
+    }
+
+    #[test]
+    fn channel_stats_from_mixed_values() {
+        let stats = ChannelStats::from_histogram("G", histogram(&[(10, 1), (20, 1), (30, 2)]));
+        assert_eq!(stats.mean, 22.5);
+        assert!((stats.std_dev - 68.75f64.sqrt()).abs() < 1e-9);
+        assert_eq!((stats.min, stats.max, stats.median), (10, 30, 20));
+        assert_eq!(stats.percentiles, vec![10, 10, 30, 30]);
+    }
+
+    #[test]
+    fn channel_stats_of_constant_and_empty_rois() {
+        let constant = ChannelStats::from_histogram("G", histogram(&[(42, 7)]));
+        assert_eq!(constant.mean, 42.0);
+        assert_eq!(constant.std_dev, 0.0);
+        assert_eq!((constant.min, constant.max, constant.median), (42, 42, 42));
+        assert_eq!(constant.percentiles, vec![42; PERCENTILES.len()]);
+
+        let empty = ChannelStats::from_histogram("G", histogram(&[]));
+        assert_eq!((empty.mean, empty.std_dev), (0.0, 0.0));
+        assert_eq!((empty.min, empty.max, empty.median), (0, 0, 0));
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("cat"), "cat");
+        assert_eq!(csv_field("cat, tabby"), "\"cat, tabby\"");
+        assert_eq!(csv_field("the \"big\" one"), "\"the \"\"big\"\" one\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,182 @@
+// Thresholding into a binary mask, and turning that mask into ROIs
+//
+// Global methods (fixed, Otsu, triangle) pick one level for the whole image,
+// which the toolbox draws on the histogram; adaptive methods compare each
+// pixel against its neighbourhood and have no single level.
+use opencv::{
+    core::{Point, Rect, Vector},
+    imgproc,
+    prelude::*,
+};
+
+use crate::core::roi_model::LabeledRoi;
+
+pub const THRESHOLD_VALUE: f64 = 128.0;
+pub const ADAPTIVE_BLOCK_SIZE: i32 = 11;
+pub const ADAPTIVE_C: f64 = 2.0;
+/// Label prefix of ROIs created from a mask, so they can be replaced together
+pub const MASK_LABEL: &str = "mask";
+/// Blobs smaller than this many pixels are noise, not ROIs
+pub const MASK_MIN_AREA: i32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdMethod {
+    Fixed,
+    Otsu,
+    Triangle,
+    /// Mean of the `block_size` neighbourhood minus `c`
+    AdaptiveMean,
+    /// Gaussian-weighted sum of the neighbourhood minus `c`
+    AdaptiveGaussian,
+}
+
+impl ThresholdMethod {
+    pub const ALL: [ThresholdMethod; 5] = [
+        ThresholdMethod::Fixed,
+        ThresholdMethod::Otsu,
+        ThresholdMethod::Triangle,
+        ThresholdMethod::AdaptiveMean,
+        ThresholdMethod::AdaptiveGaussian,
+    ];
+
+    pub const NAMES: [&'static str; 5] = ["fixed", "otsu", "triangle", "mean", "gaussian"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|m| m.name() == name)
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        matches!(
+            self,
+            ThresholdMethod::AdaptiveMean | ThresholdMethod::AdaptiveGaussian
+        )
+    }
+}
+
+/// Binary 0/255 mask of a single-channel 8-bit image, 255 above the threshold
+/// (below it when `inverted`), plus the global level used, if there is one.
+/// `value` only applies to `Fixed`, `block_size` and `c` only to the adaptive
+/// methods.
+pub fn threshold(
+    gray: &Mat,
+    method: ThresholdMethod,
+    value: f64,
+    block_size: i32,
+    c: f64,
+    inverted: bool,
+) -> opencv::Result<(Mat, Option<f64>)> {
+    let kind = if inverted {
+        imgproc::THRESH_BINARY_INV
+    } else {
+        imgproc::THRESH_BINARY
+    };
+    let mut mask = Mat::default();
+    let adaptive = match method {
+        ThresholdMethod::AdaptiveMean => imgproc::ADAPTIVE_THRESH_MEAN_C,
+        ThresholdMethod::AdaptiveGaussian => imgproc::ADAPTIVE_THRESH_GAUSSIAN_C,
+        _ => {
+            let flags = match method {
+                ThresholdMethod::Otsu => kind | imgproc::THRESH_OTSU,
+                ThresholdMethod::Triangle => kind | imgproc::THRESH_TRIANGLE,
+                _ => kind,
+            };
+            let level = imgproc::threshold(gray, &mut mask, value, 255.0, flags)?;
+            return Ok((mask, Some(level)));
+        }
+    };
+    imgproc::adaptive_threshold(gray, &mut mask, 255.0, adaptive, kind, block_size, c)?;
+    Ok((mask, None))
+}
+
+/// 256-bin histogram of a single-channel 8-bit image
+pub fn gray_histogram(gray: &Mat) -> opencv::Result<Vec<u32>> {
+    let mut histogram = vec![0u32; 256];
+    // A cropped view is not contiguous; copying makes it so
+    let copy;
+    let gray = if gray.is_continuous() {
+        gray
+    } else {
+        copy = gray.try_clone()?;
+        &copy
+    };
+    for &v in gray.data_bytes()? {
+        histogram[v as usize] += 1;
+    }
+    Ok(histogram)
+}
+
+/// Bounding boxes of the outer contours of `mask`'s non-zero blobs, at least
+/// `min_area` pixels each, as ROIs labelled `mask 1`, `mask 2`, ... in reading
+/// order
+pub fn mask_to_rois(mask: &Mat, min_area: i32) -> opencv::Result<Vec<LabeledRoi>> {
+    let mut contours = Vector::<Vector<Point>>::new();
+    imgproc::find_contours(
+        mask,
+        &mut contours,
+        imgproc::RETR_EXTERNAL,
+        imgproc::CHAIN_APPROX_SIMPLE,
+        Point::new(0, 0),
+    )?;
+    let mut rects: Vec<Rect> = contours
+        .iter()
+        .map(|contour| imgproc::bounding_rect(&contour))
+        .collect::<opencv::Result<_>>()?;
+    rects.retain(|r| r.area() >= min_area);
+    rects.sort_by_key(|r| (r.y, r.x));
+    Ok(rects
+        .into_iter()
+        .enumerate()
+        .map(|(i, rect)| LabeledRoi::new(rect, format!("{} {}", MASK_LABEL, i + 1)))
+        .collect())
+}
+
+/// `existing` with the ROIs of an earlier mask swapped for `mask_rois`
+pub fn replace_mask_rois(
+    mut existing: Vec<LabeledRoi>,
+    mask_rois: Vec<LabeledRoi>,
+) -> Vec<LabeledRoi> {
+    existing.retain(|roi| {
+        roi.label
+            .strip_prefix(MASK_LABEL)
+            .is_none_or(|n| n.trim().parse::<usize>().is_err())
+    });
+    existing.extend(mask_rois);
+    existing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_names_round_trip() {
+        for method in ThresholdMethod::ALL {
+            assert_eq!(ThresholdMethod::from_name(method.name()), Some(method));
+        }
+        assert_eq!(
+            ThresholdMethod::from_name("otsu"),
+            Some(ThresholdMethod::Otsu)
+        );
+        assert!(ThresholdMethod::AdaptiveGaussian.is_adaptive());
+        assert!(!ThresholdMethod::Triangle.is_adaptive());
+        assert_eq!(ThresholdMethod::from_name("binary"), None);
+    }
+
+    #[test]
+    fn replaces_only_earlier_mask_rois() {
+        let roi = |label: &str| LabeledRoi::new(Rect::new(0, 0, 4, 4), label);
+        let existing = vec![
+            roi("cell"),
+            roi("mask 1"),
+            roi("mask 2"),
+            roi("masked area"),
+        ];
+        let merged = replace_mask_rois(existing, vec![roi("mask 1")]);
+        let labels: Vec<&str> = merged.iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(labels, ["cell", "masked area", "mask 1"]);
+    }
+}
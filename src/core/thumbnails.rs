@@ -0,0 +1,135 @@
+// Folder thumbnails for the filmstrip, cached on disk next to the images
+//
+// `<folder>/.thumbnails/<key>.png`, where the key hashes the file name, size
+// and modification time, so an edited image gets a fresh thumbnail and stale
+// ones are simply never read again. The hash is FNV-1a, which unlike std's
+// hashers stays the same across Rust releases and keeps the cache valid.
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::core::filters::{decode_image, encode_png};
+use crate::core::pipeline::thumbnail;
+use crate::core::roi_sidecar::read_sidecar;
+
+pub const THUMBNAIL_CACHE_DIR: &str = ".thumbnails";
+
+/// Where the thumbnail of `image_path` is cached, as it is now.
+pub fn thumbnail_cache_path(image_path: &Path, max_side: i32) -> io::Result<PathBuf> {
+    let metadata = fs::metadata(image_path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let name = image_path
+        .file_name()
+        .unwrap_or_default()
+        .as_encoded_bytes();
+    let mut key = Vec::with_capacity(24 + name.len());
+    key.extend_from_slice(&metadata.len().to_le_bytes());
+    key.extend_from_slice(&modified.as_secs().to_le_bytes());
+    key.extend_from_slice(&modified.subsec_nanos().to_le_bytes());
+    key.extend_from_slice(&max_side.to_le_bytes());
+    key.extend_from_slice(name);
+    let folder = image_path.parent().unwrap_or(Path::new("."));
+    Ok(folder
+        .join(THUMBNAIL_CACHE_DIR)
+        .join(format!("{:016x}.png", fnv1a(&key))))
+}
+
+/// 64-bit FNV-1a
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// PNG of `image_path` with its longer side at most `max_side`, read from the
+/// cache or made and cached. Slow on a miss; call it off the UI thread.
+pub fn cached_thumbnail(image_path: &Path, max_side: i32) -> io::Result<Vec<u8>> {
+    let cache_path = thumbnail_cache_path(image_path, max_side)?;
+    if let Ok(png) = fs::read(&cache_path) {
+        return Ok(png);
+    }
+    let to_io = |e: opencv::Error| io::Error::other(e.to_string());
+    let image = decode_image(&fs::read(image_path)?).map_err(to_io)?;
+    let png = encode_png(&thumbnail(&image, max_side).map_err(to_io)?).map_err(to_io)?;
+    // A read-only folder still gets thumbnails, just not cached ones
+    let cached = cache_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&cache_path, &png));
+    if let Err(e) = cached {
+        eprintln!("Failed to cache {}: {}", cache_path.display(), e);
+    }
+    Ok(png)
+}
+
+/// Number of ROIs in the sidecar of `image_path`.
+pub fn roi_count(image_path: &Path) -> usize {
+    read_sidecar(image_path).map_or(0, |sidecar| sidecar.rois.len())
+}
+
+/// First item of a `shown`-item window over `count` items, moved as little as
+/// possible from `first` so that item `current` is inside it.
+pub fn window_start(first: usize, current: usize, shown: usize, count: usize) -> usize {
+    let last_start = count.saturating_sub(shown);
+    let first = if current < first {
+        current
+    } else if current >= first + shown {
+        current + 1 - shown
+    } else {
+        first
+    };
+    first.min(last_start)
+}
+
+/// Items `start..start + shown`, cut off at `count`.
+pub fn window(start: usize, shown: usize, count: usize) -> Range<usize> {
+    start.min(count)..(start + shown).min(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_follows_the_current_item() {
+        assert_eq!(window_start(0, 3, 8, 100), 0);
+        assert_eq!(window_start(0, 8, 8, 100), 1);
+        assert_eq!(window_start(50, 20, 8, 100), 20);
+        // Never scrolls past the last full window
+        assert_eq!(window_start(97, 99, 8, 100), 92);
+        assert_eq!(window_start(5, 2, 8, 4), 0);
+    }
+
+    #[test]
+    fn window_is_cut_off_at_the_end() {
+        assert_eq!(window(92, 8, 100), 92..100);
+        assert_eq!(window(0, 8, 3), 0..3);
+        assert_eq!(window(10, 8, 0), 0..0);
+    }
+
+    #[test]
+    fn fnv1a_matches_the_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn cache_path_tracks_the_file() {
+        let dir = std::env::temp_dir().join(format!("thumbs_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("a.png");
+        fs::write(&image, b"one").unwrap();
+        let first = thumbnail_cache_path(&image, 96).unwrap();
+        assert!(first.starts_with(dir.join(THUMBNAIL_CACHE_DIR)));
+        assert_eq!(thumbnail_cache_path(&image, 96).unwrap(), first);
+        assert_ne!(thumbnail_cache_path(&image, 48).unwrap(), first);
+        fs::write(&image, b"longer").unwrap();
+        assert_ne!(thumbnail_cache_path(&image, 96).unwrap(), first);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+// Tonal adjustments: histogram equalization, CLAHE and lookup-table curves
+// (gamma, brightness/contrast, levels)
+//
+// Each works either on luminance only, leaving the colours alone, or on
+// every channel separately, which also shifts the colour balance.
+use opencv::{
+    core::{lut, merge, split, AlgorithmHint, Size, Vector},
+    imgproc,
+    prelude::*,
+};
+
+pub const CLAHE_CLIP_LIMIT: f64 = 2.0;
+pub const CLAHE_TILES: i32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneChannels {
+    /// The Y plane of YCrCb
+    Luminance,
+    /// B, G and R independently
+    PerChannel,
+}
+
+impl ToneChannels {
+    pub const ALL: [ToneChannels; 2] = [ToneChannels::Luminance, ToneChannels::PerChannel];
+
+    pub const NAMES: [&'static str; 2] = ["luminance", "channels"];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.name() == name)
+    }
+}
+
+fn to_byte(x: f64) -> u8 {
+    x.round().clamp(0.0, 255.0) as u8
+}
+
+/// Maps `black` to 0 and `white` to 255, with `gamma` bending the midtones
+/// (above 1 brightens them)
+pub fn levels_table(black: f64, white: f64, gamma: f64) -> Vec<u8> {
+    let range = (white - black).max(1.0);
+    (0..256)
+        .map(|v| {
+            let x = ((v as f64 - black) / range).clamp(0.0, 1.0);
+            to_byte(255.0 * x.powf(1.0 / gamma))
+        })
+        .collect()
+}
+
+pub fn gamma_table(gamma: f64) -> Vec<u8> {
+    levels_table(0.0, 255.0, gamma)
+}
+
+/// `contrast * (v - 128) + 128 + brightness`: contrast pivots around mid-gray
+pub fn brightness_contrast_table(brightness: f64, contrast: f64) -> Vec<u8> {
+    (0..256)
+        .map(|v| to_byte(contrast * (v as f64 - 128.0) + 128.0 + brightness))
+        .collect()
+}
+
+/// Runs `adjust` on the luminance plane or on each channel of an 8-bit image
+fn adjust_planes(
+    input: &Mat,
+    channels: ToneChannels,
+    mut adjust: impl FnMut(&Mat) -> opencv::Result<Mat>,
+) -> opencv::Result<Mat> {
+    if input.channels() == 1 {
+        return adjust(input);
+    }
+    let luminance = channels == ToneChannels::Luminance;
+    let mut planes = Vector::<Mat>::new();
+    if luminance {
+        let mut ycrcb = Mat::default();
+        imgproc::cvt_color(
+            input,
+            &mut ycrcb,
+            imgproc::COLOR_BGR2YCrCb,
+            0,
+            AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        split(&ycrcb, &mut planes)?;
+    } else {
+        split(input, &mut planes)?;
+    }
+    let mut adjusted = Vector::<Mat>::new();
+    for (i, plane) in planes.iter().enumerate() {
+        adjusted.push(if i == 0 || !luminance {
+            adjust(&plane)?
+        } else {
+            plane
+        });
+    }
+    let mut merged = Mat::default();
+    merge(&adjusted, &mut merged)?;
+    if !luminance {
+        return Ok(merged);
+    }
+    let mut output = Mat::default();
+    imgproc::cvt_color(
+        &merged,
+        &mut output,
+        imgproc::COLOR_YCrCb2BGR,
+        0,
+        AlgorithmHint::ALGO_HINT_DEFAULT,
+    )?;
+    Ok(output)
+}
+
+pub fn equalize(input: &Mat, channels: ToneChannels) -> opencv::Result<Mat> {
+    adjust_planes(input, channels, |plane| {
+        let mut output = Mat::default();
+        imgproc::equalize_hist(plane, &mut output)?;
+        Ok(output)
+    })
+}
+
+/// Contrast-limited adaptive equalization over a `tiles` x `tiles` grid
+pub fn clahe(
+    input: &Mat,
+    clip_limit: f64,
+    tiles: i32,
+    channels: ToneChannels,
+) -> opencv::Result<Mat> {
+    let mut clahe = imgproc::create_clahe(clip_limit, Size::new(tiles, tiles))?;
+    adjust_planes(input, channels, |plane| {
+        let mut output = Mat::default();
+        clahe.apply(plane, &mut output)?;
+        Ok(output)
+    })
+}
+
+/// Maps every value through a 256-entry table, e.g. from [`levels_table`]
+pub fn apply_table(input: &Mat, table: &[u8], channels: ToneChannels) -> opencv::Result<Mat> {
+    let table = Mat::from_slice(table)?.try_clone()?;
+    adjust_planes(input, channels, |plane| {
+        let mut output = Mat::default();
+        lut(plane, &table, &mut output)?;
+        Ok(output)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neutral_tables_are_identity() {
+        let identity: Vec<u8> = (0..=255).collect();
+        assert_eq!(gamma_table(1.0), identity);
+        assert_eq!(levels_table(0.0, 255.0, 1.0), identity);
+        assert_eq!(brightness_contrast_table(0.0, 1.0), identity);
+    }
+
+    #[test]
+    fn levels_stretch_between_black_and_white() {
+        let table = levels_table(50.0, 150.0, 1.0);
+        assert_eq!(table[0], 0);
+        assert_eq!(table[50], 0);
+        assert_eq!(table[100], 128);
+        assert_eq!(table[150], 255);
+        assert_eq!(table[255], 255);
+        assert!(gamma_table(2.0)[64] > 64);
+        assert!(gamma_table(0.5)[64] < 64);
+    }
+
+    #[test]
+    fn brightness_and_contrast_clamp() {
+        let table = brightness_contrast_table(20.0, 2.0);
+        assert_eq!(table[128], 148);
+        assert_eq!(table[0], 0);
+        assert_eq!(table[255], 255);
+        assert_eq!(
+            ToneChannels::from_name("channels"),
+            Some(ToneChannels::PerChannel)
+        );
+    }
+}
@@ -0,0 +1,302 @@
+// Mapping between zoomed and panned canvas pixels and image pixels
+use opencv::core::Rect;
+
+// Low enough to fit large scans into the canvas
+pub const MIN_SCALE: f32 = 0.05;
+// High enough for a pixel's values to fit inside it
+pub const MAX_SCALE: f32 = 64.0;
+/// From this zoom on pixels are drawn as sharp squares instead of smoothed.
+pub const PIXELATED_SCALE: f32 = 2.0;
+/// Smallest zoom at which the pixel grid is drawn; below it the lines would
+/// cover the image.
+pub const GRID_SCALE: f32 = 8.0;
+/// Smallest zoom at which each pixel shows its values.
+pub const VALUE_SCALE: f32 = 40.0;
+/// Factor applied per zoom step (button press or wheel notch).
+pub const ZOOM_STEP: f32 = 1.1;
+
+/// Zoom and pan of a canvas: image pixel (0, 0) has its top-left corner at
+/// canvas position `(offset_x, offset_y)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewTransform {
+    pub scale: f32,
+    pub offset_x: f32,
+    pub offset_y: f32,
+}
+
+impl ViewTransform {
+    /// Unpanned view, the image's top-left corner at the canvas origin.
+    pub fn new(scale: f32) -> Self {
+        ViewTransform {
+            scale,
+            offset_x: 0.0,
+            offset_y: 0.0,
+        }
+    }
+
+    /// Image position under a canvas position, with sub-pixel precision.
+    pub fn to_image(&self, x: f64, y: f64) -> (f32, f32) {
+        let scale = self.scale as f64;
+        (
+            ((x - self.offset_x as f64) / scale) as f32,
+            ((y - self.offset_y as f64) / scale) as f32,
+        )
+    }
+
+    /// Image pixel under a canvas position. Rounds towards negative infinity,
+    /// so positions just left of or above the image don't land on pixel 0.
+    pub fn to_pixel(&self, x: f64, y: f64) -> (i32, i32) {
+        let scale = self.scale as f64;
+        (
+            ((x - self.offset_x as f64) / scale).floor() as i32,
+            ((y - self.offset_y as f64) / scale).floor() as i32,
+        )
+    }
+
+    pub fn to_canvas(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            x * self.scale + self.offset_x,
+            y * self.scale + self.offset_y,
+        )
+    }
+
+    /// Canvas box `(left, top, width, height)` of an image rect, in whole
+    /// pixels. Edges are rounded rather than sizes, so rects that touch in the
+    /// image also touch on the canvas.
+    pub fn rect_to_canvas(&self, rect: &Rect) -> (f32, f32, f32, f32) {
+        let (left, top) = self.to_canvas(rect.x as f32, rect.y as f32);
+        let (right, bottom) =
+            self.to_canvas((rect.x + rect.width) as f32, (rect.y + rect.height) as f32);
+        let (left, top) = (left.round(), top.round());
+        (left, top, right.round() - left, bottom.round() - top)
+    }
+
+    /// Zoomed to `scale` (kept within `MIN_SCALE..=MAX_SCALE`) so the image
+    /// position under canvas position `anchor` stays where it is.
+    pub fn zoom_at(&self, scale: f32, anchor: (f32, f32)) -> Self {
+        let scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+        let ratio = scale / self.scale;
+        ViewTransform {
+            scale,
+            offset_x: anchor.0 - (anchor.0 - self.offset_x) * ratio,
+            offset_y: anchor.1 - (anchor.1 - self.offset_y) * ratio,
+        }
+    }
+
+    /// Shifted by `(dx, dy)` canvas pixels.
+    pub fn panned(&self, dx: f32, dy: f32) -> Self {
+        ViewTransform {
+            offset_x: self.offset_x + dx,
+            offset_y: self.offset_y + dy,
+            ..*self
+        }
+    }
+
+    /// Whole image centred in a `viewport`-sized canvas, as large as it fits.
+    pub fn fit(image: (i32, i32), viewport: (f32, f32)) -> Self {
+        let scale = (viewport.0 / image.0.max(1) as f32)
+            .min(viewport.1 / image.1.max(1) as f32)
+            .clamp(MIN_SCALE, MAX_SCALE);
+        ViewTransform::new(scale).clamped(image, viewport)
+    }
+
+    /// Image pixels at least partly inside a `viewport`-sized canvas, or
+    /// `None` when the image is out of view.
+    pub fn visible_rect(&self, image: (i32, i32), viewport: (f32, f32)) -> Option<Rect> {
+        let (x0, y0) = self.to_pixel(0.0, 0.0);
+        let (x1, y1) = self.to_image(viewport.0 as f64, viewport.1 as f64);
+        let (x0, y0) = (x0.max(0), y0.max(0));
+        let x1 = (x1.ceil() as i32).min(image.0);
+        let y1 = (y1.ceil() as i32).min(image.1);
+        (x1 > x0 && y1 > y0).then(|| Rect::new(x0, y0, x1 - x0, y1 - y0))
+    }
+
+    /// Panned so the image can't be dragged out of a `viewport`-sized canvas:
+    /// an image narrower than the canvas is centred, a wider one covers it.
+    /// Same for the height.
+    pub fn clamped(&self, image: (i32, i32), viewport: (f32, f32)) -> Self {
+        let limit = |offset: f32, size: i32, room: f32| {
+            let extent = size as f32 * self.scale;
+            if extent <= room {
+                ((room - extent) / 2.0).round()
+            } else {
+                offset.clamp(room - extent, 0.0)
+            }
+        };
+        ViewTransform {
+            offset_x: limit(self.offset_x, image.0, viewport.0),
+            offset_y: limit(self.offset_y, image.1, viewport.1),
+            ..*self
+        }
+    }
+}
+
+/// Steps `scale` one notch in or out, staying within `MIN_SCALE..=MAX_SCALE`.
+pub fn zoom_step(scale: f32, zoom_in: bool) -> f32 {
+    if zoom_in {
+        (scale * ZOOM_STEP).min(MAX_SCALE)
+    } else {
+        (scale / ZOOM_STEP).max(MIN_SCALE)
+    }
+}
+
+pub fn in_image(x: i32, y: i32, image_width: i32, image_height: i32) -> bool {
+    x >= 0 && y >= 0 && x < image_width && y < image_height
+}
+
+/// Rect spanning two corner points given in either order.
+pub fn rect_from_corners(a: (i32, i32), b: (i32, i32)) -> Rect {
+    Rect::new(
+        a.0.min(b.0),
+        a.1.min(b.1),
+        (b.0 - a.0).abs(),
+        (b.1 - a.1).abs(),
+    )
+}
+
+/// `width` x `height` rect centred on pixel `(x, y)`.
+pub fn centered_rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+    Rect::new(x - width / 2, y - height / 2, width, height)
+}
+
+/// Shrinks `rect` to the image size if it is larger, then shifts it fully
+/// inside. Returns `None` for an empty rect or image.
+pub fn fit_rect(rect: &Rect, image_width: i32, image_height: i32) -> Option<Rect> {
+    if rect.width <= 0 || rect.height <= 0 || image_width <= 0 || image_height <= 0 {
+        return None;
+    }
+    let width = rect.width.min(image_width);
+    let height = rect.height.min(image_height);
+    Some(Rect::new(
+        rect.x.clamp(0, image_width - width),
+        rect.y.clamp(0, image_height - height),
+        width,
+        height,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canvas_and_image_positions_round_trip() {
+        let view = ViewTransform::new(2.5);
+        let (x, y) = view.to_image(25.0, 10.0);
+        assert_eq!((x, y), (10.0, 4.0));
+        assert_eq!(view.to_canvas(x, y), (25.0, 10.0));
+    }
+
+    #[test]
+    fn panned_positions_round_trip() {
+        let view = ViewTransform::new(2.0).panned(-30.0, 12.0);
+        assert_eq!(view.to_image(10.0, 20.0), (20.0, 4.0));
+        assert_eq!(view.to_canvas(20.0, 4.0), (10.0, 20.0));
+        assert_eq!(view.to_pixel(-29.0, 11.0), (0, -1));
+    }
+
+    #[test]
+    fn zoom_at_keeps_the_anchor_still() {
+        let view = ViewTransform::new(1.5).panned(-40.0, 25.0);
+        let anchor = (123.0, 77.0);
+        let before = view.to_image(anchor.0 as f64, anchor.1 as f64);
+        for scale in [0.3, 1.0, 3.7] {
+            let zoomed = view.zoom_at(scale, anchor);
+            assert_eq!(zoomed.scale, scale);
+            let after = zoomed.to_image(anchor.0 as f64, anchor.1 as f64);
+            assert!((after.0 - before.0).abs() < 1e-3 && (after.1 - before.1).abs() < 1e-3);
+        }
+        assert_eq!(view.zoom_at(1000.0, anchor).scale, MAX_SCALE);
+    }
+
+    #[test]
+    fn fit_and_clamp_keep_the_image_in_view() {
+        let fitted = ViewTransform::fit((1280, 440), (640.0, 440.0));
+        assert_eq!(fitted.scale, 0.5);
+        assert_eq!((fitted.offset_x, fitted.offset_y), (0.0, 110.0));
+        // A wide image can't be dragged past its edges
+        let view = ViewTransform::new(1.0).panned(50.0, -900.0);
+        let clamped = view.clamped((1000, 100), (640.0, 440.0));
+        assert_eq!((clamped.offset_x, clamped.offset_y), (0.0, 170.0));
+        let clamped = view
+            .panned(-2000.0, 0.0)
+            .clamped((1000, 100), (640.0, 440.0));
+        assert_eq!(clamped.offset_x, -360.0);
+    }
+
+    #[test]
+    fn visible_rect_covers_partly_shown_pixels() {
+        let view = ViewTransform::new(10.0).panned(-15.0, 5.0);
+        assert_eq!(
+            view.visible_rect((100, 100), (640.0, 440.0)),
+            Some(Rect::new(1, 0, 65, 44))
+        );
+        assert_eq!(
+            view.visible_rect((3, 3), (640.0, 440.0)),
+            Some(Rect::new(1, 0, 2, 3))
+        );
+        let away = ViewTransform::new(1.0).panned(700.0, 0.0);
+        assert_eq!(away.visible_rect((100, 100), (640.0, 440.0)), None);
+    }
+
+    #[test]
+    fn to_pixel_floors_negative_positions() {
+        let view = ViewTransform::new(2.0);
+        assert_eq!(view.to_pixel(3.9, 0.0), (1, 0));
+        assert_eq!(view.to_pixel(-0.5, -1.0), (-1, -1));
+    }
+
+    #[test]
+    fn rect_to_canvas_scales_every_edge() {
+        let view = ViewTransform::new(1.5);
+        assert_eq!(
+            view.rect_to_canvas(&Rect::new(2, 4, 10, 3)),
+            (3.0, 6.0, 15.0, 5.0)
+        );
+        let panned = view.panned(0.25, 10.0);
+        assert_eq!(
+            panned.rect_to_canvas(&Rect::new(2, 4, 10, 3)),
+            (3.0, 16.0, 15.0, 5.0)
+        );
+    }
+
+    #[test]
+    fn zoom_step_is_clamped() {
+        assert_eq!(zoom_step(MAX_SCALE, true), MAX_SCALE);
+        assert_eq!(zoom_step(MIN_SCALE, false), MIN_SCALE);
+        assert!(zoom_step(1.0, true) > 1.0);
+    }
+
+    #[test]
+    fn rect_from_corners_normalises_order() {
+        assert_eq!(rect_from_corners((8, 2), (3, 6)), Rect::new(3, 2, 5, 4));
+        assert_eq!(rect_from_corners((3, 3), (3, 3)), Rect::new(3, 3, 0, 0));
+    }
+
+    #[test]
+    fn centered_rect_surrounds_point() {
+        assert_eq!(centered_rect(10, 10, 16, 8), Rect::new(2, 6, 16, 8));
+    }
+
+    #[test]
+    fn fit_rect_shifts_and_shrinks_into_image() {
+        assert_eq!(
+            fit_rect(&Rect::new(-4, 95, 10, 10), 100, 100),
+            Some(Rect::new(0, 90, 10, 10))
+        );
+        assert_eq!(
+            fit_rect(&Rect::new(5, 5, 300, 20), 100, 50),
+            Some(Rect::new(0, 5, 100, 20))
+        );
+        assert_eq!(fit_rect(&Rect::new(0, 0, 0, 5), 100, 100), None);
+        assert_eq!(fit_rect(&Rect::new(0, 0, 5, 5), 0, 0), None);
+    }
+
+    #[test]
+    fn in_image_excludes_far_edges() {
+        assert!(in_image(0, 0, 10, 10));
+        assert!(in_image(9, 9, 10, 10));
+        assert!(!in_image(10, 0, 10, 10));
+        assert!(!in_image(-1, 0, 10, 10));
+    }
+}
@@ -0,0 +1,111 @@
+// Canvas for clicking the four corners of a perspective warp
+use dioxus::prelude::*;
+use opencv::core::Point2f;
+
+use crate::core::geometry::order_corners;
+use crate::core::transform::ViewTransform;
+
+// Width the image is scaled down to, so the whole of it can be clicked
+const PICKER_WIDTH_PX: f32 = 640.0;
+// On-screen diameter of a clicked corner
+const CORNER_DOT_PX: f32 = 10.0;
+
+/// Shows `src` and collects four clicks, in any order. `on_pick` receives
+/// them as top-left, top-right, bottom-right and bottom-left, in image pixels.
+/// Backspace takes back the last click, Escape gives up.
+#[component]
+pub fn CornerPicker(
+    /// Image data URL
+    src: String,
+    image_width: i32,
+    image_height: i32,
+    on_pick: EventHandler<[Point2f; 4]>,
+    on_cancel: EventHandler<()>,
+) -> Element {
+    let mut corners = use_signal(Vec::<Point2f>::new);
+    let view = ViewTransform::new((PICKER_WIDTH_PX / image_width.max(1) as f32).min(1.0));
+    let (canvas_width, canvas_height) = view.to_canvas(image_width as f32, image_height as f32);
+
+    let on_click = move |evt: MouseEvent| {
+        let coords = evt.data().element_coordinates();
+        let (x, y) = view.to_image(coords.x, coords.y);
+        // Pixel centres sit half a pixel in from the clicked edges
+        let snap = |v: f32, size: i32| {
+            ((v - 0.5).clamp(0.0, (size - 1) as f32) * 10.0).round() / 10.0
+        };
+        corners.push(Point2f::new(snap(x, image_width), snap(y, image_height)));
+        if let Ok(picked) = <[Point2f; 4]>::try_from(corners().as_slice()) {
+            corners.clear();
+            on_pick.call(order_corners(picked));
+        }
+    };
+
+    let on_key_down = move |evt: KeyboardEvent| match evt.key() {
+        Key::Escape => on_cancel.call(()),
+        Key::Backspace => {
+            corners.pop();
+        }
+        _ => {}
+    };
+
+    let outline = corners
+        .read()
+        .iter()
+        .map(|p| {
+            let (x, y) = view.to_canvas(p.x + 0.5, p.y + 0.5);
+            format!("{},{}", x, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let remaining = 4 - corners.read().len();
+
+    rsx! {
+        div { class: "flex flex-col gap-2 mb-4",
+            div { class: "flex items-center gap-2 text-sm",
+                span { "Click the {remaining} remaining corners of the area to straighten (Backspace undoes a click)" }
+                button {
+                    onclick: move |_| on_cancel.call(()),
+                    class: "px-2 py-1 bg-gray-400 text-white rounded",
+                    "Cancel"
+                }
+            }
+            div {
+                class: "relative border outline-none",
+                style: "width: {canvas_width}px; height: {canvas_height}px; cursor: crosshair;",
+                tabindex: "0",
+                onclick: on_click,
+                onkeydown: on_key_down,
+                img {
+                    src: "{src}",
+                    class: "select-none pointer-events-none",
+                    style: "width: {canvas_width}px; height: {canvas_height}px;",
+                }
+                svg {
+                    class: "absolute inset-0 pointer-events-none",
+                    width: "{canvas_width}",
+                    height: "{canvas_height}",
+                    polyline {
+                        points: "{outline}",
+                        fill: "none",
+                        stroke: "#dc2626",
+                        stroke_width: "2",
+                    }
+                }
+                for (i, corner) in corners.read().iter().enumerate() {
+                    div {
+                        key: "{i}",
+                        class: "absolute rounded-full bg-red-600 border border-white pointer-events-none",
+                        style: {
+                            let (x, y) = view.to_canvas(corner.x + 0.5, corner.y + 0.5);
+                            format!(
+                                "left: {}px; top: {}px; width: {CORNER_DOT_PX}px; height: {CORNER_DOT_PX}px;",
+                                x - CORNER_DOT_PX / 2.0,
+                                y - CORNER_DOT_PX / 2.0
+                            )
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+// Canvas for dragging or typing the rectangle to crop the image to
+use dioxus::prelude::*;
+use opencv::core::Rect;
+
+use crate::core::crop::{drag_rect, fit_crop, AspectLock};
+use crate::core::transform::ViewTransform;
+
+// Width the image is scaled down to, so the whole of it can be dragged over
+const PICKER_WIDTH_PX: f32 = 640.0;
+// Value of the aspect select that shows the custom ratio fields
+const CUSTOM_ASPECT: &str = "custom";
+
+// Field of the numeric crop editor
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    X,
+    Y,
+    Width,
+    Height,
+}
+
+impl Field {
+    const ALL: [Field; 4] = [Field::X, Field::Y, Field::Width, Field::Height];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Field::X => "x",
+            Field::Y => "y",
+            Field::Width => "w",
+            Field::Height => "h",
+        }
+    }
+
+    fn get(&self, rect: &Rect) -> i32 {
+        match self {
+            Field::X => rect.x,
+            Field::Y => rect.y,
+            Field::Width => rect.width,
+            Field::Height => rect.height,
+        }
+    }
+
+    fn set(&self, rect: &mut Rect, value: i32) {
+        match self {
+            Field::X => rect.x = value,
+            Field::Y => rect.y = value,
+            Field::Width => rect.width = value,
+            Field::Height => rect.height = value,
+        }
+    }
+}
+
+/// Shows `src` with a crop rectangle that is dragged out on the image or typed
+/// in, optionally held to an aspect ratio. It starts as the whole image.
+/// `on_crop` receives it in image pixels; Enter applies, Escape gives up.
+#[component]
+pub fn CropPicker(
+    /// Image data URL
+    src: String,
+    image_width: i32,
+    image_height: i32,
+    on_crop: EventHandler<Rect>,
+    on_cancel: EventHandler<()>,
+) -> Element {
+    let mut crop = use_signal(|| Rect::new(0, 0, image_width, image_height));
+    let mut lock = use_signal(AspectLock::default);
+    let mut custom = use_signal(|| false);
+    let mut custom_ratio = use_signal(|| (3u32, 2u32));
+    // Pixel edge the drag started at
+    let mut anchor = use_signal(|| None::<(i32, i32)>);
+
+    let view = ViewTransform::new((PICKER_WIDTH_PX / image_width.max(1) as f32).min(1.0));
+    let (canvas_width, canvas_height) = view.to_canvas(image_width as f32, image_height as f32);
+
+    // Nearest pixel edge under the mouse
+    let edge_at = move |evt: &MouseEvent| {
+        let coords = evt.data().element_coordinates();
+        let (x, y) = view.to_image(coords.x, coords.y);
+        (x.round() as i32, y.round() as i32)
+    };
+
+    let mut set_lock = move |new_lock: AspectLock| {
+        lock.set(new_lock);
+        if let Some(fitted) = fit_crop(&crop(), new_lock, false, image_width, image_height) {
+            crop.set(fitted);
+        }
+    };
+
+    let on_aspect = move |evt: FormEvent| {
+        let value = evt.value();
+        custom.set(value == CUSTOM_ASPECT);
+        let new_lock = if value == CUSTOM_ASPECT {
+            let (width, height) = custom_ratio();
+            Some(AspectLock::ratio(width, height))
+        } else {
+            AspectLock::from_name(&value)
+        };
+        if let Some(new_lock) = new_lock {
+            set_lock(new_lock);
+        }
+    };
+
+    let mut on_custom_ratio = move |width: Option<u32>, height: Option<u32>| {
+        let (old_width, old_height) = custom_ratio();
+        let ratio = (width.unwrap_or(old_width), height.unwrap_or(old_height));
+        custom_ratio.set(ratio);
+        set_lock(AspectLock::ratio(ratio.0, ratio.1));
+    };
+
+    let mut on_field = move |field: Field, value: i32| {
+        let mut typed = crop();
+        field.set(&mut typed, value);
+        let height_edited = field == Field::Height;
+        if let Some(fitted) = fit_crop(&typed, lock(), height_edited, image_width, image_height) {
+            crop.set(fitted);
+        }
+    };
+
+    let on_mouse_down = move |evt: MouseEvent| {
+        let start = edge_at(&evt);
+        anchor.set(Some(start));
+        crop.set(Rect::new(start.0, start.1, 0, 0));
+    };
+
+    let on_mouse_move = move |evt: MouseEvent| {
+        if let Some(start) = anchor() {
+            crop.set(drag_rect(start, edge_at(&evt), lock(), image_width, image_height));
+        }
+    };
+
+    let on_mouse_up = move |_: MouseEvent| {
+        if anchor().is_none() {
+            return;
+        }
+        anchor.set(None);
+        if crop.read().area() == 0 {
+            // A click without a drag keeps nothing, so go back to the whole image
+            crop.set(Rect::new(0, 0, image_width, image_height));
+        }
+    };
+
+    let apply = move || {
+        let rect = crop();
+        if rect.width > 0 && rect.height > 0 {
+            on_crop.call(rect);
+        }
+    };
+
+    let on_key_down = move |evt: KeyboardEvent| match evt.key() {
+        Key::Escape => on_cancel.call(()),
+        Key::Enter => apply(),
+        _ => {}
+    };
+
+    let rect = crop();
+    let (left, top, width, height) = view.rect_to_canvas(&rect);
+    let lock_name = if custom() { CUSTOM_ASPECT.to_string() } else { lock().name() };
+    let (ratio_width, ratio_height) = custom_ratio();
+
+    rsx! {
+        div { class: "flex flex-col gap-2 mb-4",
+            div { class: "flex flex-wrap items-center gap-2 text-sm",
+                span { "Drag the area to keep" }
+                label { class: "flex items-center gap-1",
+                    "Aspect"
+                    select {
+                        class: "border rounded px-2 py-1",
+                        onchange: on_aspect,
+                        for preset in AspectLock::PRESETS {
+                            option {
+                                value: preset.name(),
+                                selected: preset.name() == lock_name,
+                                "{preset.name()}"
+                            }
+                        }
+                        option { value: CUSTOM_ASPECT, selected: custom(), "custom" }
+                    }
+                }
+                if custom() {
+                    input {
+                        r#type: "number",
+                        min: "1",
+                        value: "{ratio_width}",
+                        class: "border rounded px-2 py-1 w-16",
+                        onchange: move |evt| on_custom_ratio(evt.value().parse().ok(), None),
+                    }
+                    span { ":" }
+                    input {
+                        r#type: "number",
+                        min: "1",
+                        value: "{ratio_height}",
+                        class: "border rounded px-2 py-1 w-16",
+                        onchange: move |evt| on_custom_ratio(None, evt.value().parse().ok()),
+                    }
+                }
+                for field in Field::ALL {
+                    label { class: "flex items-center gap-1",
+                        "{field.label()}"
+                        input {
+                            r#type: "number",
+                            min: "0",
+                            value: "{field.get(&rect)}",
+                            class: "border rounded px-2 py-1 w-20",
+                            onchange: move |evt| {
+                                if let Ok(value) = evt.value().parse::<i32>() {
+                                    on_field(field, value);
+                                }
+                            },
+                        }
+                    }
+                }
+                button {
+                    disabled: rect.area() == 0,
+                    onclick: move |_| apply(),
+                    class: "px-2 py-1 bg-blue-500 text-white rounded",
+                    "Crop"
+                }
+                button {
+                    onclick: move |_| on_cancel.call(()),
+                    class: "px-2 py-1 bg-gray-400 text-white rounded",
+                    "Cancel"
+                }
+            }
+            div {
+                class: "relative border outline-none overflow-hidden select-none",
+                style: "width: {canvas_width}px; height: {canvas_height}px; cursor: crosshair;",
+                tabindex: "0",
+                onmousedown: on_mouse_down,
+                onmousemove: on_mouse_move,
+                onmouseup: on_mouse_up,
+                onmouseleave: on_mouse_up,
+                onkeydown: on_key_down,
+                img {
+                    src: "{src}",
+                    class: "pointer-events-none",
+                    style: "width: {canvas_width}px; height: {canvas_height}px;",
+                }
+                // Everything outside the crop is dimmed by the box's shadow
+                div {
+                    class: "absolute border border-white pointer-events-none",
+                    style: "left: {left}px; top: {top}px; width: {width}px; height: {height}px; box-shadow: 0 0 0 9999px rgba(0, 0, 0, 0.5);",
+                }
+            }
+            div { class: "text-sm text-gray-500", "{rect.width} × {rect.height} px" }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// Strip of thumbnails of every image in the loaded folder
+//
+// Only the thumbnails in view exist in the DOM and in memory, so folders with
+// tens of thousands of images scroll as smoothly as small ones. Thumbnails are
+// made off the UI thread and cached on disk by `core::thumbnails`.
+use crate::dioxus_elements::geometry::WheelDelta;
+use base64::engine::general_purpose;
+use base64::Engine;
+use dioxus::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::core::thumbnails::{cached_thumbnail, roi_count, window, window_start};
+
+// Longer side of a thumbnail, in pixels
+const THUMBNAIL_SIDE: i32 = 96;
+// Thumbnails in view at once
+const SHOWN: usize = 8;
+// Thumbnails kept in memory; past this, the ones out of view are dropped
+const MEMORY_THUMBNAILS: usize = 256;
+
+// A loaded thumbnail; `url` is `None` when the image couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+struct Thumb {
+    url: Option<String>,
+    rois: usize,
+}
+
+/// Thumbnails of `paths` with a badge counting each image's ROIs. The strip
+/// keeps `current` in view, scrolls with the wheel, the arrows or the slider,
+/// and `on_select` receives the index of a clicked thumbnail.
+///
+/// Badges come from the sidecars. The current image's is `current_rois` once
+/// its ROIs are loaded, so it follows edits that haven't been saved yet;
+/// `None` while it is loading.
+#[component]
+pub fn Filmstrip(
+    paths: Signal<Vec<PathBuf>>,
+    current: Signal<usize>,
+    current_rois: Option<usize>,
+    on_select: EventHandler<usize>,
+) -> Element {
+    // First thumbnail in view, and the current image it was scrolled to with
+    let mut scroll = use_signal(|| (0usize, usize::MAX));
+    let mut thumbs = use_signal(HashMap::<PathBuf, Thumb>::new);
+    let mut pending = use_signal(HashSet::<PathBuf>::new);
+    let mut last_current = use_signal(|| None::<PathBuf>);
+
+    // Scrolling is kept until another image becomes current, then the strip
+    // moves just enough to show it
+    let start = move || {
+        let (first, scrolled_with) = scroll();
+        let count = paths.read().len();
+        if scrolled_with == current() {
+            first.min(count.saturating_sub(SHOWN))
+        } else {
+            window_start(first, current(), SHOWN, count)
+        }
+    };
+    let mut scroll_to = move |first: usize| scroll.set((first, current()));
+
+    // Loads the thumbnails in view that aren't in memory or on their way
+    use_effect(move || {
+        let shown = {
+            let paths = paths.read();
+            paths[window(start(), SHOWN, paths.len())].to_vec()
+        };
+        if thumbs.peek().len() > MEMORY_THUMBNAILS {
+            thumbs.write().retain(|path, _| shown.contains(path));
+        }
+        for path in shown {
+            if thumbs.peek().contains_key(&path) || !pending.write().insert(path.clone()) {
+                continue;
+            }
+            spawn(async move {
+                let job = path.clone();
+                let loaded = tokio::task::spawn_blocking(move || {
+                    (cached_thumbnail(&job, THUMBNAIL_SIDE), roi_count(&job))
+                })
+                .await;
+                pending.write().remove(&path);
+                let (png, rois) = match loaded {
+                    Ok(loaded) => loaded,
+                    Err(e) => {
+                        eprintln!("Thumbnail of {} failed: {}", path.display(), e);
+                        return;
+                    }
+                };
+                let url = match png {
+                    Ok(png) => Some(format!(
+                        "data:image/png;base64,{}",
+                        general_purpose::STANDARD.encode(png)
+                    )),
+                    Err(e) => {
+                        eprintln!("Thumbnail of {} failed: {}", path.display(), e);
+                        None
+                    }
+                };
+                thumbs.write().insert(path, Thumb { url, rois });
+            });
+        }
+    });
+
+    // The image just left may have been edited; its sidecar is saved by now
+    use_effect(move || {
+        let now = paths.read().get(current()).cloned();
+        let previous = last_current.peek().clone();
+        if let Some(previous) = previous.filter(|p| Some(p) != now.as_ref()) {
+            if let Some(thumb) = thumbs.write().get_mut(&previous) {
+                thumb.rois = roi_count(&previous);
+            }
+        }
+        last_current.set(now);
+    });
+
+    let on_wheel = move |evt: WheelEvent| {
+        if let WheelDelta::Pixels(pixels) = evt.data().delta() {
+            let delta = if pixels.x.abs() > pixels.y.abs() { pixels.x } else { pixels.y };
+            if delta > 0.0 {
+                scroll_to(start() + 1);
+            } else if delta < 0.0 {
+                scroll_to(start().saturating_sub(1));
+            }
+        }
+    };
+
+    let count = paths.read().len();
+    let start = start();
+    let shown = window(start, SHOWN, count);
+    let last_start = count.saturating_sub(SHOWN);
+    let current_index = current();
+    let items = {
+        let paths = paths.read();
+        let thumbs = thumbs.read();
+        shown
+            .clone()
+            .map(|index| {
+                let path = &paths[index];
+                let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                let thumb = thumbs.get(path);
+                let rois = current_rois
+                    .filter(|_| index == current_index)
+                    .unwrap_or_else(|| thumb.map_or(0, |t| t.rois));
+                let url = thumb.and_then(|t| t.url.clone());
+                let unreadable = thumb.is_some_and(|t| t.url.is_none());
+                (index, name, url, unreadable, rois)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    rsx! {
+        div { class: "flex flex-col items-center gap-1 mt-4",
+            div { class: "flex items-center gap-2",
+                button {
+                    disabled: start == 0,
+                    onclick: move |_| scroll_to(start.saturating_sub(SHOWN)),
+                    class: "px-2 py-1 bg-gray-600 text-white rounded",
+                    "◀"
+                }
+                div { class: "flex gap-2", onwheel: on_wheel,
+                    for (index, name, url, unreadable, rois) in items {
+                        div {
+                            key: "{index}",
+                            title: "{name}",
+                            class: "relative flex items-center justify-center bg-gray-100 rounded cursor-pointer border-2",
+                            style: {
+                                let border = if index == current_index { "#4f46e5" } else { "transparent" };
+                                format!("width: {THUMBNAIL_SIDE}px; height: {THUMBNAIL_SIDE}px; border-color: {border};")
+                            },
+                            onclick: move |_| on_select.call(index),
+                            if let Some(url) = url {
+                                img { src: "{url}", class: "max-w-full max-h-full pointer-events-none" }
+                            } else if unreadable {
+                                span { class: "text-xs text-gray-500", "unreadable" }
+                            } else {
+                                span { class: "text-xs text-gray-400", "…" }
+                            }
+                            if rois > 0 {
+                                span {
+                                    class: "absolute top-0 right-0 px-1 text-xs text-white bg-red-600 rounded-full",
+                                    "{rois}"
+                                }
+                            }
+                        }
+                    }
+                }
+                button {
+                    disabled: start >= last_start,
+                    onclick: move |_| scroll_to(start + SHOWN),
+                    class: "px-2 py-1 bg-gray-600 text-white rounded",
+                    "▶"
+                }
+            }
+            if count > SHOWN {
+                input {
+                    r#type: "range",
+                    min: "0",
+                    max: "{last_start}",
+                    value: "{start}",
+                    class: "w-96",
+                    oninput: move |evt| {
+                        if let Ok(first) = evt.value().parse::<usize>() {
+                            scroll_to(first);
+                        }
+                    },
+                }
+            }
+            span { class: "text-xs text-gray-500", "{shown.start + 1}–{shown.end} of {count}" }
+        }
+    }
+}
@@ -1,28 +1,402 @@
 use base64::engine::general_purpose;
 use base64::Engine;
 use dioxus::prelude::*;
+use opencv::core::{Rect, Size};
+use opencv::prelude::*;
 use rfd::FileDialog;
 use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use opencv::{
-    core::{
-        bitwise_not, no_array, rotate, AlgorithmHint, Point, Size, Vector, BORDER_DEFAULT,
-        ROTATE_90_CLOCKWISE,
-    },
-    imgcodecs::{imdecode, imencode, IMREAD_COLOR},
-    imgproc,
-    prelude::*,
-};
-fn decode_mat(bytes: &[u8]) -> opencv::Result<Mat> {
-    let vec = Vector::from_slice(bytes);
-    imdecode(&vec, IMREAD_COLOR)
+use crate::batch_panel::BatchPanel;
+use crate::compare_view::CompareView;
+use crate::corner_picker::CornerPicker;
+use crate::crop_picker::CropPicker;
+use crate::core::encode::{encode_image, EncodeOptions, ImageFormat};
+use crate::core::exif::{embed_exif, read_exif, reset_orientation, supports_exif};
+use crate::core::filters::{decode_image, encode_png, to_gray, Filter, ParamSpec};
+use crate::core::history::History;
+use crate::core::pipeline::{thumbnail, Pipeline};
+use crate::core::recipe::{load_recipe, save_recipe};
+use crate::core::roi_model::LabeledRoi;
+use crate::core::roi_sidecar::{load_rois, save_rois};
+use crate::core::roi_stats::histogram_points;
+use crate::core::threshold::{gray_histogram, mask_to_rois, replace_mask_rois, MASK_MIN_AREA};
+
+// Undo steps are pipeline snapshots, which are small
+const FILTER_HISTORY_BUDGET_BYTES: usize = 1024 * 1024;
+// Longer side of the per-step previews in the pipeline list
+const THUMBNAIL_SIZE: i32 = 96;
+// Longer side of the copy the live preview runs on; saving uses the original
+const PREVIEW_MAX_SIDE: i32 = 1280;
+// Pause in editing, e.g. dragging a slider, before the preview is rendered
+const PREVIEW_DEBOUNCE: Duration = Duration::from_millis(80);
+
+// Filter being tuned in the parameter panel, either a new step or an edit of `step`
+#[derive(Debug, Clone, PartialEq)]
+struct Tuning {
+    step: Option<usize>,
+    filter: Filter,
+}
+
+// Quarter turns and flips in the toolbox, as (operation, button label)
+const ORIENTATION_TOOLS: [(&str, &str); 5] = [
+    ("rotate", "Rotate 90°"),
+    ("rotate180", "Rotate 180°"),
+    ("rotate270", "Rotate 270°"),
+    ("flip=horizontal", "Flip ↔"),
+    ("flip=vertical", "Flip ↕"),
+];
+
+// Tone curve tools in the toolbox, as (operation, button label)
+const TONE_TOOLS: [(&str, &str); 5] = [
+    ("equalize", "Equalize"),
+    ("clahe", "CLAHE"),
+    ("gamma", "Gamma"),
+    ("brightness", "Brightness/Contrast"),
+    ("levels", "Levels"),
+];
+
+// Denoising and sharpening tools in the toolbox, as (operation, button label)
+const DENOISE_TOOLS: [(&str, &str); 5] = [
+    ("gaussian", "Gaussian"),
+    ("median", "Median"),
+    ("bilateral", "Bilateral"),
+    ("nlmeans", "NL-means"),
+    ("sharpen", "Unsharp mask"),
+];
+
+// Gray histogram of what the tuned step receives, with the values it marks
+// there (threshold level, levels black and white points)
+#[derive(Debug, Clone, PartialEq)]
+struct LevelHistogram {
+    bins: Vec<u32>,
+    markers: Vec<f64>,
+    caption: String,
+}
+
+// Output of the pipeline as data URLs: the final image and one thumbnail per
+// step, plus the histogram of the step being tuned if it has one
+#[derive(Debug, Clone, PartialEq)]
+struct Rendered {
+    image_url: String,
+    /// Width and height of the final image at full resolution
+    size: (i32, i32),
+    thumbnails: Vec<String>,
+    histogram: Option<LevelHistogram>,
+    /// The image's ROIs, moved along by the geometric steps, in `size` pixels
+    rois: Vec<Rect>,
+}
+
+fn png_data_url(png: &[u8]) -> String {
+    format!(
+        "data:image/png;base64,{}",
+        general_purpose::STANDARD.encode(png)
+    )
+}
+
+fn level_histogram(filter: &Filter, input: &Mat) -> opencv::Result<LevelHistogram> {
+    let markers = filter.histogram_markers(input)?;
+    let caption = match (filter, markers.as_slice()) {
+        (Filter::Threshold { .. }, []) => "adaptive: level varies per pixel".to_string(),
+        (Filter::Threshold { .. }, [level, ..]) => format!("level {:.0}", level),
+        (_, [black, white]) => format!("black {:.0}, white {:.0}", black, white),
+        _ => String::new(),
+    };
+    Ok(LevelHistogram {
+        bins: gray_histogram(&to_gray(input)?)?,
+        markers,
+        caption,
+    })
+}
+
+// Runs the whole pipeline on `preview`, the source image scaled down by
+// `factor`, with the steps scaled along. `probe` is the index of a step whose
+// input histogram is wanted. The size and ROIs are worked out for the
+// `source_size` original, so pickers and outlines use its pixels.
+fn render_pipeline(
+    preview: Mat,
+    factor: f64,
+    source_size: (i32, i32),
+    pipeline: &Pipeline,
+    probe: Option<usize>,
+    rois: &[LabeledRoi],
+) -> Result<Rendered, String> {
+    let scaled = if factor < 1.0 { pipeline.scaled(factor) } else { pipeline.clone() };
+    let probed = probe.and_then(|index| scaled.steps.get(index).map(|s| (index, &s.filter)));
+    let mut histogram = None;
+    if let Some((0, filter)) = probed {
+        histogram = Some(level_histogram(filter, &preview).map_err(|e| e.to_string())?);
+    }
+    let mut thumbnails = vec![];
+    let output = scaled
+        .run_with(preview, |index, mat| {
+            // The output of the step before is the probed step's input
+            if let Some((_, filter)) = probed.filter(|(probe, _)| *probe == index + 1) {
+                histogram = Some(level_histogram(filter, mat)?);
+            }
+            let small = thumbnail(mat, THUMBNAIL_SIZE)?;
+            thumbnails.push(png_data_url(&encode_png(&small)?));
+            Ok(())
+        })
+        .map_err(|e| e.to_string())?;
+    let png = encode_png(&output).map_err(|e| e.to_string())?;
+    let (size, rois) = pipeline
+        .layout(Size::new(source_size.0, source_size.1), rois)
+        .map_err(|e| e.to_string())?;
+    Ok(Rendered {
+        image_url: png_data_url(&png),
+        size: (size.width, size.height),
+        thumbnails,
+        histogram,
+        rois: rois.iter().map(|roi| roi.rect).collect(),
+    })
+}
+
+// ROIs around the blobs of the pipeline's binary output, whose pixels must
+// stay where they are so the ROIs line up with the image; also returns its size
+fn mask_rois(bytes: &[u8], pipeline: &Pipeline) -> Result<(i32, i32, Vec<LabeledRoi>), String> {
+    if let Some(filter) = pipeline.active_filters().find(|f| f.is_geometric()) {
+        return Err(format!(
+            "{} moves pixels, so the mask would not line up with the image; disable it",
+            filter
+        ));
+    }
+    let source = decode_image(bytes).map_err(|e| e.to_string())?;
+    let (width, height) = (source.cols(), source.rows());
+    let mask = pipeline.run(source).map_err(|e| e.to_string())?;
+    let rois = mask_to_rois(&mask, MASK_MIN_AREA).map_err(|e| e.to_string())?;
+    Ok((width, height, rois))
+}
+
+// A processed image encoded for saving
+struct Exported {
+    data: Vec<u8>,
+    size: (i32, i32),
+    /// The image's ROIs, moved along with it
+    rois: Vec<LabeledRoi>,
+    /// The original's EXIF was asked for but couldn't be embedded, e.g. a
+    /// JPEG EXIF block over 64 KiB
+    exif_dropped: bool,
+}
+
+// Runs the pipeline at full resolution and encodes the result for saving
+fn export_image(
+    bytes: &[u8],
+    pipeline: &Pipeline,
+    rois: &[LabeledRoi],
+    options: &EncodeOptions,
+    keep_exif: bool,
+) -> Result<Exported, String> {
+    let source = decode_image(bytes).map_err(|e| e.to_string())?;
+    let (output, rois) = pipeline
+        .run_with_rois(source, rois, |_, _| Ok(()))
+        .map_err(|e| e.to_string())?;
+    let size = (output.cols(), output.rows());
+    let encoded = encode_image(&output, options).map_err(|e| e.to_string())?;
+    let (data, exif_dropped) = match read_exif(bytes).filter(|_| keep_exif) {
+        Some(mut exif) => {
+            reset_orientation(&mut exif);
+            match embed_exif(&encoded, options.format, &exif) {
+                Some(data) => (data, false),
+                None => (encoded, true),
+            }
+        }
+        None => (encoded, false),
+    };
+    Ok(Exported {
+        data,
+        size,
+        rois,
+        exif_dropped,
+    })
+}
+
+// Slider plus numeric input for one filter parameter, or a dropdown for a choice
+#[component]
+fn ParamControl(spec: ParamSpec, value: f64, on_change: EventHandler<f64>) -> Element {
+    if !spec.options.is_empty() {
+        return rsx! {
+            label { class: "flex items-center gap-2 text-sm",
+                span { class: "w-32 capitalize", "{spec.name}" }
+                select {
+                    class: "border rounded px-2 py-1",
+                    onchange: move |evt| {
+                        if let Some(i) = spec.options.iter().position(|o| *o == evt.value()) {
+                            on_change.call(i as f64);
+                        }
+                    },
+                    for (i, name) in spec.options.iter().enumerate() {
+                        option { value: *name, selected: i as f64 == value, "{name}" }
+                    }
+                }
+            }
+        };
+    }
+    rsx! {
+        label { class: "flex items-center gap-2 text-sm",
+            span { class: "w-32 capitalize", "{spec.name}" }
+            input {
+                r#type: "range",
+                min: "{spec.min}",
+                max: "{spec.max}",
+                step: "{spec.step}",
+                value: "{value}",
+                class: "w-64",
+                oninput: move |evt| {
+                    if let Ok(val) = evt.value().parse::<f64>() {
+                        on_change.call(spec.snap(val));
+                    }
+                }
+            }
+            input {
+                r#type: "number",
+                min: "{spec.min}",
+                max: "{spec.max}",
+                step: "{spec.step}",
+                value: "{value}",
+                class: "border rounded px-2 py-1 w-24",
+                onchange: move |evt| {
+                    if let Ok(val) = evt.value().parse::<f64>() {
+                        on_change.call(spec.snap(val));
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[component]
 pub fn ImageUploader() -> Element {
+    // Data URL and bytes of the image as loaded; filters never modify them
     let mut image_data_url = use_signal(|| None::<String>);
     let mut original_image_bytes = use_signal(|| None::<Vec<u8>>);
+    // Picked file; its folder is suggested as the batch input
+    let mut image_path = use_signal(|| None::<PathBuf>);
+    // Size of the picked image and the ROIs saved for it, in its pixels
+    let mut source_size = use_signal(|| (0, 0));
+    let mut source_rois = use_signal(Vec::<LabeledRoi>::new);
+    // The image scaled down for the live preview, and the factor it was scaled by
+    let mut preview_source = use_signal(|| None::<(Mat, f64)>);
+    let mut pipeline = use_signal(Pipeline::default);
+    let mut filter_history = use_signal(|| History::<Pipeline>::new(FILTER_HISTORY_BUDGET_BYTES));
+    let mut save_options = use_signal(|| EncodeOptions::new(ImageFormat::Png));
+    let mut keep_exif = use_signal(|| true);
+    // Parsed once per picked image rather than on every render
+    let source_has_exif = use_memo(move || {
+        original_image_bytes
+            .read()
+            .as_deref()
+            .and_then(read_exif)
+            .is_some()
+    });
+    // Filter whose parameters are being tuned, previewed live until applied
+    let mut tuning = use_signal(|| None::<Tuning>);
+    // Last applied parameters of each tunable filter, reused when it is reopened
+    let mut tuned = use_signal(Vec::<Filter>::new);
+    // Whether the result view is replaced by the perspective corner picker
+    let mut picking_corners = use_signal(|| false);
+    // Whether the result view is replaced by the crop rectangle picker
+    let mut picking_crop = use_signal(|| false);
+
+    // The pipeline as shown, including the step being tuned. It is rendered
+    // off the UI thread once edits pause, and a render overtaken by a later
+    // edit is dropped
+    let mut rendered = use_signal(|| None::<Result<Rendered, String>>);
+    let mut render_generation = use_signal(|| 0u64);
+    let mut rendering = use_signal(|| false);
+    use_effect(move || {
+        let source = preview_source();
+        let mut shown = pipeline();
+        let current = tuning();
+        let rois = source_rois();
+        let size = source_size();
+        let generation = {
+            let mut latest = render_generation.write();
+            *latest += 1;
+            *latest
+        };
+        let with_histogram = current.as_ref().is_some_and(|t| t.filter.has_histogram());
+        let tuned_index = match current {
+            Some(Tuning {
+                step: Some(index),
+                filter,
+            }) => {
+                shown.set_filter(index, filter);
+                Some(index)
+            }
+            Some(Tuning { step: None, filter }) => {
+                shown.push(filter);
+                Some(shown.steps.len() - 1)
+            }
+            None => None,
+        };
+        let Some((preview, factor)) = source.filter(|_| !shown.is_empty()) else {
+            rendered.set(None);
+            rendering.set(false);
+            return;
+        };
+        let probe = tuned_index.filter(|_| with_histogram);
+        rendering.set(true);
+        spawn(async move {
+            tokio::time::sleep(PREVIEW_DEBOUNCE).await;
+            if *render_generation.peek() != generation {
+                return;
+            }
+            let result = tokio::task::spawn_blocking(move || {
+                render_pipeline(preview, factor, size, &shown, probe, &rois)
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+            if *render_generation.peek() == generation {
+                rendered.set(Some(result));
+                rendering.set(false);
+            }
+        });
+    });
 
+    // Snapshot taken before every pipeline edit
+    let mut record_filter = move || filter_history.write().record(pipeline());
+
+    let mut undo_filter = move || {
+        let previous = filter_history.write().undo(pipeline());
+        if let Some(previous) = previous {
+            tuning.set(None);
+            pipeline.set(previous);
+        }
+    };
+
+    let mut redo_filter = move || {
+        let next = filter_history.write().redo(pipeline());
+        if let Some(next) = next {
+            tuning.set(None);
+            pipeline.set(next);
+        }
+    };
+
+    let on_key_down = move |evt: KeyboardEvent| {
+        let modifiers = evt.modifiers();
+        if !(modifiers.ctrl() || modifiers.meta()) {
+            return;
+        }
+        match evt.key() {
+            Key::Character(c) if c.eq_ignore_ascii_case("z") => {
+                evt.prevent_default();
+                if modifiers.shift() {
+                    redo_filter();
+                } else {
+                    undo_filter();
+                }
+            }
+            Key::Character(c) if c.eq_ignore_ascii_case("y") => {
+                evt.prevent_default();
+                redo_filter();
+            }
+            _ => {}
+        }
+    };
+
+    // The pipeline is kept, so the same steps run on the newly picked image
     let pick_image = move |_| {
         if let Some(path) = FileDialog::new()
             .add_filter("Image", &["png", "jpg", "jpeg", "webp"])
@@ -37,173 +411,292 @@ pub fn ImageUploader() -> Element {
                 };
                 let encoded = general_purpose::STANDARD.encode(&bytes);
                 let data_url = format!("data:{};base64,{}", mime, encoded);
+                let (size, rois, preview) = match decode_image(&bytes) {
+                    Ok(mat) => {
+                        let preview = thumbnail(&mat, PREVIEW_MAX_SIDE).ok().map(|small| {
+                            let factor = small.cols() as f64 / mat.cols().max(1) as f64;
+                            (small, factor)
+                        });
+                        (
+                            (mat.cols(), mat.rows()),
+                            load_rois(&path, mat.cols(), mat.rows()),
+                            preview,
+                        )
+                    }
+                    Err(_) => ((0, 0), vec![], None),
+                };
+                source_size.set(size);
+                source_rois.set(rois);
+                preview_source.set(preview);
+                picking_corners.set(false);
+                picking_crop.set(false);
                 image_data_url.set(Some(data_url));
                 original_image_bytes.set(Some(bytes));
+                image_path.set(Some(path));
             }
         }
     };
 
-    let apply_blur = move |_event: dioxus::events::MouseEvent| {
-        if let Some(bytes) = original_image_bytes().clone() {
-            if let Ok(input_mat) = imdecode(&Vector::from_slice(&bytes), IMREAD_COLOR) {
-                let mut blurred = Mat::default();
-                if imgproc::blur(
-                    &input_mat,
-                    &mut blurred,
-                    Size::new(15, 15),
-                    Point::new(-1, -1),
-                    BORDER_DEFAULT,
-                )
-                .is_ok()
-                {
-                    let mut buf = Vector::new();
-                    if imencode(".png", &blurred, &mut buf, &Vector::<i32>::new()).is_ok() {
-                        let encoded = general_purpose::STANDARD.encode(buf.as_slice());
-                        let data_url = format!("data:image/png;base64,{}", encoded);
-                        image_data_url.set(Some(data_url));
-                    }
-                }
-            }
+    let mut add_step = move |filter: Filter| {
+        record_filter();
+        pipeline.write().push(filter);
+    };
+
+    // Opens the parameter panel for a new step, starting from the last used values
+    let mut open_tuner = move |name: &str| {
+        let last = tuned.read().iter().find(|f| f.name() == name).cloned();
+        if let Some(filter) = last.or_else(|| name.parse().ok()) {
+            tuning.set(Some(Tuning { step: None, filter }));
         }
     };
 
-    let apply_resize = move |_event: dioxus::events::MouseEvent| {
-        if let Some(bytes) = original_image_bytes().clone() {
-            let vec = Vector::from_slice(&bytes);
-
-            if let Ok(input_mat) = imdecode(&vec, IMREAD_COLOR) {
-                let mut resized = Mat::default();
-                let new_size = Size::new(200, 200); // Resize to 200x200
-
-                if imgproc::resize(
-                    &input_mat,
-                    &mut resized,
-                    new_size,
-                    0.0,
-                    0.0,
-                    imgproc::INTER_LINEAR,
-                )
-                .is_ok()
-                {
-                    let mut buf = Vector::new(); // this is Vector<u8>
-                    if imencode(".png", &resized, &mut buf, &Vector::new()).is_ok() {
-                        let encoded = general_purpose::STANDARD.encode(buf.as_slice());
-                        let data_url = format!("data:image/png;base64,{}", encoded);
-                        image_data_url.set(Some(data_url));
-                    }
-                }
+    let apply_tuned = move |_event: MouseEvent| {
+        let Some(Tuning { step, filter }) = tuning() else {
+            return;
+        };
+        tuned.with_mut(|t| {
+            t.retain(|f| f.name() != filter.name());
+            t.push(filter.clone());
+        });
+        tuning.set(None);
+        match step {
+            Some(index) => {
+                record_filter();
+                pipeline.write().set_filter(index, filter);
             }
+            None => add_step(filter),
         }
     };
 
+    let mut edit_step = move |index: usize, edit: fn(&mut Pipeline, usize)| {
+        tuning.set(None);
+        record_filter();
+        pipeline.with_mut(|p| edit(p, index));
+    };
+
+    let apply_blur = move |_event: dioxus::events::MouseEvent| {
+        open_tuner("blur");
+    };
+
+    let apply_resize = move |_event: dioxus::events::MouseEvent| {
+        open_tuner("resize");
+    };
+
     let apply_grayscale = move |_event: MouseEvent| {
-        if let Some(bytes) = original_image_bytes().clone() {
-            if let Ok(input_mat) = decode_mat(&bytes) {
-                let mut gray = Mat::default();
-                if imgproc::cvt_color(
-                    &input_mat,
-                    &mut gray,
-                    imgproc::COLOR_BGR2GRAY,
-                    0,
-                    AlgorithmHint::ALGO_HINT_DEFAULT,
-                )
-                .is_ok()
-                {
-                    let mut buf = Vector::new();
-                    if imencode(".png", &gray, &mut buf, &Vector::new()).is_ok() {
-                        let encoded = general_purpose::STANDARD.encode(buf.as_slice());
-                        let data_url = format!("data:image/png;base64,{}", encoded);
-                        image_data_url.set(Some(data_url));
-                    }
-                }
-            }
-        }
+        add_step(Filter::Grayscale);
     };
 
     let apply_invert = move |_event: MouseEvent| {
-        if let Some(bytes) = original_image_bytes().clone() {
-            if let Ok(input_mat) = decode_mat(&bytes) {
-                let mut inverted = Mat::default();
-                if bitwise_not(&input_mat, &mut inverted, &no_array()).is_ok() {
-                    let mut buf = Vector::new();
-                    if imencode(".png", &inverted, &mut buf, &Vector::new()).is_ok() {
-                        let encoded = general_purpose::STANDARD.encode(buf.as_slice());
-                        let data_url = format!("data:image/png;base64,{}", encoded);
-                        image_data_url.set(Some(data_url));
-                    }
-                }
-            }
-        }
+        add_step(Filter::Invert);
     };
 
     let apply_edge_detect = move |_event: MouseEvent| {
-        if let Some(bytes) = original_image_bytes().clone() {
-            if let Ok(input_mat) = decode_mat(&bytes) {
-                let mut gray = Mat::default();
-                if imgproc::cvt_color(
-                    &input_mat,
-                    &mut gray,
-                    imgproc::COLOR_BGR2GRAY,
-                    0,
-                    AlgorithmHint::ALGO_HINT_DEFAULT,
-                )
-                .is_ok()
-                {
-                    let mut edges = Mat::default();
-                    if imgproc::canny(&gray, &mut edges, 100.0, 200.0, 3, false).is_ok() {
-                        let mut buf = Vector::new();
-                        if imencode(".png", &edges, &mut buf, &Vector::new()).is_ok() {
-                            let encoded = general_purpose::STANDARD.encode(buf.as_slice());
-                            let data_url = format!("data:image/png;base64,{}", encoded);
-                            image_data_url.set(Some(data_url));
-                        }
-                    }
-                }
-            }
-        }
+        open_tuner("canny");
     };
 
-    let apply_rotate_90 = move |_event: MouseEvent| {
-        if let Some(bytes) = original_image_bytes().clone() {
-            if let Ok(input_mat) = decode_mat(&bytes) {
-                let mut rotated = Mat::default();
+    let apply_threshold = move |_event: MouseEvent| {
+        open_tuner("threshold");
+    };
 
-                // Rotate the image 90 degrees clockwise
-                if rotate(&input_mat, &mut rotated, ROTATE_90_CLOCKWISE).is_ok() {
-                    let mut buf = Vector::new();
-                    if imencode(".png", &rotated, &mut buf, &Vector::new()).is_ok() {
-                        let encoded = general_purpose::STANDARD.encode(buf.as_slice());
-                        let data_url = format!("data:image/png;base64,{}", encoded);
+    let apply_morphology = move |_event: MouseEvent| {
+        open_tuner("morph");
+    };
 
-                        // Update the image signal
-                        image_data_url.set(Some(data_url));
+    // Replaces the result view with a canvas for clicking the warp's corners
+    let start_warp = move |_event: MouseEvent| {
+        tuning.set(None);
+        picking_crop.set(false);
+        picking_corners.set(true);
+    };
+
+    // Replaces the result view with a canvas for dragging out the crop
+    let start_crop = move |_event: MouseEvent| {
+        tuning.set(None);
+        picking_corners.set(false);
+        picking_crop.set(true);
+    };
+
+    let clear_pipeline = move |_event: MouseEvent| {
+        if !pipeline.read().is_empty() {
+            tuning.set(None);
+            record_filter();
+            pipeline.set(Pipeline::default());
+        }
+    };
 
-                        // Optional: update the original bytes to allow chained effects
-                        original_image_bytes.set(Some(buf.to_vec()));
+    #[cfg(not(target_arch = "wasm32"))]
+    let save_pipeline = move |_| {
+        let current = pipeline();
+        if current.is_empty() {
+            return;
+        }
+        spawn(async move {
+            if let Some(path) = FileDialog::new()
+                .set_file_name("recipe.toml")
+                .add_filter("Recipe", &["toml", "json"])
+                .save_file()
+            {
+                match save_recipe(&path, &current) {
+                    Ok(()) => println!(
+                        "📝 Saved {} steps to {}",
+                        current.steps.len(),
+                        path.display()
+                    ),
+                    Err(e) => eprintln!("Saving recipe failed: {}", e),
+                }
+            }
+        });
+    };
+
+    // Replaces the pipeline with the recipe's steps, replayed on the current image
+    #[cfg(not(target_arch = "wasm32"))]
+    let load_pipeline = move |_| {
+        spawn(async move {
+            if let Some(path) = FileDialog::new()
+                .add_filter("Recipe", &["toml", "json"])
+                .pick_file()
+            {
+                match load_recipe(&path) {
+                    Ok(loaded) => {
+                        println!(
+                            "📂 Loaded {} steps from {}",
+                            loaded.steps.len(),
+                            path.display()
+                        );
+                        tuning.set(None);
+                        record_filter();
+                        pipeline.set(loaded);
                     }
+                    Err(e) => eprintln!("Loading recipe {} failed: {}", path.display(), e),
                 }
             }
-        }
+        });
+    };
+
+    // Writes the blobs of the threshold step being tuned to the image's ROI
+    // sidecar, replacing earlier mask ROIs; the ROI canvas loads them from there
+    let save_mask = move |_event: MouseEvent| {
+        let (Some(bytes), Some(path), Some(Tuning { step, filter })) =
+            (original_image_bytes(), image_path(), tuning())
+        else {
+            return;
+        };
+        let mut upto = pipeline();
+        let index = match step {
+            Some(index) => {
+                upto.set_filter(index, filter);
+                index
+            }
+            None => {
+                upto.push(filter);
+                upto.steps.len() - 1
+            }
+        };
+        upto.steps.truncate(index + 1);
+        // The mask is the tuned step's output even while it is switched off
+        // in the pipeline
+        upto.steps[index].enabled = true;
+        spawn(async move {
+            let saved = tokio::task::spawn_blocking(move || {
+                let (width, height, rois) = mask_rois(&bytes, &upto)?;
+                let count = rois.len();
+                let merged = replace_mask_rois(load_rois(&path, width, height), rois);
+                save_rois(&path, width, height, &merged).map_err(|e| e.to_string())?;
+                Ok((count, merged, path))
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+            match saved {
+                Ok((count, merged, path)) => {
+                    source_rois.set(merged);
+                    println!(
+                        "🎯 Saved {} mask ROIs for {}; open it in the ROI canvas to edit them",
+                        count,
+                        path.display()
+                    );
+                }
+                Err(e) => eprintln!("Saving mask ROIs failed: {}", e),
+            }
+        });
     };
 
-    let apply_crop = move |_event: MouseEvent| {
-        if let Some(bytes) = original_image_bytes().clone() {
-            if let Ok(input_mat) = decode_mat(&bytes) {
-                let roi = opencv::core::Rect::new(50, 50, 100, 100); // x, y, width, height
-                if let Ok(cropped) = Mat::roi(&input_mat, roi) {
-                    let mut buf = Vector::new();
-                    if imencode(".png", &cropped, &mut buf, &Vector::new()).is_ok() {
-                        let encoded = general_purpose::STANDARD.encode(buf.as_slice());
-                        let data_url = format!("data:image/png;base64,{}", encoded);
-                        image_data_url.set(Some(data_url));
+    #[cfg(not(target_arch = "wasm32"))]
+    let save_as = move |_| {
+        let Some(bytes) = original_image_bytes() else {
+            return;
+        };
+        let current = pipeline();
+        let rois = source_rois();
+        let options = save_options();
+        // The checkbox is shown off for formats that can't carry EXIF
+        let keep = keep_exif() && supports_exif(options.format);
+        let stem = image_path()
+            .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "image".to_string());
+        spawn(async move {
+            let extension = options.format.extension();
+            if let Some(path) = FileDialog::new()
+                .set_file_name(format!("{}_processed.{}", stem, extension))
+                .add_filter(options.format.name(), &[extension])
+                .save_file()
+            {
+                // The ROIs go along, into the saved image's own sidecar; the
+                // full-resolution run and encode stay off the UI thread
+                let target = path.clone();
+                let saved = tokio::task::spawn_blocking(move || {
+                    let exported = export_image(&bytes, &current, &rois, &options, keep)?;
+                    let (width, height) = exported.size;
+                    fs::write(&target, exported.data).map_err(|e| e.to_string())?;
+                    save_rois(&target, width, height, &exported.rois).map_err(|e| e.to_string())?;
+                    if exported.exif_dropped {
+                        eprintln!(
+                            "⚠️ {} was saved without EXIF: it couldn't be embedded in the {}",
+                            target.display(),
+                            options.format.name()
+                        );
                     }
+                    Ok(exported.rois.len())
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+                match saved {
+                    Ok(0) => println!("💾 Saved {} as {}", path.display(), options.format.name()),
+                    Ok(count) => println!(
+                        "💾 Saved {} as {} with {} ROIs",
+                        path.display(),
+                        options.format.name(),
+                        count
+                    ),
+                    Err(e) => eprintln!("Saving {} failed: {}", path.display(), e),
                 }
             }
-        }
+        });
+    };
+
+    let options = save_options();
+    let source_has_exif = source_has_exif();
+    let exif_available = source_has_exif && supports_exif(options.format);
+    let exif_hint = if !source_has_exif {
+        "The original has no EXIF data"
+    } else if !exif_available {
+        "Only JPEG and PNG can carry EXIF"
+    } else {
+        "Copies the original's EXIF, with orientation reset to upright"
     };
 
+    let (render, render_error) = match rendered() {
+        Some(Ok(r)) => (Some(r), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let thumbnails = render.as_ref().map(|r| r.thumbnails.clone()).unwrap_or_default();
+    let histogram = render.as_ref().and_then(|r| r.histogram.clone());
+    let step_count = pipeline.read().steps.len();
+
     rsx! {
-        div { class: "p-4 font-sans",
+        div { class: "p-4 font-sans outline-none",
+            tabindex: "0",
+            onkeydown: on_key_down,
             button {
                 onclick: pick_image,
                 class: "px-4 py-2 bg-indigo-600 text-white rounded text-2xl",
@@ -214,7 +707,196 @@ pub fn ImageUploader() -> Element {
                 if let Some(url) = image_data_url() {
                     Some(rsx! {
                         div { class: "mt-4",
-                        img { src: "{url}", class: "max-w-[600px] border rounded shadow mb-4" }
+                        div { class: "flex gap-4 items-start",
+                            if picking_corners() {
+                                CornerPicker {
+                                    src: render.as_ref().map_or(url.clone(), |r| r.image_url.clone()),
+                                    image_width: render.as_ref().map_or(source_size().0, |r| r.size.0),
+                                    image_height: render.as_ref().map_or(source_size().1, |r| r.size.1),
+                                    on_pick: move |corners| {
+                                        picking_corners.set(false);
+                                        add_step(Filter::Perspective { corners });
+                                    },
+                                    on_cancel: move |_| picking_corners.set(false),
+                                }
+                            } else if picking_crop() {
+                                CropPicker {
+                                    src: render.as_ref().map_or(url.clone(), |r| r.image_url.clone()),
+                                    image_width: render.as_ref().map_or(source_size().0, |r| r.size.0),
+                                    image_height: render.as_ref().map_or(source_size().1, |r| r.size.1),
+                                    on_crop: move |rect| {
+                                        picking_crop.set(false);
+                                        add_step(Filter::Crop { rect });
+                                    },
+                                    on_cancel: move |_| picking_crop.set(false),
+                                }
+                            } else if let Some(r) = render.clone() {
+                                CompareView {
+                                    original: url.clone(),
+                                    result: r.image_url,
+                                    size: r.size,
+                                    boxes: r.rois,
+                                }
+                            } else {
+                                img { src: "{url}", class: "max-w-[600px] border rounded shadow mb-4" }
+                            }
+
+                            div { class: "flex flex-col gap-2 min-w-[320px]",
+                                div { class: "flex items-center",
+                                    span { class: "font-semibold", "Pipeline" }
+                                    if rendering() {
+                                        span { class: "text-xs text-gray-500 ml-2", "Updating preview…" }
+                                    }
+                                    button {
+                                        disabled: step_count == 0,
+                                        onclick: save_pipeline,
+                                        class: "px-2 py-1 bg-emerald-600 text-white rounded text-sm ml-auto mr-2",
+                                        "Save recipe…"
+                                    }
+                                    button {
+                                        onclick: load_pipeline,
+                                        class: "px-2 py-1 bg-emerald-600 text-white rounded text-sm mr-2",
+                                        "Load recipe…"
+                                    }
+                                    button {
+                                        disabled: step_count == 0,
+                                        onclick: clear_pipeline,
+                                        class: "px-2 py-1 bg-gray-400 text-white rounded text-sm",
+                                        "Clear"
+                                    }
+                                }
+                                if step_count == 0 {
+                                    div { class: "text-sm text-gray-500", "No steps yet, the original image is shown" }
+                                }
+                                for (i, step) in pipeline.read().steps.iter().cloned().enumerate() {
+                                    div {
+                                        key: "{i}-{step.filter}",
+                                        class: "flex items-center gap-2 p-1 border rounded",
+                                        style: if tuning().is_some_and(|t| t.step == Some(i)) { "border-color: #6366f1;" } else { "" },
+                                        input {
+                                            r#type: "checkbox",
+                                            checked: step.enabled,
+                                            title: "Enable or bypass this step",
+                                            onchange: move |_| edit_step(i, Pipeline::toggle),
+                                        }
+                                        if let Some(thumb) = thumbnails.get(i) {
+                                            img { src: "{thumb}", class: "w-12 h-12 object-contain border bg-white" }
+                                        }
+                                        span {
+                                            class: "flex-1 text-sm font-mono",
+                                            style: if step.enabled { "" } else { "opacity: 0.5; text-decoration: line-through;" },
+                                            "{i + 1}. {step.filter}"
+                                        }
+                                        button {
+                                            disabled: i == 0,
+                                            onclick: move |_| edit_step(i, |p, i| p.move_step(i, i - 1)),
+                                            class: "px-1 text-sm",
+                                            "↑"
+                                        }
+                                        button {
+                                            disabled: i + 1 == step_count,
+                                            onclick: move |_| edit_step(i, |p, i| p.move_step(i, i + 1)),
+                                            class: "px-1 text-sm",
+                                            "↓"
+                                        }
+                                        if !step.filter.params().is_empty() {
+                                            button {
+                                                onclick: {
+                                                    let filter = step.filter.clone();
+                                                    move |_| tuning.set(Some(Tuning { step: Some(i), filter: filter.clone() }))
+                                                },
+                                                class: "px-1 text-sm",
+                                                title: "Edit parameters",
+                                                "✎"
+                                            }
+                                        }
+                                        button {
+                                            onclick: move |_| edit_step(i, Pipeline::remove),
+                                            class: "px-1 text-sm text-red-600",
+                                            title: "Remove step",
+                                            "✕"
+                                        }
+                                    }
+                                }
+                                if let Some(e) = render_error.clone() {
+                                    div { class: "text-sm text-red-600", "Pipeline failed: {e}" }
+                                }
+                            }
+                        }
+
+                        if let Some(Tuning { step, filter }) = tuning() {
+                            div { class: "flex flex-col gap-2 mb-4 p-3 border rounded bg-gray-50",
+                                div { class: "font-semibold",
+                                    if let Some(index) = step {
+                                        "Edit step {index + 1}: {filter.name()} (live preview)"
+                                    } else {
+                                        "Tune {filter.name()} (live preview)"
+                                    }
+                                }
+                                for (i, (spec, value)) in filter.params().into_iter().enumerate() {
+                                    ParamControl {
+                                        key: "{spec.name}",
+                                        spec,
+                                        value,
+                                        on_change: move |v: f64| {
+                                            tuning.with_mut(|t| {
+                                                if let Some(t) = t.as_mut() {
+                                                    t.filter = t.filter.with_param(i, v);
+                                                }
+                                            });
+                                        }
+                                    }
+                                }
+                                if let Some(LevelHistogram { bins, markers, caption }) = histogram.clone() {
+                                    div { class: "flex items-center gap-2",
+                                        svg {
+                                            width: "256",
+                                            height: "64",
+                                            view_box: "0 0 256 64",
+                                            class: "border bg-white",
+                                            polyline {
+                                                points: "{histogram_points(&bins, 256.0, 64.0)}",
+                                                fill: "none",
+                                                stroke: "#374151",
+                                                stroke_width: "1",
+                                            }
+                                            for marker in markers {
+                                                line {
+                                                    x1: "{marker}",
+                                                    x2: "{marker}",
+                                                    y1: "0",
+                                                    y2: "64",
+                                                    stroke: "#dc2626",
+                                                    stroke_width: "1.5",
+                                                }
+                                            }
+                                        }
+                                        span { class: "text-sm font-mono", "{caption}" }
+                                    }
+                                }
+                                div { class: "flex gap-2",
+                                    button {
+                                        onclick: apply_tuned,
+                                        class: "px-4 py-2 bg-indigo-600 text-white rounded",
+                                        if step.is_some() { "Update" } else { "Add step" }
+                                    }
+                                    button {
+                                        onclick: move |_| tuning.set(None),
+                                        class: "px-4 py-2 bg-gray-400 text-white rounded",
+                                        "Cancel"
+                                    }
+                                    if matches!(filter, Filter::Threshold { .. }) {
+                                        button {
+                                            disabled: image_path().is_none(),
+                                            onclick: save_mask,
+                                            title: "Save the white blobs as ROIs of this image for the ROI canvas",
+                                            class: "px-4 py-2 bg-teal-600 text-white rounded",
+                                            "Mask → ROIs"
+                                        }
+                                    }
+                                }
+                            }
+                        }
 
                         div { class: "flex gap-4",
                             button {
@@ -225,7 +907,7 @@ pub fn ImageUploader() -> Element {
                             button {
                                 onclick: apply_resize,
                                 class: "px-4 py-2 bg-green-500 text-white rounded",
-                                "Resize 200x200"
+                                "Resize"
                             }
                             button {
                                 onclick: apply_grayscale,
@@ -243,17 +925,131 @@ pub fn ImageUploader() -> Element {
                                 "Edge Detect"
                             }
                             button {
-                                onclick: apply_crop,
+                                onclick: apply_threshold,
+                                class: "px-4 py-2 bg-purple-600 text-white rounded",
+                                "Threshold"
+                            }
+                            button {
+                                onclick: apply_morphology,
+                                class: "px-4 py-2 bg-purple-600 text-white rounded",
+                                "Morphology"
+                            }
+                            for (name, label) in TONE_TOOLS {
+                                button {
+                                    onclick: move |_| open_tuner(name),
+                                    class: "px-4 py-2 bg-amber-600 text-white rounded",
+                                    "{label}"
+                                }
+                            }
+                            for (name, label) in DENOISE_TOOLS {
+                                button {
+                                    onclick: move |_| open_tuner(name),
+                                    class: "px-4 py-2 bg-teal-600 text-white rounded",
+                                    "{label}"
+                                }
+                            }
+                            button {
+                                disabled: picking_crop(),
+                                onclick: start_crop,
                                 class: "px-4 py-2 bg-blue-500 text-white rounded",
-                                "Crop (ROI)"
+                                "Crop…"
                             }
 
+                            for (spec, label) in ORIENTATION_TOOLS {
+                                button {
+                                    onclick: move |_| {
+                                        if let Ok(filter) = spec.parse() {
+                                            add_step(filter);
+                                        }
+                                    },
+                                    class: "px-4 py-2 bg-green-500 text-white rounded",
+                                    "{label}"
+                                }
+                            }
                             button {
-                                onclick: apply_rotate_90,
+                                onclick: move |_| open_tuner("angle"),
                                 class: "px-4 py-2 bg-green-500 text-white rounded",
-                                "Rotate 90°"
+                                "Rotate…"
+                            }
+                            button {
+                                disabled: picking_corners(),
+                                onclick: start_warp,
+                                class: "px-4 py-2 bg-green-500 text-white rounded",
+                                "Perspective…"
+                            }
+                            button {
+                                disabled: !filter_history.read().can_undo(),
+                                onclick: move |_| undo_filter(),
+                                class: "px-4 py-2 bg-slate-600 text-white rounded",
+                                "↶ Undo"
+                            }
+                            button {
+                                disabled: !filter_history.read().can_redo(),
+                                onclick: move |_| redo_filter(),
+                                class: "px-4 py-2 bg-slate-600 text-white rounded",
+                                "↷ Redo"
                             }
                         }
+
+                        div { class: "flex flex-wrap items-center gap-4 mt-4 p-3 border rounded",
+                            span { class: "font-semibold", "Save as" }
+                            select {
+                                class: "border rounded px-2 py-1",
+                                onchange: move |evt| {
+                                    if let Some(format) = ImageFormat::from_name(&evt.value()) {
+                                        save_options.with_mut(|o| o.format = format);
+                                    }
+                                },
+                                for format in ImageFormat::ALL {
+                                    option { value: format.name(), selected: format == options.format, "{format.name()}" }
+                                }
+                            }
+                            if matches!(options.format, ImageFormat::Jpeg | ImageFormat::WebP) {
+                                ParamControl {
+                                    spec: ParamSpec::new("quality", 1.0, 100.0, 1.0),
+                                    value: options.quality as f64,
+                                    on_change: move |v: f64| save_options.with_mut(|o| o.quality = v as i32),
+                                }
+                            }
+                            if options.format == ImageFormat::Png {
+                                ParamControl {
+                                    spec: ParamSpec::new("compression", 0.0, 9.0, 1.0),
+                                    value: options.png_compression as f64,
+                                    on_change: move |v: f64| save_options.with_mut(|o| o.png_compression = v as i32),
+                                }
+                            }
+                            if options.format == ImageFormat::Tiff {
+                                label { class: "flex items-center gap-1 text-sm",
+                                    input {
+                                        r#type: "checkbox",
+                                        checked: options.tiff_lzw,
+                                        onchange: move |evt| save_options.with_mut(|o| o.tiff_lzw = evt.checked()),
+                                    }
+                                    "LZW compression"
+                                }
+                            }
+                            label {
+                                class: "flex items-center gap-1 text-sm",
+                                title: exif_hint,
+                                input {
+                                    r#type: "checkbox",
+                                    disabled: !exif_available,
+                                    checked: keep_exif() && exif_available,
+                                    onchange: move |evt| keep_exif.set(evt.checked()),
+                                }
+                                "Keep original EXIF"
+                            }
+                            button {
+                                onclick: save_as,
+                                class: "px-4 py-2 bg-indigo-600 text-white rounded",
+                                "Save as…"
+                            }
+                        }
+
+                        BatchPanel {
+                            pipeline: pipeline(),
+                            input_dir: image_path().and_then(|p| p.parent().map(|dir| dir.to_path_buf())),
+                        }
                     }
 
                     })
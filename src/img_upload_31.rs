@@ -1,35 +1,63 @@
 // Updated ImageUploader31 component with optional Subsample ROI placement
-use crate::dioxus_elements::geometry::WheelDelta;
 use base64::engine::general_purpose;
 use base64::Engine;
 use dioxus::prelude::*;
 use rfd::FileDialog;
 use std::fs;
+use std::path::PathBuf;
+
+use crate::core::annotations::{export_dataset, import_dataset, AnnotationFormat};
+use crate::core::color_model::{to_color_space, ChannelOrder, ColorSpace};
+use crate::core::crop::export_roi_crops;
+use crate::core::history::History;
+use crate::core::pixel_export::{export_roi_pixels, RoiPixels};
+use crate::core::roi_model::{group_labels, ClassPalette, LabeledRoi};
+use crate::core::roi_sidecar::{load_rois, save_rois};
+use crate::core::roi_stats::{compute_roi_stats, export_folder_summary, histogram_points, PERCENTILES};
+use crate::core::transform::zoom_step;
+use crate::filmstrip::Filmstrip;
+use crate::roi_canvas::RoiCanvas;
 
 use opencv::{
-    core::{Rect, Scalar, Vec3b, Vector},
+    core::Vector,
     imgcodecs::{imdecode, imencode, IMREAD_COLOR},
-    imgproc::rectangle,
     prelude::*,
 };
 
-fn point_in_rect(x: i32, y: i32, roi: &Rect) -> bool {
-    x >= roi.x && y >= roi.y && x < roi.x + roi.width && y < roi.y + roi.height
+// Largest block of pixels rendered in the pixel table, per side
+const PIXEL_TABLE_MAX: i32 = 64;
+
+// ROI lists are tiny, this allows thousands of undo steps per image
+const ROI_HISTORY_BUDGET_BYTES: usize = 4 * 1024 * 1024;
+
+fn channel_color(name: &str) -> &'static str {
+    match name {
+        "R" | "Cr" => "#ef4444",
+        "G" => "#22c55e",
+        "B" | "Cb" => "#3b82f6",
+        "H" => "#f59e0b",
+        "S" => "#a855f7",
+        "a" => "#ec4899",
+        "b" => "#14b8a6",
+        _ => "#374151",
+    }
+}
+
+fn format_pixel(values: &[u8]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
 }
 
 #[component]
 pub fn ImageUploader31() -> Element {
     let image_data_url = use_signal(|| None::<String>);
-    let rois = use_signal(|| Vec::<Rect>::new());
+    let mut rois = use_signal(|| Vec::<LabeledRoi>::new());
     let mut scale = use_signal(|| 1.0f32);
     let image_width = use_signal(|| 0f32);
     let image_height = use_signal(|| 0f32);
 
-    let drag_start = use_signal(|| None::<(i32, i32)>);
-    let drag_current = use_signal(|| None::<(i32, i32)>);
     let mut subsample_mode = use_signal(|| true);
-    let mut subsample_grayscale = use_signal(|| false);
-    let mut subsample_rgb = use_signal(|| true);
+    let subsample_grayscale = use_signal(|| false);
+    let subsample_rgb = use_signal(|| true);
 
     let all_image_paths = use_signal(|| Vec::<std::path::PathBuf>::new());
     let mut current_index = use_signal(|| 0usize);
@@ -39,224 +67,141 @@ pub fn ImageUploader31() -> Element {
     let mut roi_height = use_signal(|| 16i32);
 
     let mut original_mat= use_signal(|| None::<Mat>);
-
-
-    let on_wheel = {
-        to_owned![scale];
-        move |evt: WheelEvent| {
-            let delta = evt.data().delta();
-            scale.with_mut(|s| match delta {
-                WheelDelta::Pixels(pixels) => {
-                    if pixels.y < 0.0 {
-                        *s = (*s * 1.1).min(5.0);
-                    } else {
-                        *s = (*s / 1.1).max(0.2);
-                    }
+    // Channel layout `load_image` left in `original_mat`
+    let mut mat_channel_order = use_signal(|| ChannelOrder::Bgr);
+    // Colour space every readout, statistic and export is reported in
+    let mut readout_space = use_signal(|| ColorSpace::Rgb);
+
+    // `original_mat` converted to the readout space, kept next to the space it
+    // was converted to so channel names can never lag behind the values
+    let mut readout_mat = use_signal(|| None::<(ColorSpace, Mat)>);
+    use_effect(move || {
+        let space = readout_space();
+        let converted = original_mat.read().as_ref().and_then(|mat| {
+            match to_color_space(mat, mat_channel_order(), space) {
+                Ok(converted) => Some((space, converted)),
+                Err(e) => {
+                    eprintln!("Failed to convert image to {}: {}", space.name(), e);
+                    None
                 }
-                _ => {}
-            });
+            }
+        });
+        readout_mat.set(converted);
+    });
+
+    // Recomputed whenever the ROIs or the image change, including mid-drag
+    let roi_stats = use_memo(move || {
+        readout_mat
+            .read()
+            .as_ref()
+            .map(|(space, mat)| compute_roi_stats(mat, &rois.read(), *space))
+            .unwrap_or_default()
+    });
+
+    let mut annotation_format = use_signal(|| AnnotationFormat::Coco);
+
+    // Class palette of the current folder and the class given to new ROIs
+    let mut palette = use_signal(ClassPalette::default);
+    let mut active_class = use_signal(|| ClassPalette::default().classes[0].name.clone());
+    let mut new_class_name = use_signal(String::new);
+
+    // Selected ROI (index into `rois`), shared with the canvas
+    let mut selected_roi = use_signal(|| None::<usize>);
+
+    // Undo/redo of ROI edits on the current image
+    let mut roi_history = use_signal(|| History::<Vec<LabeledRoi>>::new(ROI_HISTORY_BUDGET_BYTES));
+
+    // Image the ROIs in `rois` were loaded for, with its size; `None` while an
+    // image is loading, which blocks edits so they can't land in the wrong sidecar
+    let mut rois_image = use_signal(|| None::<(PathBuf, i32, i32)>);
+    // Bumped by every load, so a slow earlier load can't replace a later one
+    let mut load_generation = use_signal(|| 0u64);
+    let mut load_failed = use_signal(|| false);
+
+    let persist_palette = move || {
+        if let Some(folder) = all_image_paths.read().first().and_then(|p| p.parent()) {
+            if let Err(e) = palette.read().save(folder) {
+                eprintln!("Failed to save class palette in {}: {}", folder.display(), e);
+            }
         }
     };
 
-    let on_mouse_down = {
-        to_owned![scale, image_width, image_height, drag_start, drag_current, rois, subsample_mode, subsample_grayscale, subsample_rgb, roi_width, roi_height, original_mat];
-        move |evt: MouseEvent| {
-            let coords = evt.data().element_coordinates();
-            let shift_pressed = evt.data().modifiers().shift();
-            let scale_val = scale();
-            let x = (coords.x / scale_val as f64) as i32;
-            let y = (coords.y / scale_val as f64) as i32;
-
-            if x >= 0 && y >= 0 && x < image_width() as i32 && y < image_height() as i32 {
-                if subsample_mode() {
-                    let w = roi_width();
-                    let h = roi_height();
-                    let roi = Rect::new(x - (w / 2), y - (h / 2), w, h); // ⬅️ Centered ROI
-
-                    // rois.with_mut(|r| r.push(roi));
-                    if shift_pressed {
-                        rois.with_mut(|r| {
-                            if let Some(index) = r.iter().position(|existing_roi| {
-                                point_in_rect(x, y, existing_roi)
-                            }) {
-                                r.remove(index);
-                            }
-                        });
-                    } else {
-                        rois.with_mut(|r| r.push(roi));
-                    }
-
-                    if let Some(image) = &*original_mat.read() {
-                        if subsample_grayscale() {
-                            println!("🟢 Grayscale ROI @ ({x},{y}) ============>");
-                            let mut count = 0;
-                            for row in 0..h {
-                                for col in 0..w {
-                                    if count >= 256 {
-                                        break;
-                                    }
-                                    match image.at_2d::<u8>(y + row, x + col) {
-                                        Ok(val) => print!("{:3}, ", val),
-                                        Err(_) => print!("Err, "),
-                                    }
-                                    count += 1;
-                                }
-                                if count >= 256 {
-                                    break;
-                                }
-                                println!();
-                            }
-                        } else if subsample_rgb() {
-                            println!("🎨  R Channel ({}x{})", w, h);
-                            for row in 0..h {
-                                for col in 0..w {
-                                    match image.at_2d::<Vec3b>(y + row, x + col) {
-                                        Ok(val) => print!("{:4}", val[0]), // R channel
-                                        Err(_) => print!("Err "),
-                                    }
-                                }
-                                println!();
-                            }
-
-                            println!("🎨  G Channel ({}x{})", w, h);
-                            for row in 0..h {
-                                for col in 0..w {
-                                    match image.at_2d::<Vec3b>(y + row, x + col) {
-                                        Ok(val) => print!("{:4}", val[1]), // G channel
-                                        Err(_) => print!("Err "),
-                                    }
-                                }
-                                println!();
-                            }
-
-                            println!("🎨  B Channel ({}x{})", w, h);
-                            for row in 0..h {
-                                for col in 0..w {
-                                    match image.at_2d::<Vec3b>(y + row, x + col) {
-                                        Ok(val) => print!("{:4}", val[2]), // B channel
-                                        Err(_) => print!("Err "),
-                                    }
-                                }
-                                println!();
-                            }
-
-                            println!("🔗  Combined RGB ({}x{})", w, h);
-                            for row in 0..h {
-                                for col in 0..w {
-                                    match image.at_2d::<Vec3b>(y + row, x + col) {
-                                        Ok(val) => print!("[{:3}, {:3}, {:3}] ", val[0], val[1], val[2]), // R, G, B
-                                        Err(_) => print!("[Err] "),
-                                    }
-                                }
-                                println!();
-                            }
-                        }
-                    }
-                } else {
-                    drag_start.set(Some((x, y)));
-                    drag_current.set(Some((x, y)));
-                }
+    // Write the ROIs to their image's sidecar after every edit
+    let persist_rois = move || {
+        if let Some((path, width, height)) = rois_image.read().as_ref() {
+            if let Err(e) = save_rois(path, *width, *height, &rois.read()) {
+                eprintln!("Failed to save ROIs for {}: {}", path.display(), e);
             }
         }
     };
 
+    // Snapshot taken before every ROI edit
+    let mut record_roi_edit = move || roi_history.write().record(rois());
 
-    let on_mouse_move = {
-        to_owned![scale, drag_current];
-        move |evt: MouseEvent| {
-            if drag_start().is_some() {
-                let coords = evt.data().element_coordinates();
-                let scale_val = scale();
-                let x = (coords.x / scale_val as f64) as i32;
-                let y = (coords.y / scale_val as f64) as i32;
-                drag_current.set(Some((x, y)));
-            }
+    let mut undo_roi_edit = move || {
+        if let Some(previous) = roi_history.write().undo(rois()) {
+            rois.set(previous);
+            selected_roi.set(None);
+            persist_rois();
         }
     };
 
-    let on_mouse_up = {
-        to_owned![drag_start, drag_current, rois, image_width, image_height, subsample_mode, subsample_grayscale, subsample_rgb, original_mat];
-        move |evt: MouseEvent| {
-            if subsample_mode() {
-                drag_start.set(None);
-                drag_current.set(None);
-                return;
-            }
+    let mut redo_roi_edit = move || {
+        if let Some(next) = roi_history.write().redo(rois()) {
+            rois.set(next);
+            selected_roi.set(None);
+            persist_rois();
+        }
+    };
 
-            if let (Some((x0, y0)), Some((x1, y1))) = (drag_start(), drag_current()) {
-                let shift_pressed = evt.data().modifiers().shift();
-
-                let x = x0.min(x1);
-                let y = y0.min(y1);
-                let roi_width = (x1 - x0).abs();
-                let roi_height = (y1 - y0).abs();
-
-                let max_x = (image_width() as i32).saturating_sub(1);
-                let max_y = (image_height() as i32).saturating_sub(1);
-                let clamped_x = x.clamp(0, max_x);
-                let clamped_y = y.clamp(0, max_y);
-
-                if shift_pressed {
-                    rois.with_mut(|r| {
-                        if let Some(index) = r
-                            .iter()
-                            .position(|roi| point_in_rect(clamped_x, clamped_y, roi))
-                        {
-                            r.remove(index);
-                        }
-                    });
-                } else if roi_width > 0 && roi_height > 0 {
-                    let clamped_x = x.clamp(0, max_x - roi_width + 1);
-                    let clamped_y = y.clamp(0, max_y - roi_height + 1);
-
-                    rois.with_mut(|r| {
-                        r.push(Rect::new(clamped_x, clamped_y, roi_width, roi_height));
-                    });
-
-                    if let Some(mat) = original_mat.read().as_ref() {
-                        let roi = Rect::new(clamped_x, clamped_y, roi_width, roi_height);
-
-                        for y in roi.y..roi.y + roi.height {
-                            for x in roi.x..roi.x + roi.width {
-                                if y < mat.rows() && x < mat.cols() {
-                                    match mat.typ() {
-                                        // Grayscale image
-                                        opencv::core::CV_8UC1 => {
-                                            if let Ok(val) = mat.at_2d::<u8>(y, x) {
-                                                println!("Pixel at ({}, {}): Gray={}", x, y, *val);
-                                            }
-                                        }
-                                        // RGB image
-                                        opencv::core::CV_8UC3 => {
-                                            if let Ok(p) = mat.at_2d::<Vec3b>(y, x) {
-                                                println!(
-                                                    "Pixel at ({}, {}): R={} G={} B={}",
-                                                    x, y, p[2], p[1], p[0]
-                                                );
-                                            }
-                                        }
-                                        _ => {
-                                            println!("Unsupported image format at ({}, {})", x, y);
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+    // Shows image `index` of the folder with its saved ROIs. Nothing of the
+    // previous image stays editable meanwhile, and a load overtaken by a later
+    // one is dropped
+    let mut open_image = move |index: usize| {
+        let Some(path) = all_image_paths.read().get(index).cloned() else {
+            return;
+        };
+        current_index.set(index);
+        rois.set(Vec::new());
+        rois_image.set(None);
+        selected_roi.set(None);
+        roi_history.write().clear();
+        load_failed.set(false);
+        load_generation.with_mut(|g| *g += 1);
+        let generation = *load_generation.peek();
+        spawn({
+            to_owned![image_data_url, image_width, image_height, rois, scale];
+            async move {
+                let loaded = load_image(
+                    &path,
+                    generation,
+                    load_generation,
+                    &mut image_data_url,
+                    &mut image_width,
+                    &mut image_height,
+                    &mut rois,
+                    &mut rois_image,
+                    &mut scale,
+                    subsample_mode(),
+                    subsample_grayscale(),
+                    subsample_rgb(),
+                    &mut original_mat,
+                    &mut mat_channel_order
+                ).await;
+                if !loaded && *load_generation.peek() == generation {
+                    eprintln!("Failed to load {}", path.display());
+                    load_failed.set(true);
                 }
             }
-
-            drag_start.set(None);
-            drag_current.set(None);
-        }
+        });
     };
 
     #[cfg(not(target_arch = "wasm32"))]
     let pick_image = {
-        to_owned![image_data_url, rois, scale, image_width, image_height, all_image_paths, current_index];
+        to_owned![all_image_paths];
         move |_| {
             spawn({
-                to_owned![image_data_url, rois, scale, image_width, image_height, all_image_paths, current_index];
+                to_owned![all_image_paths];
                 async move {
                     if let Some(path) = FileDialog::new()
                         .add_filter("Image", &["png", "jpg", "jpeg", "webp"])
@@ -275,22 +220,16 @@ pub fn ImageUploader31() -> Element {
                                 }
 
                                 image_paths.sort();
+
+                                let folder_palette = ClassPalette::load(folder);
+                                if !folder_palette.contains(&active_class()) {
+                                    active_class.set(folder_palette.classes[0].name.clone());
+                                }
+                                palette.set(folder_palette);
+
                                 let selected_index = image_paths.iter().position(|p| p == &path).unwrap_or(0);
-                                current_index.set(selected_index);
                                 all_image_paths.set(image_paths);
-
-                                load_image(
-                                    &all_image_paths()[selected_index],
-                                    &mut image_data_url,
-                                    &mut image_width,
-                                    &mut image_height,
-                                    &mut rois,
-                                    &mut scale,
-                                    true,
-                                    false,
-                                    true,
-                                    &mut original_mat
-                                ).await;
+                                open_image(selected_index);
                             }
                         }
                     }
@@ -299,19 +238,145 @@ pub fn ImageUploader31() -> Element {
         }
     };
 
+    #[cfg(not(target_arch = "wasm32"))]
+    let export_annotations = move |_| {
+        let format = annotation_format();
+        let paths = all_image_paths();
+        if paths.is_empty() {
+            return;
+        }
+        spawn(async move {
+            if let Some(dir) = FileDialog::new().pick_folder() {
+                match export_dataset(format, &paths, &dir) {
+                    Ok(count) => println!("📦 Exported {} images as {} to {}", count, format.name(), dir.display()),
+                    Err(e) => eprintln!("{} export failed: {}", format.name(), e),
+                }
+            }
+        });
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let import_annotations = move |_| {
+        let format = annotation_format();
+        let paths = all_image_paths();
+        if paths.is_empty() {
+            return;
+        }
+        spawn(async move {
+            // COCO keeps everything in one JSON file, VOC and YOLO use one file per image
+            let source = match format {
+                AnnotationFormat::Coco => FileDialog::new().add_filter("COCO JSON", &["json"]).pick_file(),
+                _ => FileDialog::new().pick_folder(),
+            };
+            if let Some(source) = source {
+                match import_dataset(format, &source, &paths) {
+                    Ok(count) => {
+                        println!("📥 Imported {} ROIs for {} images", format.name(), count);
+                        if let Some((path, width, height)) = rois_image() {
+                            rois.set(load_rois(&path, width, height));
+                            selected_roi.set(None);
+                            roi_history.write().clear();
+                        }
+                    }
+                    Err(e) => eprintln!("{} import failed: {}", format.name(), e),
+                }
+            }
+        });
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let export_pixels = move |_| {
+        let Some((path, ..)) = rois_image() else {
+            return;
+        };
+        let Some((space, mat)) = readout_mat() else {
+            return;
+        };
+        let roi_list = rois();
+        spawn(async move {
+            if let Some(dir) = FileDialog::new().pick_folder() {
+                match export_roi_pixels(&mat, &roi_list, space, &path, &dir) {
+                    Ok(count) => println!("📤 Exported pixels of {} ROIs to {}", count, dir.display()),
+                    Err(e) => eprintln!("Pixel export failed: {}", e),
+                }
+            }
+        });
+    };
+
+    // Crops are cut from the image as loaded, written in the channel order PNG expects
+    #[cfg(not(target_arch = "wasm32"))]
+    let export_crops = move |_| {
+        let Some((path, ..)) = rois_image() else {
+            return;
+        };
+        let order = mat_channel_order();
+        let space = if order == ChannelOrder::Gray { ColorSpace::Gray } else { ColorSpace::Bgr };
+        let mat = match original_mat.read().as_ref().map(|mat| to_color_space(mat, order, space)) {
+            Some(Ok(mat)) => mat,
+            Some(Err(e)) => {
+                eprintln!("ROI crop export failed: {}", e);
+                return;
+            }
+            None => return,
+        };
+        let roi_list = rois();
+        spawn(async move {
+            if let Some(dir) = FileDialog::new().pick_folder() {
+                match export_roi_crops(&mat, &roi_list, &path, &dir) {
+                    Ok(count) => println!("✂️ Saved {} ROI crops to {}", count, dir.display()),
+                    Err(e) => eprintln!("ROI crop export failed: {}", e),
+                }
+            }
+        });
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let export_stats = move |_| {
+        let paths = all_image_paths();
+        if paths.is_empty() {
+            return;
+        }
+        let space = readout_space();
+        spawn(async move {
+            if let Some(out) = FileDialog::new()
+                .set_file_name("roi_stats.csv")
+                .add_filter("CSV", &["csv"])
+                .save_file()
+            {
+                // Decodes every image of the folder, so it runs off the UI thread
+                let target = out.clone();
+                let summary = tokio::task::spawn_blocking(move || {
+                    export_folder_summary(&paths, space, &target)
+                })
+                .await
+                .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+                match summary {
+                    Ok(count) => println!("📊 Wrote statistics for {} ROIs to {}", count, out.display()),
+                    Err(e) => eprintln!("Statistics export failed: {}", e),
+                }
+            }
+        });
+    };
+
+    // Returns false when the file couldn't be read or decoded. Nothing is shown
+    // once `load_generation` has moved past `generation`
     #[cfg(not(target_arch = "wasm32"))]
     async fn load_image(
         path: &std::path::PathBuf,
+        generation: u64,
+        load_generation: Signal<u64>,
         image_data_url: &mut Signal<Option<String>>,
         image_width: &mut Signal<f32>,
         image_height: &mut Signal<f32>,
-        rois: &mut Signal<Vec<Rect>>,
+        rois: &mut Signal<Vec<LabeledRoi>>,
+        rois_image: &mut Signal<Option<(PathBuf, i32, i32)>>,
         scale: &mut Signal<f32>,
         subsample_mode: bool,
         subsample_grayscale: bool,
         subsample_rgb: bool,
         original_mat: &mut Signal<Option<Mat>>,
-    ) {
+        mat_channel_order: &mut Signal<ChannelOrder>,
+    ) -> bool {
         if let Ok(bytes) = tokio::fs::read(path).await {
             let mime = match path.extension().and_then(|e| e.to_str()) {
                 Some("png") => "image/png",
@@ -323,6 +388,7 @@ pub fn ImageUploader31() -> Element {
             if let Ok(mat) = Mat::from_slice(&bytes) {
                 if let Ok(mut image) = imdecode(&mat, IMREAD_COLOR) {
                     let original_image= image.clone();
+                    let mut channel_order = ChannelOrder::Bgr;
                     // Handle subsampling mode
                     if subsample_mode {
                         if subsample_grayscale {
@@ -331,6 +397,7 @@ pub fn ImageUploader31() -> Element {
                             let mut gray = Mat::default();
                             if imgproc::cvt_color(&image, &mut gray, imgproc::COLOR_BGR2GRAY, 0, AlgorithmHint::ALGO_HINT_DEFAULT).is_ok() {
                                 image = gray; // Replace original image with grayscale
+                                channel_order = ChannelOrder::Gray;
                             }
                         } else if subsample_rgb {
                             use opencv::{core::AlgorithmHint, imgproc};
@@ -338,14 +405,19 @@ pub fn ImageUploader31() -> Element {
                             let mut rgb = Mat::default();
                             if imgproc::cvt_color(&image, &mut rgb, imgproc::COLOR_BGR2RGB, 0, AlgorithmHint::ALGO_HINT_DEFAULT).is_ok() {
                                 image = rgb;
+                                channel_order = ChannelOrder::Rgb;
                             }
                         }
                     }
 
+                    if *load_generation.peek() != generation {
+                        return true;
+                    }
                     let (w, h) = (image.cols(), image.rows());
                     image_width.set(w as f32);
                     image_height.set(h as f32);
                     original_mat.set(Some(image.clone()));
+                    mat_channel_order.set(channel_order);
 
                     let mut buf = Vector::new();
                     // Use ".jpg" regardless of format for simplicity
@@ -353,44 +425,126 @@ pub fn ImageUploader31() -> Element {
                         let encoded = general_purpose::STANDARD.encode(buf.to_vec());
                         let data_url = format!("data:{};base64,{}", mime, encoded);
                         image_data_url.set(Some(data_url));
-                        rois.set(vec![]);
+                        rois.set(load_rois(path, w, h));
+                        rois_image.set(Some((path.clone(), w, h)));
                         scale.set(1.0);
+                        return true;
                     }
                 }
             }
         }
+        false
     }
 
 
-    let dragging_preview = if let (Some((x0, y0)), Some((x1, y1))) = (drag_start(), drag_current()) {
-        let scale_val = scale();
-        let x = x0.min(x1);
-        let y = y0.min(y1);
-        let width = (x1 - x0).abs();
-        let height = (y1 - y0).abs();
-        let roi = Rect::new(x, y, width, height);
-        let left = (roi.x as f32 * scale_val).round();
-        let top = (roi.y as f32 * scale_val).round();
-        let w = (roi.width as f32 * scale_val).round();
-        let h = (roi.height as f32 * scale_val).round();
-        Some(rsx! {
-            div {
-                class: "absolute border-2 border-blue-400 bg-blue-200 bg-opacity-30 pointer-events-none",
-                style: "left: {left}px; top: {top}px; width: {w}px; height: {h}px;",
+    // Ctrl+Z / Ctrl+Shift+Z undo and redo; the canvas handles the other keys
+    let on_key_down = move |evt: KeyboardEvent| {
+        let modifiers = evt.modifiers();
+        if !(modifiers.ctrl() || modifiers.meta()) {
+            return;
+        }
+        match evt.key() {
+            Key::Character(c) if c.eq_ignore_ascii_case("z") => {
+                evt.prevent_default();
+                if modifiers.shift() {
+                    redo_roi_edit();
+                } else {
+                    undo_roi_edit();
+                }
+            }
+            Key::Character(c) if c.eq_ignore_ascii_case("y") => {
+                evt.prevent_default();
+                redo_roi_edit();
             }
+            _ => {}
+        }
+    };
+
+    // Values of the selected ROI for the pixel table
+    let pixel_table = selected_roi()
+        .and_then(|i| rois.read().get(i).cloned())
+        .and_then(|roi| {
+            let readout = readout_mat.read();
+            let (space, mat) = readout.as_ref()?;
+            RoiPixels::from_mat(mat, &roi, *space).ok().flatten()
+        });
+
+    // Side panel data: ROIs grouped by class, keeping their index into `rois`
+    let palette_val = palette();
+    let roi_groups: Vec<(String, String, Vec<(usize, LabeledRoi)>)> = group_labels(&palette_val, &rois.read())
+        .into_iter()
+        .map(|label| {
+            let color = palette_val.color_for(&label).to_string();
+            let members = rois
+                .read()
+                .iter()
+                .cloned()
+                .enumerate()
+                .filter(|(_, roi)| roi.label == label)
+                .collect();
+            (label, color, members)
         })
-    } else {
-        None
+        .filter(|group| !group.2.is_empty())
+        .collect();
+
+    let add_class = move |_| {
+        let name = new_class_name().trim().to_string();
+        if palette.with_mut(|p| p.add_class(&name)) {
+            active_class.set(name);
+            new_class_name.set(String::new());
+            persist_palette();
+        }
     };
 
-    
     rsx! {
         div { class: "p-4 font-sans",
             div { class: "flex gap-2 mb-4",
                 button { onclick: pick_image, class: "px-4 py-2 bg-indigo-600 text-white rounded", "Upload Image" },
                 button { onclick: move |_| subsample_mode.set(!subsample_mode()), class: "px-4 py-2 bg-yellow-500 text-white rounded", "Toggle Subsample" },
-                button { onclick: move |_| scale.with_mut(|s| *s *= 1.1), class: "px-2 py-1 bg-green-600 text-white rounded", "+" },
-                button { onclick: move |_| scale.with_mut(|s| *s /= 1.1), class: "px-2 py-1 bg-red-600 text-white rounded", "-" },
+                button { onclick: move |_| scale.with_mut(|s| *s = zoom_step(*s, true)), class: "px-2 py-1 bg-green-600 text-white rounded", "+" },
+                button { onclick: move |_| scale.with_mut(|s| *s = zoom_step(*s, false)), class: "px-2 py-1 bg-red-600 text-white rounded", "-" },
+                select {
+                    class: "border rounded px-2 py-1",
+                    onchange: move |evt| {
+                        if let Some(format) = AnnotationFormat::from_name(&evt.value()) {
+                            annotation_format.set(format);
+                        }
+                    },
+                    for format in AnnotationFormat::ALL {
+                        option { value: format.name(), selected: format == annotation_format(), "{format.name()}" }
+                    }
+                }
+                button { onclick: export_annotations, class: "px-4 py-2 bg-gray-700 text-white rounded", "Export Dataset" },
+                button { onclick: import_annotations, class: "px-4 py-2 bg-gray-500 text-white rounded", "Import Dataset" },
+                label { class: "flex items-center gap-1 text-sm",
+                    "Readout"
+                    select {
+                        class: "border rounded px-2 py-1",
+                        onchange: move |evt| {
+                            if let Some(space) = ColorSpace::from_name(&evt.value()) {
+                                readout_space.set(space);
+                            }
+                        },
+                        for space in ColorSpace::ALL {
+                            option { value: space.name(), selected: space == readout_space(), "{space.name()}" }
+                        }
+                    }
+                }
+                button { onclick: export_pixels, class: "px-4 py-2 bg-teal-600 text-white rounded", "Export ROI pixels" },
+                button { onclick: export_crops, class: "px-4 py-2 bg-teal-600 text-white rounded", "Save ROI crops" },
+                button { onclick: export_stats, class: "px-4 py-2 bg-teal-700 text-white rounded", "Export stats CSV" },
+                button {
+                    disabled: !roi_history.read().can_undo(),
+                    onclick: move |_| undo_roi_edit(),
+                    class: "px-2 py-1 bg-slate-600 text-white rounded",
+                    "↶ Undo"
+                },
+                button {
+                    disabled: !roi_history.read().can_redo(),
+                    onclick: move |_| redo_roi_edit(),
+                    class: "px-2 py-1 bg-slate-600 text-white rounded",
+                    "↷ Redo"
+                },
             }
             label {
                 class: "text-sm",
@@ -425,43 +579,152 @@ pub fn ImageUploader31() -> Element {
                 }
             }    
             if let Some(url) = image_data_url() {
-                div {
-                    style: "width: 640px; height: 440px; overflow: auto; border: 2px solid #ccc; margin: auto;",
-                    div {
-                        class: "relative",
-                        style: "width: 600px; height: 400px; position: relative;",
-
-                        div {
-                            class: "relative border",
-                            style: "width: {(image_width() * scale())}px; height: {(image_height() * scale())}px;",
-                            onmousedown: on_mouse_down,
-                            onmousemove: on_mouse_move,
-                            onmouseup: on_mouse_up,
-                            // onwheel: on_wheel,
-
-                            img {
-                                src: "{url}",
-                                class: "select-none pointer-events-none",
-                                style: "width: {(image_width() * scale())}px; height: {(image_height() * scale())}px;",
-                            }
+                if rois_image.read().is_none() {
+                    p { class: "text-sm text-gray-500 my-2",
+                        if load_failed() { "Couldn't load this image; pick another one." } else { "Loading…" }
+                    }
+                }
+                div { class: "flex gap-4 items-start",
+                div { onkeydown: on_key_down,
+                    RoiCanvas {
+                        src: url,
+                        image_width: image_width() as i32,
+                        image_height: image_height() as i32,
+                        scale,
+                        rois,
+                        selected: selected_roi,
+                        click_size: if subsample_mode() { Some((roi_width(), roi_height())) } else { None },
+                        label: active_class(),
+                        palette: palette_val.clone(),
+                        show_labels: true,
+                        editable: rois_image.read().is_some(),
+                        zoom_controls: true,
+                        readout: readout_mat,
+                        source: original_mat,
+                        source_order: mat_channel_order(),
+                        on_edit: move |before: Vec<LabeledRoi>| {
+                            roi_history.write().record(before);
+                            persist_rois();
+                        },
+                    }
+                }
 
-                            { rois.read().iter().map(|roi| {
-                                let scale_val = scale();
-                                let left = (roi.x as f32 * scale_val).round();
-                                let top = (roi.y as f32 * scale_val).round();
-                                let width = (roi.width as f32 * scale_val).round();
-                                let height = (roi.height as f32 * scale_val).round();
-                                rsx! {
-                                    div {
-                                        class: "absolute border-2 border-red-500 pointer-events-none",
-                                        style: "left: {left}px; top: {top}px; width: {width}px; height: {height}px;",
+                // Class palette and ROI list
+                div { class: "w-80 text-sm space-y-3",
+                    div { class: "border rounded p-2",
+                        h3 { class: "font-semibold mb-2", "Classes" }
+                        { palette_val.classes.iter().map(|class| {
+                            let select_name = class.name.clone();
+                            let color_name = class.name.clone();
+                            let remove_name = class.name.clone();
+                            rsx! {
+                                div { key: "{class.name}", class: "flex items-center gap-2 mb-1",
+                                    input {
+                                        r#type: "radio",
+                                        name: "active_class",
+                                        checked: class.name == active_class(),
+                                        onchange: move |_| active_class.set(select_name.clone()),
+                                    }
+                                    input {
+                                        r#type: "color",
+                                        value: "{class.color}",
+                                        oninput: move |evt| {
+                                            palette.with_mut(|p| p.set_color(&color_name, &evt.value()));
+                                            persist_palette();
+                                        }
+                                    }
+                                    span { class: "flex-1", "{class.name}" }
+                                    button {
+                                        class: "text-red-600",
+                                        disabled: palette_val.classes.len() <= 1,
+                                        onclick: move |_| {
+                                            palette.with_mut(|p| p.remove_class(&remove_name));
+                                            if active_class() == remove_name {
+                                                active_class.set(palette.read().classes[0].name.clone());
+                                            }
+                                            persist_palette();
+                                        },
+                                        "✕"
                                     }
                                 }
-                            }) }
-
-                            {dragging_preview}
+                            }
+                        }) }
+                        div { class: "flex gap-1 mt-2",
+                            input {
+                                class: "border rounded px-2 py-1 flex-1",
+                                placeholder: "New class",
+                                value: "{new_class_name}",
+                                oninput: move |evt| new_class_name.set(evt.value()),
+                            }
+                            button { onclick: add_class, class: "px-2 py-1 bg-indigo-600 text-white rounded", "Add" }
                         }
                     }
+
+                    { roi_groups.iter().map(|(label, color, members)| {
+                        rsx! {
+                            div { key: "{label}", class: "border rounded p-2",
+                                h3 { class: "font-semibold mb-1", style: "color: {color};", "{label} ({members.len()})" }
+                                { members.iter().map(|(i, roi)| {
+                                    let i = *i;
+                                    rsx! {
+                                        div { key: "{i}", class: "flex flex-col gap-1 border-b py-1",
+                                            div { class: "flex items-center gap-2",
+                                                span {
+                                                    class: if selected_roi() == Some(i) { "flex-1 cursor-pointer font-semibold" } else { "flex-1 cursor-pointer" },
+                                                    onclick: move |_| selected_roi.set(Some(i)),
+                                                    "#{i + 1} ({roi.rect.x}, {roi.rect.y}) {roi.rect.width}×{roi.rect.height}"
+                                                }
+                                                select {
+                                                    class: "border rounded",
+                                                    onchange: move |evt| {
+                                                        record_roi_edit();
+                                                        rois.with_mut(|r| {
+                                                            if let Some(roi) = r.get_mut(i) {
+                                                                roi.label = evt.value();
+                                                            }
+                                                        });
+                                                        persist_rois();
+                                                    },
+                                                    { palette_val.classes.iter().map(|class| rsx! {
+                                                        option { value: "{class.name}", selected: class.name == roi.label, "{class.name}" }
+                                                    }) }
+                                                }
+                                                button {
+                                                    class: "text-red-600",
+                                                    onclick: move |_| {
+                                                        record_roi_edit();
+                                                        rois.with_mut(|r| {
+                                                            if i < r.len() {
+                                                                r.remove(i);
+                                                            }
+                                                        });
+                                                        selected_roi.set(None);
+                                                        persist_rois();
+                                                    },
+                                                    "✕"
+                                                }
+                                            }
+                                            input {
+                                                class: "border rounded px-2 py-1",
+                                                placeholder: "Note",
+                                                value: "{roi.note}",
+                                                onchange: move |evt| {
+                                                    record_roi_edit();
+                                                    rois.with_mut(|r| {
+                                                        if let Some(roi) = r.get_mut(i) {
+                                                            roi.note = evt.value();
+                                                        }
+                                                    });
+                                                    persist_rois();
+                                                }
+                                            }
+                                        }
+                                    }
+                                }) }
+                            }
+                        }
+                    }) }
+                }
                 }
 
                  // ✅ Show navigation only if image is loaded
@@ -471,25 +734,7 @@ pub fn ImageUploader31() -> Element {
                         onclick: move |_| {
                             let idx = *current_index.read();
                             if idx > 0 {
-                                let new_index = idx - 1;
-                                current_index.set(new_index);
-                                spawn({
-                                    to_owned![all_image_paths, image_data_url, image_width, image_height, rois, scale];
-                                    async move {
-                                        load_image(
-                                            &all_image_paths()[new_index],
-                                            &mut image_data_url,
-                                            &mut image_width,
-                                            &mut image_height,
-                                            &mut rois,
-                                            &mut scale,
-                                            true,
-                                            false,
-                                            true,
-                                            &mut original_mat
-                                        ).await;
-                                    }
-                                });
+                                open_image(idx - 1);
                             }
                         },
                         "⏮ Prev"
@@ -500,31 +745,127 @@ pub fn ImageUploader31() -> Element {
                         onclick: move |_| {
                             let idx = *current_index.read();
                             if idx + 1 < all_image_paths().len() {
-                                let new_index = idx + 1;
-                                current_index.set(new_index);
-                                spawn({
-                                    to_owned![all_image_paths, image_data_url, image_width, image_height, rois, scale];
-                                    async move {
-                                        load_image(
-                                            &all_image_paths()[new_index],
-                                            &mut image_data_url,
-                                            &mut image_width,
-                                            &mut image_height,
-                                            &mut rois,
-                                            &mut scale,
-                                            true,
-                                            false,
-                                            true,
-                                            &mut original_mat
-                                        ).await;
-                                    }
-                                });
+                                open_image(idx + 1);
                             }
                         },
                         "Next ⏭"
                     }
                 }
 
+                if !all_image_paths.read().is_empty() {
+                    Filmstrip {
+                        paths: all_image_paths,
+                        current: current_index,
+                        current_rois: rois_image.read().is_some().then(|| rois.read().len()),
+                        on_select: move |index: usize| {
+                            if index != current_index() {
+                                open_image(index);
+                            }
+                        },
+                    }
+                }
+
+                if let Some(pixels) = pixel_table {
+                    div { class: "mt-4",
+                        h3 { class: "font-semibold text-sm mb-1",
+                            "Pixel values · {pixels.label} @ ({pixels.rect.x}, {pixels.rect.y}) {pixels.rect.width}×{pixels.rect.height} · {pixels.color_space.name()}"
+                        }
+                        if pixels.rect.width > PIXEL_TABLE_MAX || pixels.rect.height > PIXEL_TABLE_MAX {
+                            p { class: "text-xs text-gray-500 mb-1",
+                                "Showing the top-left {PIXEL_TABLE_MAX}×{PIXEL_TABLE_MAX} pixels; use Export ROI pixels for the full data."
+                            }
+                        }
+                        div { style: "max-height: 320px; overflow: auto;",
+                            table { class: "font-mono text-xs border-collapse",
+                                thead {
+                                    tr {
+                                        th { class: "px-1 text-gray-500", "y\\x" }
+                                        for col in 0..pixels.rect.width.min(PIXEL_TABLE_MAX) {
+                                            th { class: "px-1 text-gray-500", "{pixels.rect.x + col}" }
+                                        }
+                                    }
+                                }
+                                tbody {
+                                    for row in 0..pixels.rect.height.min(PIXEL_TABLE_MAX) {
+                                        tr {
+                                            th { class: "px-1 text-gray-500 text-right", "{pixels.rect.y + row}" }
+                                            for col in 0..pixels.rect.width.min(PIXEL_TABLE_MAX) {
+                                                td { class: "border px-1 text-right whitespace-nowrap",
+                                                    "{format_pixel(pixels.pixel(row, col))}"
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if !roi_stats.read().is_empty() {
+                    div { class: "mt-4 space-y-2",
+                        h3 { class: "font-semibold text-sm", "ROI statistics" }
+                        { roi_stats.read().iter().map(|(i, stats)| {
+                            let i = *i;
+                            rsx! {
+                                div {
+                                    key: "{i}",
+                                    class: if selected_roi() == Some(i) { "border rounded p-2 flex gap-4 items-start bg-yellow-50" } else { "border rounded p-2 flex gap-4 items-start" },
+                                    onclick: move |_| selected_roi.set(Some(i)),
+                                    div { class: "text-xs w-32",
+                                        p { class: "font-semibold", "#{i + 1} {stats.label}" }
+                                        p { "({stats.rect.x}, {stats.rect.y}) {stats.rect.width}×{stats.rect.height}" }
+                                        p { "{stats.pixel_count} px" }
+                                    }
+                                    svg {
+                                        width: "256",
+                                        height: "64",
+                                        view_box: "0 0 256 64",
+                                        class: "border bg-gray-50",
+                                        { stats.channels.iter().map(|ch| rsx! {
+                                            polyline {
+                                                points: "{histogram_points(&ch.histogram, 256.0, 64.0)}",
+                                                fill: "none",
+                                                stroke: channel_color(ch.name),
+                                                stroke_width: "1",
+                                            }
+                                        }) }
+                                    }
+                                    table { class: "font-mono text-xs",
+                                        thead {
+                                            tr {
+                                                th { class: "px-1 text-left", "ch" }
+                                                th { class: "px-1", "mean" }
+                                                th { class: "px-1", "std" }
+                                                th { class: "px-1", "min" }
+                                                th { class: "px-1", "max" }
+                                                th { class: "px-1", "median" }
+                                                for p in PERCENTILES {
+                                                    th { class: "px-1", "p{p}" }
+                                                }
+                                            }
+                                        }
+                                        tbody {
+                                            for ch in stats.channels.iter() {
+                                                tr {
+                                                    td { class: "px-1", style: "color: {channel_color(ch.name)};", "{ch.name}" }
+                                                    td { class: "px-1 text-right", "{ch.mean:.2}" }
+                                                    td { class: "px-1 text-right", "{ch.std_dev:.2}" }
+                                                    td { class: "px-1 text-right", "{ch.min}" }
+                                                    td { class: "px-1 text-right", "{ch.max}" }
+                                                    td { class: "px-1 text-right", "{ch.median}" }
+                                                    for v in ch.percentiles.iter() {
+                                                        td { class: "px-1 text-right", "{v}" }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }) }
+                    }
+                }
             }
         }
     }
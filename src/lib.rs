@@ -0,0 +1,2 @@
+// UI-free core shared by the desktop app and the `imgtool` command-line binary
+pub mod core;
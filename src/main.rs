@@ -27,6 +27,14 @@ use crate::img_upload_28::ImageUploader28;
 mod img_upload_31;
 use crate::img_upload_31::ImageUploader31;
 
+use image_upload_demo::core;
+mod roi_canvas;
+mod batch_panel;
+mod compare_view;
+mod corner_picker;
+mod crop_picker;
+mod filmstrip;
+
 fn main() {
     LaunchBuilder::new()
         .with_cfg(
@@ -0,0 +1,656 @@
+// Zoomable, pannable image canvas for drawing, selecting, moving and resizing ROIs
+//
+// All geometry lives in `core`; this component only turns mouse and keyboard
+// events into edits of the `rois` signal it is given.
+use crate::dioxus_elements::geometry::WheelDelta;
+use crate::dioxus_elements::input_data::MouseButton;
+use base64::engine::general_purpose;
+use base64::Engine;
+use dioxus::prelude::*;
+use opencv::core::{Mat, Rect};
+
+use crate::core::color_model::{ChannelOrder, ColorSpace};
+use crate::core::filters::encode_png;
+use crate::core::loupe::{loupe, LOUPE_CELL_PX, LOUPE_RADIUS};
+use crate::core::pixel_export::RoiPixels;
+use crate::core::roi_edit::{hit_handle, move_rect, resize_rect, roi_at, Handle};
+use crate::core::roi_model::{ClassPalette, LabeledRoi, DEFAULT_CLASS};
+use crate::core::transform::{
+    centered_rect, fit_rect, in_image, rect_from_corners, zoom_step, ViewTransform, GRID_SCALE,
+    PIXELATED_SCALE, VALUE_SCALE,
+};
+
+// On-screen size of the resize handles drawn on the selected ROI
+const HANDLE_SIZE_PX: f32 = 8.0;
+// Size of the visible part of the canvas
+const VIEWPORT_PX: (f32, f32) = (640.0, 440.0);
+// Gap between the cursor and the loupe beside it
+const LOUPE_GAP_PX: f32 = 24.0;
+// Zoom levels offered next to "Fit" and "1:1", in percent
+const ZOOM_PRESETS: [u32; 9] = [10, 25, 50, 200, 400, 800, 1600, 3200, 6400];
+
+// An in-progress edit of an existing ROI, relative to where the drag started
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RoiDrag {
+    Move {
+        index: usize,
+        origin: Rect,
+        start: (i32, i32),
+    },
+    Resize {
+        index: usize,
+        handle: Handle,
+        origin: Rect,
+        start: (i32, i32),
+    },
+}
+
+/// Image with ROI overlays.
+///
+/// - drag on empty space draws a ROI; a plain click places a `click_size` ROI
+///   centred on the cursor when one is given
+/// - click a ROI to select it, drag it or its handles to move/resize
+/// - Shift+click removes the ROI under the cursor
+/// - arrow keys nudge the selection, Delete removes it, Escape deselects
+/// - the wheel zooms about the cursor, middle-drag or Space+drag pans
+///
+/// Zoomed in far, pixels are drawn as sharp squares, optionally with a grid,
+/// and with their `readout` values printed inside them. A status bar shows
+/// the pixel under the cursor and its `readout` values, and the loupe magnifies
+/// the pixels around it from `source`.
+///
+/// Changes of `scale` from outside zoom about the middle of the view.
+/// `on_edit` receives the ROI list from just before every finished edit, for
+/// undo history and saving.
+#[component]
+pub fn RoiCanvas(
+    /// Image data URL
+    src: String,
+    image_width: i32,
+    image_height: i32,
+    scale: Signal<f32>,
+    rois: Signal<Vec<LabeledRoi>>,
+    /// Selected ROI; the canvas keeps its own selection when not given
+    selected: Option<Signal<Option<usize>>>,
+    click_size: Option<(i32, i32)>,
+    /// Label given to new ROIs
+    #[props(default = DEFAULT_CLASS.to_string())]
+    label: String,
+    #[props(default)] palette: ClassPalette,
+    #[props(default)] show_labels: bool,
+    #[props(default = true)] wheel_zoom: bool,
+    /// When false the ROIs are only shown; zooming and panning still work
+    #[props(default = true)]
+    editable: bool,
+    /// Shows the fit, 1:1 and percentage zoom presets above the canvas
+    #[props(default)]
+    zoom_controls: bool,
+    /// The image's pixels in the colour space they are read in
+    readout: Option<Signal<Option<(ColorSpace, Mat)>>>,
+    /// The image as loaded, stored as `source_order`, for the loupe
+    source: Option<Signal<Option<Mat>>>,
+    #[props(default = ChannelOrder::Bgr)]
+    source_order: ChannelOrder,
+    #[props(default)] on_edit: EventHandler<Vec<LabeledRoi>>,
+) -> Element {
+    let own_selection = use_signal(|| None::<usize>);
+    let mut selected = selected.unwrap_or(own_selection);
+    let mut scale = scale;
+    let mut rois = rois;
+
+    let mut drag_start = use_signal(|| None::<(i32, i32)>);
+    let mut drag_current = use_signal(|| None::<(i32, i32)>);
+    let mut roi_drag = use_signal(|| None::<RoiDrag>);
+    let mut cursor = use_signal(|| "crosshair");
+
+    // View as last zoomed or panned here; `scale` may have changed since
+    let mut pan = use_signal(|| ViewTransform::new(scale()));
+    // Where a pan started, in canvas pixels, and the view at that moment
+    let mut pan_drag = use_signal(|| None::<((f64, f64), ViewTransform)>);
+    let mut space_held = use_signal(|| false);
+    let mut show_grid = use_signal(|| true);
+    // Image pixel under the cursor
+    let mut hover = use_signal(|| None::<(i32, i32)>);
+    let mut show_loupe = use_signal(|| false);
+
+    let image_size = (image_width, image_height);
+    let current_view = move || {
+        let last = pan();
+        let scale = scale();
+        let view = if last.scale == scale {
+            last
+        } else {
+            last.zoom_at(scale, (VIEWPORT_PX.0 / 2.0, VIEWPORT_PX.1 / 2.0))
+        };
+        view.clamped(image_size, VIEWPORT_PX)
+    };
+    let mut set_view = move |view: ViewTransform| {
+        let view = view.clamped(image_size, VIEWPORT_PX);
+        pan.set(view);
+        if scale() != view.scale {
+            scale.set(view.scale);
+        }
+    };
+    let mut zoom_centered = move |scale: f32| {
+        set_view(current_view().zoom_at(scale, (VIEWPORT_PX.0 / 2.0, VIEWPORT_PX.1 / 2.0)));
+    };
+
+    // Ends a move/resize and reports it; returns false when none was in progress
+    let mut finish_roi_drag = move || {
+        let Some(drag) = roi_drag() else {
+            return false;
+        };
+        roi_drag.set(None);
+        let (RoiDrag::Move { index, origin, .. } | RoiDrag::Resize { index, origin, .. }) = drag;
+        if rois.read().get(index).is_some_and(|roi| roi.rect != origin) {
+            // The list was edited live while dragging, so rebuild the state from before it
+            let mut before = rois();
+            before[index].rect = origin;
+            on_edit.call(before);
+        }
+        true
+    };
+
+    let on_wheel = move |evt: WheelEvent| {
+        if !wheel_zoom {
+            return;
+        }
+        if let WheelDelta::Pixels(pixels) = evt.data().delta() {
+            evt.prevent_default();
+            let coords = evt.data().element_coordinates();
+            let view = current_view();
+            let scale = zoom_step(view.scale, pixels.y < 0.0);
+            set_view(view.zoom_at(scale, (coords.x as f32, coords.y as f32)));
+        }
+    };
+
+    let on_mouse_down = move |evt: MouseEvent| {
+        let coords = evt.data().element_coordinates();
+        let view = current_view();
+        if evt.trigger_button() == Some(MouseButton::Auxiliary) || space_held() {
+            // Keeps the middle button from starting the webview's autoscroll
+            evt.prevent_default();
+            pan_drag.set(Some(((coords.x, coords.y), view)));
+            return;
+        }
+        let (x, y) = view.to_pixel(coords.x, coords.y);
+        if !editable || !in_image(x, y, image_width, image_height) {
+            return;
+        }
+
+        if evt.modifiers().shift() {
+            let hit = roi_at(&rois.read(), x, y);
+            if let Some(index) = hit {
+                let before = rois();
+                rois.with_mut(|r| {
+                    r.remove(index);
+                });
+                selected.set(None);
+                on_edit.call(before);
+                println!("ROI removed at ({}, {})", x, y);
+            }
+            return;
+        }
+
+        // Handles of the selected ROI win, then the topmost ROI under the cursor
+        let (x_f, y_f) = view.to_image(coords.x, coords.y);
+        let tolerance = HANDLE_SIZE_PX / view.scale;
+        let handle_hit = selected().and_then(|index| {
+            let origin = rois.read().get(index)?.rect;
+            hit_handle(&origin, x_f, y_f, tolerance).map(|handle| (index, handle, origin))
+        });
+        if let Some((index, handle, origin)) = handle_hit {
+            roi_drag.set(Some(RoiDrag::Resize {
+                index,
+                handle,
+                origin,
+                start: (x, y),
+            }));
+            return;
+        }
+
+        let under_cursor = roi_at(&rois.read(), x, y);
+        if let Some(index) = under_cursor {
+            let origin = rois.read()[index].rect;
+            selected.set(Some(index));
+            roi_drag.set(Some(RoiDrag::Move {
+                index,
+                origin,
+                start: (x, y),
+            }));
+            return;
+        }
+
+        selected.set(None);
+        drag_start.set(Some((x, y)));
+        drag_current.set(Some((x, y)));
+    };
+
+    let on_mouse_move = move |evt: MouseEvent| {
+        let coords = evt.data().element_coordinates();
+        let view = current_view();
+        let (x, y) = view.to_pixel(coords.x, coords.y);
+        let over = in_image(x, y, image_width, image_height).then_some((x, y));
+        if hover() != over {
+            hover.set(over);
+        }
+        if let Some((start, origin)) = pan_drag() {
+            set_view(origin.panned((coords.x - start.0) as f32, (coords.y - start.1) as f32));
+            return;
+        }
+
+        if let Some(drag) = roi_drag() {
+            let (index, rect) = match drag {
+                RoiDrag::Move {
+                    index,
+                    origin,
+                    start,
+                } => (
+                    index,
+                    move_rect(&origin, x - start.0, y - start.1, image_width, image_height),
+                ),
+                RoiDrag::Resize {
+                    index,
+                    handle,
+                    origin,
+                    start,
+                } => (
+                    index,
+                    resize_rect(
+                        &origin,
+                        handle,
+                        x - start.0,
+                        y - start.1,
+                        image_width,
+                        image_height,
+                    ),
+                ),
+            };
+            rois.with_mut(|r| {
+                if let Some(roi) = r.get_mut(index) {
+                    roi.rect = rect;
+                }
+            });
+            return;
+        }
+
+        if drag_start().is_some() {
+            // The viewport is larger than a zoomed-out image; stop at its edges
+            drag_current.set(Some((x.clamp(0, image_width), y.clamp(0, image_height))));
+            return;
+        }
+
+        // Hover feedback: resize cursor over handles, move cursor inside ROIs
+        let (x_f, y_f) = view.to_image(coords.x, coords.y);
+        let handle = selected()
+            .and_then(|i| rois.read().get(i).map(|roi| roi.rect))
+            .and_then(|rect| hit_handle(&rect, x_f, y_f, HANDLE_SIZE_PX / view.scale));
+        let hovered = match handle {
+            Some(handle) => handle.cursor(),
+            None if roi_at(&rois.read(), x, y).is_some() => "move",
+            None => "crosshair",
+        };
+        if cursor() != hovered {
+            cursor.set(hovered);
+        }
+    };
+
+    let on_mouse_up = move |_evt: MouseEvent| {
+        if pan_drag().is_some() {
+            pan_drag.set(None);
+            return;
+        }
+        if finish_roi_drag() {
+            return;
+        }
+        let (Some(start), Some(current)) = (drag_start(), drag_current()) else {
+            return;
+        };
+        drag_start.set(None);
+        drag_current.set(None);
+
+        let drawn = rect_from_corners(start, current);
+        let rect = if drawn.width > 0 && drawn.height > 0 {
+            drawn
+        } else if let Some((w, h)) = click_size {
+            centered_rect(start.0, start.1, w, h)
+        } else {
+            return;
+        };
+        let Some(rect) = fit_rect(&rect, image_width, image_height) else {
+            return;
+        };
+
+        let before = rois();
+        rois.with_mut(|r| r.push(LabeledRoi::new(rect, label.clone())));
+        // Select the new ROI so its details show up right away
+        selected.set(Some(rois.read().len() - 1));
+        on_edit.call(before);
+        println!(
+            "🏷️  ROI [{}] @ ({}, {}) {}x{}",
+            label, rect.x, rect.y, rect.width, rect.height
+        );
+    };
+
+    // Arrow keys nudge the selected ROI by one image pixel, Delete removes it.
+    // Shortcuts with Ctrl/Cmd are left to bubble up to the parent
+    let on_key_down = move |evt: KeyboardEvent| {
+        let modifiers = evt.modifiers();
+        if modifiers.ctrl() || modifiers.meta() {
+            return;
+        }
+        if evt.key() == Key::Character(" ".to_string()) {
+            // Held for panning, not for scrolling the page
+            evt.prevent_default();
+            space_held.set(true);
+            return;
+        }
+        if !editable {
+            return;
+        }
+        let Some(index) = selected().filter(|i| *i < rois.read().len()) else {
+            return;
+        };
+        let (dx, dy) = match evt.key() {
+            Key::ArrowLeft => (-1, 0),
+            Key::ArrowRight => (1, 0),
+            Key::ArrowUp => (0, -1),
+            Key::ArrowDown => (0, 1),
+            Key::Delete | Key::Backspace => {
+                let before = rois();
+                rois.with_mut(|r| {
+                    r.remove(index);
+                });
+                selected.set(None);
+                on_edit.call(before);
+                return;
+            }
+            Key::Escape => {
+                selected.set(None);
+                return;
+            }
+            _ => return,
+        };
+        // Keep the scroll box from scrolling along with the nudge
+        evt.prevent_default();
+
+        let before = rois();
+        rois.with_mut(|r| {
+            r[index].rect = move_rect(&r[index].rect, dx, dy, image_width, image_height);
+        });
+        on_edit.call(before);
+    };
+
+    let view = current_view();
+    let (canvas_left, canvas_top) = (view.offset_x, view.offset_y);
+    let (canvas_width, canvas_height) = (
+        image_width as f32 * view.scale,
+        image_height as f32 * view.scale,
+    );
+    let shown_cursor = if pan_drag().is_some() {
+        "grabbing"
+    } else if space_held() {
+        "grab"
+    } else {
+        cursor()
+    };
+    let percent = (view.scale * 100.0).round();
+    let rendering = if view.scale >= PIXELATED_SCALE { "pixelated" } else { "auto" };
+
+    let pixel_grid = (show_grid() && view.scale >= GRID_SCALE).then(|| {
+        let cell = view.scale;
+        rsx! {
+            div {
+                class: "absolute pointer-events-none",
+                style: "left: {canvas_left}px; top: {canvas_top}px; width: {canvas_width}px; height: {canvas_height}px; background-size: {cell}px {cell}px; background-image: linear-gradient(to right, rgba(128, 128, 128, 0.6) 1px, transparent 1px), linear-gradient(to bottom, rgba(128, 128, 128, 0.6) 1px, transparent 1px);",
+            }
+        }
+    });
+
+    // `readout` values of the pixels in `area`
+    let read_pixels = move |area: Rect| {
+        let readout = readout?;
+        let readout = readout.read();
+        let (space, mat) = readout.as_ref()?;
+        RoiPixels::from_mat(mat, &LabeledRoi::new(area, String::new()), *space)
+            .ok()
+            .flatten()
+    };
+
+    // Only the pixels in view are read, a few hundred at most at this zoom
+    let cell_values = view
+        .visible_rect(image_size, VIEWPORT_PX)
+        .filter(|_| view.scale >= VALUE_SCALE)
+        .and_then(read_pixels)
+        .map(|pixels| {
+            let font_px = (view.scale / 4.0).min(14.0).round();
+            let cells = (0..pixels.rect.height).flat_map(move |row| {
+                (0..pixels.rect.width).map(move |col| (row, col))
+            });
+            rsx! {
+                for (row, col) in cells {
+                    div {
+                        key: "{row}-{col}",
+                        class: "absolute flex flex-col items-center justify-center pointer-events-none font-mono text-white leading-none",
+                        style: {
+                            let (left, top, size, _) = view.rect_to_canvas(&Rect::new(
+                                pixels.rect.x + col,
+                                pixels.rect.y + row,
+                                1,
+                                1,
+                            ));
+                            format!(
+                                "left: {left}px; top: {top}px; width: {size}px; height: {size}px; font-size: {font_px}px; text-shadow: 0 0 2px #000;"
+                            )
+                        },
+                        for value in pixels.pixel(row, col) {
+                            span { "{value}" }
+                        }
+                    }
+                }
+            }
+        });
+
+    // Resize handles for the selected ROI
+    let selection_handles = selected()
+        .and_then(|i| rois.read().get(i).cloned())
+        .map(|roi| {
+            let color = palette.color_for(&roi.label).to_string();
+            let half = HANDLE_SIZE_PX / 2.0;
+            rsx! {
+                { Handle::ALL.iter().map(|handle| {
+                    let (ax, ay) = handle.anchor(&roi.rect);
+                    let (hx, hy) = view.to_canvas(ax, ay);
+                    let left = (hx - half).round();
+                    let top = (hy - half).round();
+                    rsx! {
+                        div {
+                            class: "absolute bg-white border pointer-events-none",
+                            style: "left: {left}px; top: {top}px; width: {HANDLE_SIZE_PX}px; height: {HANDLE_SIZE_PX}px; border-color: {color};",
+                        }
+                    }
+                }) }
+            }
+        });
+
+    let dragging_preview = if let (Some(start), Some(current)) = (drag_start(), drag_current()) {
+        let (left, top, w, h) = view.rect_to_canvas(&rect_from_corners(start, current));
+        Some(rsx! {
+            div {
+                class: "absolute border-2 border-blue-400 bg-blue-200 bg-opacity-30 pointer-events-none",
+                style: "left: {left}px; top: {top}px; width: {w}px; height: {h}px;",
+            }
+        })
+    } else {
+        None
+    };
+
+    let status = match hover() {
+        Some((x, y)) => {
+            let mut text = format!("x {}  y {}", x, y);
+            if let Some(pixel) = read_pixels(Rect::new(x, y, 1, 1)) {
+                text.push_str(&format!("   {}", pixel.color_space.name()));
+                let names = pixel.color_space.channel_names();
+                for (name, value) in names.iter().zip(pixel.pixel(0, 0)) {
+                    text.push_str(&format!("  {} {}", name, value));
+                }
+            }
+            text
+        }
+        None => "Point at the image to read its pixels".to_string(),
+    };
+
+    // Magnified pixels around the cursor, beside it and kept inside the view
+    let magnifier = hover()
+        .filter(|_| show_loupe())
+        .and_then(|center| {
+            let source = source?;
+            let source = source.read();
+            let mat = source.as_ref()?;
+            let magnified = match loupe(mat, source_order, center, LOUPE_RADIUS, LOUPE_CELL_PX) {
+                Ok(magnified) => magnified?,
+                Err(e) => {
+                    eprintln!("Loupe failed: {}", e);
+                    return None;
+                }
+            };
+            let png = encode_png(&magnified).ok()?;
+            Some((center, general_purpose::STANDARD.encode(png)))
+        })
+        .map(|((x, y), encoded)| {
+            let side = ((2 * LOUPE_RADIUS + 1) * LOUPE_CELL_PX) as f32;
+            let (cx, cy) = view.to_canvas(x as f32 + 0.5, y as f32 + 0.5);
+            let left = if cx + LOUPE_GAP_PX + side <= VIEWPORT_PX.0 {
+                cx + LOUPE_GAP_PX
+            } else {
+                cx - LOUPE_GAP_PX - side
+            };
+            let top = if cy + LOUPE_GAP_PX + side <= VIEWPORT_PX.1 {
+                cy + LOUPE_GAP_PX
+            } else {
+                cy - LOUPE_GAP_PX - side
+            };
+            let middle = LOUPE_RADIUS * LOUPE_CELL_PX;
+            rsx! {
+                div {
+                    class: "absolute border-2 border-white shadow-lg pointer-events-none",
+                    style: "left: {left}px; top: {top}px; width: {side}px; height: {side}px;",
+                    img {
+                        src: "data:image/png;base64,{encoded}",
+                        class: "max-w-none",
+                        style: "width: {side}px; height: {side}px;",
+                    }
+                    div {
+                        class: "absolute border border-red-500",
+                        style: "left: {middle}px; top: {middle}px; width: {LOUPE_CELL_PX}px; height: {LOUPE_CELL_PX}px;",
+                    }
+                }
+            }
+        });
+
+    rsx! {
+        div { class: "flex flex-col gap-1",
+            if zoom_controls {
+                div { class: "flex items-center gap-2 text-sm",
+                    button {
+                        onclick: move |_| set_view(ViewTransform::fit(image_size, VIEWPORT_PX)),
+                        class: "px-2 py-1 bg-gray-600 text-white rounded",
+                        "Fit"
+                    }
+                    button {
+                        onclick: move |_| zoom_centered(1.0),
+                        class: "px-2 py-1 bg-gray-600 text-white rounded",
+                        "1:1"
+                    }
+                    label { class: "flex items-center gap-1",
+                        input {
+                            r#type: "checkbox",
+                            checked: show_grid(),
+                            onchange: move |evt| show_grid.set(evt.checked()),
+                        }
+                        "Pixel grid"
+                    }
+                    if source.is_some() {
+                        label { class: "flex items-center gap-1",
+                            input {
+                                r#type: "checkbox",
+                                checked: show_loupe(),
+                                onchange: move |evt| show_loupe.set(evt.checked()),
+                            }
+                            "Loupe"
+                        }
+                    }
+                    select {
+                        class: "border rounded px-2 py-1",
+                        onchange: move |evt| {
+                            if let Ok(percent) = evt.value().parse::<u32>() {
+                                zoom_centered(percent as f32 / 100.0);
+                            }
+                        },
+                        option { value: "", selected: true, disabled: true, "{percent}%" }
+                        for preset in ZOOM_PRESETS {
+                            option { value: "{preset}", "{preset}%" }
+                        }
+                    }
+                    span { class: "text-gray-500", "Wheel zooms, middle-drag or Space+drag pans" }
+                }
+            }
+            div {
+                class: "relative overflow-hidden",
+                style: "width: {VIEWPORT_PX.0}px; height: {VIEWPORT_PX.1}px; box-sizing: content-box; border: 2px solid #ccc; margin: auto; outline: none; cursor: {shown_cursor};",
+                tabindex: "0",
+                onkeydown: on_key_down,
+                onkeyup: move |evt: KeyboardEvent| {
+                    if evt.key() == Key::Character(" ".to_string()) {
+                        space_held.set(false);
+                    }
+                },
+                onmousedown: on_mouse_down,
+                onmousemove: on_mouse_move,
+                onmouseup: on_mouse_up,
+                onmouseleave: move |_| {
+                    hover.set(None);
+                    pan_drag.set(None);
+                    finish_roi_drag();
+                },
+                onwheel: on_wheel,
+
+                img {
+                    src: "{src}",
+                    class: "absolute max-w-none select-none pointer-events-none",
+                    style: "left: {canvas_left}px; top: {canvas_top}px; width: {canvas_width}px; height: {canvas_height}px; image-rendering: {rendering};",
+                }
+                {pixel_grid}
+                {cell_values}
+
+                { rois.read().iter().enumerate().map(|(i, roi)| {
+                    let (left, top, width, height) = view.rect_to_canvas(&roi.rect);
+                    let color = palette.color_for(&roi.label);
+                    let border_style = if selected() == Some(i) { "dashed" } else { "solid" };
+                    rsx! {
+                        div {
+                            class: "absolute border-2 pointer-events-none",
+                            style: "left: {left}px; top: {top}px; width: {width}px; height: {height}px; border-color: {color}; border-style: {border_style};",
+                            if show_labels {
+                                span {
+                                    class: "absolute px-1 text-xs text-white whitespace-nowrap",
+                                    style: "left: -2px; top: -18px; background-color: {color};",
+                                    "{roi.label}"
+                                }
+                            }
+                        }
+                    }
+                }) }
+
+                {selection_handles}
+                {dragging_preview}
+                {magnifier}
+            }
+            div { class: "flex text-xs font-mono text-gray-600",
+                style: "width: {VIEWPORT_PX.0}px; margin: auto;",
+                span { "{status}" }
+                span { class: "ml-auto", "{percent}%" }
+            }
+        }
+    }
+}